@@ -0,0 +1,204 @@
+//! Interface between the server and the display system.
+
+use std::{error::Error, fmt::Debug, future::Future, hash::Hash, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Change in the windows of the display.
+pub enum FocusEvent<W> {
+    /// `window` is the new active window.
+    Focused(W),
+
+    /// `window` was closed.
+    Closed(W),
+}
+
+/// Key pressed while the windows are cycled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CycleKey {
+    /// Accept the activated window (Enter).
+    Commit,
+
+    /// Return to the window that was active before the cycle (Escape).
+    Cancel,
+}
+
+/// Metadata of a window.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WindowInfo {
+    /// Class name, from `WM_CLASS`.
+    pub class: Option<String>,
+
+    /// Title, from `_NET_WM_NAME` or `WM_NAME`.
+    pub title: Option<String>,
+
+    /// Desktop number, from `_NET_WM_DESKTOP`.
+    pub desktop: Option<u32>,
+
+    /// Process of the window, from `_NET_WM_PID`.
+    pub pid: Option<u32>,
+
+    /// Type of the window, from `_NET_WM_WINDOW_TYPE`, in lowercase and
+    /// without the prefix, like `dialog` or `normal`.
+    pub window_type: Option<String>,
+
+    /// Identifier of the main window, from `WM_TRANSIENT_FOR`, if this is
+    /// a transient window, like a dialog.
+    pub transient_for: Option<u64>,
+}
+
+/// State of a screen, for diagnostics.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScreenState {
+    /// Identifier of the root window.
+    pub root: u64,
+
+    /// Active window in the screen.
+    pub active_window: Option<u64>,
+
+    /// Windows activated in the screen, known by the backend.
+    pub windows: Vec<u64>,
+}
+
+/// Durations of an operation, for diagnostics.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Timing {
+    /// Number of times the operation was done.
+    pub count: u64,
+
+    /// Sum of the durations, in microseconds.
+    pub total_us: u64,
+
+    /// Longest duration, in microseconds.
+    pub max_us: u64,
+}
+
+impl Timing {
+    pub fn record(&mut self, duration: Duration) {
+        let us = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.count += 1;
+        self.total_us = self.total_us.saturating_add(us);
+        self.max_us = self.max_us.max(us);
+    }
+
+    /// Average duration, in microseconds.
+    pub fn average_us(&self) -> u64 {
+        self.total_us.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// Latency of the connection to the display server, for diagnostics.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Stats {
+    /// Time to process the events and the replies received in each
+    /// iteration of the main loop.
+    pub main_loop: Timing,
+
+    /// Time between a request is sent and its reply is received.
+    pub requests: Timing,
+
+    /// Requests waiting for a reply.
+    pub pending_requests: usize,
+
+    /// Maximum number of requests waiting for a reply.
+    pub max_pending_requests: usize,
+
+    /// Events received from the display server.
+    pub events: u64,
+}
+
+/// Operations needed by the server to track and activate windows.
+///
+/// The server runs in a single thread, so the futures are not required to
+/// be `Send`.
+#[allow(async_fn_in_trait)]
+pub trait DisplayBackend: 'static {
+    /// Identifier of the windows in this backend.
+    type Window: Copy + Eq + Hash + Debug + 'static;
+
+    /// Wait for the next change in the windows.
+    async fn focus_event(&self) -> Result<FocusEvent<Self::Window>, Box<dyn Error>>;
+
+    /// Return `true` if any keyboard modifier is pressed.
+    async fn modifiers_pressed(&self) -> Result<bool, Box<dyn Error>>;
+
+    /// Wait until all keyboard modifiers are released.
+    async fn modifiers_released(&self);
+
+    /// Wait until a key or a pointer button is pressed after `since`. It
+    /// returns immediately if the backend can't detect them.
+    async fn interaction(&self, since: Instant);
+
+    /// Grab the keys that end a cycle, and wait until one of them is
+    /// pressed. The keys are released when the future is dropped.
+    async fn cycle_key(&self) -> Result<CycleKey, Box<dyn Error>>;
+
+    /// Show the `entries` over the other windows, with the `selected` line
+    /// highlighted. Each entry is a window and its line. Used to display
+    /// the windows of a cycle.
+    async fn show_overlay(
+        &self,
+        entries: &[(Self::Window, String)],
+        selected: usize,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Hide the lines of [`show_overlay`](Self::show_overlay).
+    fn hide_overlay(&self);
+
+    /// Draw a border with `color` around `window` for a moment, to show
+    /// where it is.
+    async fn flash(&self, window: Self::Window, color: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Return `false` if `window` can't be activated, because it was
+    /// destroyed or withdrawn.
+    async fn is_available(&self, window: Self::Window) -> bool;
+
+    /// Return the windows demanding attention, most recent first.
+    fn urgent_windows(&self) -> Vec<Self::Window>;
+
+    /// Make `window` the active window.
+    async fn activate(&self, window: Self::Window) -> Result<(), Box<dyn Error>>;
+
+    /// Return the current workspace, if the backend supports them.
+    async fn current_workspace(&self) -> Result<Option<u32>, Box<dyn Error>>;
+
+    /// Return the output (monitor) where the pointer is, if the backend
+    /// supports them.
+    async fn current_output(&self) -> Result<Option<u32>, Box<dyn Error>>;
+
+    /// Return the output where `window` is.
+    async fn window_output(&self, window: Self::Window) -> Result<Option<u32>, Box<dyn Error>>;
+
+    /// Return the metadata of `window`.
+    async fn window_info(&self, window: Self::Window) -> Result<WindowInfo, Box<dyn Error>>;
+
+    /// Number of screens, if the backend has them.
+    fn screens(&self) -> Option<usize>;
+
+    /// Return the state of each screen, if the backend has them.
+    async fn screen_states(&self) -> Vec<ScreenState>;
+
+    /// Return the latency metrics, if the backend records them.
+    fn stats(&self) -> Option<Stats>;
+
+    /// Make the history available to other clients of the display
+    /// server, if the backend supports it.
+    async fn publish_history(&self, windows: &[Self::Window]) -> Result<(), Box<dyn Error>> {
+        let _ = windows;
+        Ok(())
+    }
+
+    /// Run `requests`, sending the requests made by its futures together,
+    /// so their replies are received in a single round trip.
+    async fn batch<F: Future>(&self, requests: F) -> F::Output {
+        requests.await
+    }
+
+    /// Numeric identifier of `window`, sent to the clients.
+    fn window_id(window: Self::Window) -> u64;
+
+    /// Window for an identifier returned by [`window_id`](Self::window_id),
+    /// or `None` if it is not valid in this backend.
+    fn window_from_id(id: u64) -> Option<Self::Window>;
+}
@@ -0,0 +1,70 @@
+//! Output for status bars, like i3blocks (with `interval=persist`) or
+//! polybar (with `tail = true`).
+//!
+//! A line is printed with the window that would be activated by `switch`
+//! every time the focus is changed. Every line received in stdin is a click,
+//! and it activates that window.
+
+use std::error::Error;
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use x11_alternate_focus::{socket, template};
+
+/// Format used if `--format` is not given.
+pub const DEFAULT_FORMAT: &str = "↩ {class} — {title}";
+
+pub async fn run(format: &str) -> Result<(), Box<dyn Error>> {
+    let mut watch = socket::watch().await?;
+
+    tokio::task::spawn_local(read_clicks());
+
+    loop {
+        print_last(format).await?;
+        watch.next().await?;
+    }
+}
+
+/// Print the second window in the history, or an empty line if there is
+/// none.
+async fn print_last(format: &str) -> Result<(), Box<dyn Error>> {
+    let entries = socket::request_history().await?;
+
+    match entries.get(1) {
+        Some(entry) => println!("{}", template::render(format, entry)),
+        None => println!(),
+    }
+
+    Ok(())
+}
+
+async fn read_clicks() {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if !is_primary_click(&line) {
+            continue;
+        }
+
+        let request = socket::Request::Switch {
+            nth: 1,
+            filter: Default::default(),
+            dry_run: false,
+        };
+
+        match socket::request(&request).await {
+            Ok(socket::Response::Error(e)) => eprintln!("{e}"),
+            Err(e) => eprintln!("{e}"),
+            Ok(_) => (),
+        }
+    }
+}
+
+/// i3blocks can send the clicks as JSON objects. In that case, only the
+/// left button is accepted.
+fn is_primary_click(line: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(click) => click.get("button").is_none_or(|b| b == 1),
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,431 @@
+//! Command-line interface.
+
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use xcb::x;
+
+use x11_alternate_focus::{socket, x11};
+
+use crate::{bar, logging, menu};
+
+/// Track the focused windows, and switch to the previous one.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// X display, instead of the one in `$DISPLAY`.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub display: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the server, which tracks the focused windows.
+    Server(ServerOptions),
+
+    /// Activate a window from the history.
+    Switch(SwitchOptions),
+
+    /// Activate the next window in the history, without reordering it
+    /// until the cycle ends.
+    CycleNext(FilterOptions),
+
+    /// Activate the previous window in the history, without reordering it
+    /// until the cycle ends.
+    CyclePrev(FilterOptions),
+
+    /// Activate the previous window while the modifiers are pressed, and
+    /// return to the current one when they are released.
+    Peek(FilterOptions),
+
+    /// Activate a window by its id, even if it is not in the history.
+    Focus {
+        /// Window id, in hexadecimal (`0x1a00003`) or decimal.
+        #[arg(value_parser = socket::parse_window_id)]
+        id: u64,
+    },
+
+    /// Mark the current window with a name.
+    Mark { name: String },
+
+    /// Activate the window marked with a name.
+    Goto { name: String },
+
+    /// Print the marked windows.
+    Marks {
+        /// Print the marks as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the windows in the history, most recent first.
+    History {
+        /// Print the entries as JSON.
+        #[arg(long)]
+        json: bool,
+
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+    },
+
+    /// Print the state of the server.
+    Status {
+        /// Print the state as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the internal state of the server as JSON, for bug reports.
+    DumpState,
+
+    /// Print the latency of the activations and of the connection to the
+    /// display server.
+    Stats {
+        /// Print the metrics as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Stop recording the focus changes, until `resume`.
+    Pause,
+
+    /// Record the focus changes again, after `pause`.
+    Resume,
+
+    /// Read the configuration file again, and apply it to the server.
+    Reload,
+
+    /// Stop the server, and remove its socket. The history is saved, and
+    /// restored when the server starts again.
+    Quit,
+
+    /// Check if the server is running.
+    Ping,
+
+    /// Read or change a property of a window, to debug the window
+    /// manager.
+    Prop {
+        /// Window of the property, in hexadecimal or decimal. The default
+        /// is the root window.
+        #[arg(long, value_parser = socket::parse_window_id)]
+        window: Option<u64>,
+
+        #[command(subcommand)]
+        action: PropAction,
+    },
+
+    /// Check the capabilities of the window manager and of the X server,
+    /// and print hints for the missing ones.
+    Doctor,
+
+    /// Print the focus changes.
+    Watch {
+        /// Print the changes as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the window that would be activated by `switch`, for status
+    /// bars.
+    Bar {
+        /// Template for each line. Variables: {id}, {class}, {title},
+        /// {desktop}.
+        #[arg(long, default_value = bar::DEFAULT_FORMAT)]
+        format: String,
+    },
+
+    /// Pick a window from the history with an external menu, and activate
+    /// it.
+    Menu {
+        /// Command of the menu. It receives a line for each window in
+        /// stdin, and prints the selected line.
+        #[arg(long, default_value = menu::DEFAULT_COMMAND)]
+        cmd: String,
+
+        /// Template for each line. Variables: {id}, {class}, {title},
+        /// {desktop}.
+        #[arg(long, default_value = menu::DEFAULT_FORMAT)]
+        format: String,
+    },
+
+    /// Generate the completions for a shell.
+    Completions { shell: clap_complete::Shell },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryAction {
+    /// Write the history as JSON to stdout.
+    Export,
+
+    /// Replace the history with the windows read from stdin, in the format
+    /// of `export`. Windows that don't exist anymore are skipped, and the
+    /// current window is kept at the front.
+    Import,
+}
+
+#[derive(Subcommand)]
+pub enum PropAction {
+    /// Print the value of a property.
+    Get {
+        /// Name of the property, like `_NET_ACTIVE_WINDOW`.
+        name: String,
+    },
+
+    /// Replace the value of a property.
+    Set {
+        /// Name of the property, like `_NET_ACTIVE_WINDOW`.
+        name: String,
+
+        /// Type of the value.
+        #[arg(long = "type", value_name = "TYPE", value_enum, default_value_t)]
+        kind: PropType,
+
+        /// Items of the value. Text values are joined with spaces.
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+
+    /// Remove a property.
+    Del {
+        /// Name of the property, like `_NET_ACTIVE_WINDOW`.
+        name: String,
+    },
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum PropType {
+    /// `UTF8_STRING`.
+    #[default]
+    Utf8,
+
+    /// `STRING`, in Latin-1.
+    String,
+
+    /// `CARDINAL` numbers.
+    Cardinal,
+
+    /// `WINDOW` ids, in hexadecimal or decimal.
+    Window,
+
+    /// `ATOM` names.
+    Atom,
+}
+
+#[derive(Args, Clone)]
+pub struct ServerOptions {
+    /// Move the pointer to the monitor of the target window before
+    /// activating it.
+    #[arg(long)]
+    pub focus_output: bool,
+
+    /// Change the current desktop before activating a window in another
+    /// desktop. Needed by window managers that ignore the activation of
+    /// windows in other desktops.
+    #[arg(long)]
+    pub change_desktop: bool,
+
+    /// Move the pointer to the center of the activated windows. It
+    /// replaces the `warp_pointer` key in the configuration file.
+    #[arg(long)]
+    pub warp_pointer: bool,
+
+    /// Restore minimized windows before activating them.
+    #[arg(long)]
+    pub unminimize: bool,
+
+    /// Raise the activated windows, for window managers that only focus
+    /// them.
+    #[arg(long, conflicts_with = "no_raise")]
+    pub raise: bool,
+
+    /// Keep the activated windows at their position in the stacking
+    /// order, for window managers that raise them.
+    #[arg(long)]
+    pub no_raise: bool,
+
+    /// Replace the server already running on the display.
+    #[arg(long)]
+    pub replace: bool,
+
+    /// Keep a separate history for each workspace. Not supported with sway.
+    #[arg(long)]
+    pub per_workspace: bool,
+
+    /// Path of the configuration file.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Fork into the background, and detach from the terminal.
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Write the PID of the server in this file.
+    #[arg(long, value_name = "PATH")]
+    pub pidfile: Option<PathBuf>,
+
+    /// Filter for the diagnostic messages, with the syntax of `RUST_LOG`.
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Destination of the diagnostic messages.
+    #[arg(long, value_enum, default_value_t)]
+    pub log_target: logging::Target,
+
+    /// Source of the focus changes, and how windows are activated.
+    #[arg(long, value_enum, default_value_t)]
+    pub backend: Backend,
+
+    /// Modifiers that must be released before a focused window is added
+    /// to the history, separated by commas. It replaces the `modifiers`
+    /// list in the configuration file.
+    #[arg(long, value_name = "NAMES", value_delimiter = ',', value_parser = x11::parse_modifier)]
+    pub modifiers: Option<Vec<x::ModMask>>,
+
+    /// Show the windows in an overlay while they are cycled.
+    #[arg(long)]
+    pub overlay: bool,
+
+    /// Add a focused window to the history only after a key or a pointer
+    /// button is pressed in it, for focus-follows-mouse setups. Needs the
+    /// XInput extension.
+    #[arg(long)]
+    pub ignore_hover: bool,
+
+    /// Never switch to a window in another desktop. Windows in other
+    /// desktops are skipped.
+    #[arg(long)]
+    pub no_desktop_switch: bool,
+
+    /// Draw a border around the activated windows for a moment, with this
+    /// color. It can be a name, or `#rrggbb`.
+    #[arg(long, value_name = "COLOR")]
+    pub flash: Option<String>,
+
+    /// Key combination to switch windows, like `mod1+Tab`. It replaces the
+    /// `bind` key in the configuration file.
+    #[arg(long, value_name = "KEYS", value_parser = x11::parse_key_binding)]
+    pub bind: Option<x11::KeyBinding>,
+
+    /// Key combination to start a chord that activates a marked window.
+    /// The next key, a letter or a digit, is the name of the mark. It
+    /// replaces the `goto_chord` key in the configuration file.
+    #[arg(long, value_name = "KEYS", value_parser = x11::parse_key_binding)]
+    pub goto_chord: Option<x11::KeyBinding>,
+
+    /// Key combination to start a chord that marks the current window.
+    /// The next key, a letter or a digit, is the name of the mark. It
+    /// replaces the `mark_chord` key in the configuration file.
+    #[arg(long, value_name = "KEYS", value_parser = x11::parse_key_binding)]
+    pub mark_chord: Option<x11::KeyBinding>,
+
+    /// X displays managed by the server, separated by commas. It replaces
+    /// the `displays` list in the configuration file.
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub displays: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct SwitchOptions {
+    /// Position of the window in the history.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub nth: u32,
+
+    /// Print the window that would be activated, without activating it.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Activate the most recent window demanding attention, instead of a
+    /// window from the history. The filters are ignored.
+    #[arg(long, conflicts_with_all = ["nth", "dry_run"])]
+    pub urgent: bool,
+
+    /// Return to the window that was in use when a new window took the
+    /// focus without a key or a button press. The filters are ignored.
+    #[arg(long, conflicts_with_all = ["nth", "dry_run", "urgent"])]
+    pub stolen: bool,
+
+    #[command(flatten)]
+    pub filter: FilterOptions,
+}
+
+/// Conditions for the activated window.
+#[derive(Args)]
+pub struct FilterOptions {
+    /// Only windows in the current workspace.
+    #[arg(long)]
+    pub same_workspace: bool,
+
+    /// Only windows with the same class as the current one.
+    #[arg(long)]
+    pub same_class: bool,
+
+    /// Only windows in the monitor where the pointer is.
+    #[arg(long, alias = "output")]
+    pub same_output: bool,
+
+    /// Only windows with a class matched by this regular expression.
+    #[arg(long, value_name = "REGEX")]
+    pub class: Option<String>,
+
+    /// Only windows of this process.
+    #[arg(long)]
+    pub pid: Option<u32>,
+}
+
+impl ServerOptions {
+    pub fn raise(&self) -> x11::Raise {
+        match (self.raise, self.no_raise) {
+            (true, _) => x11::Raise::Always,
+            (_, true) => x11::Raise::Never,
+            _ => x11::Raise::Auto,
+        }
+    }
+}
+
+impl FilterOptions {
+    pub fn filter(&self) -> socket::SwitchFilter {
+        socket::SwitchFilter {
+            same_workspace: self.same_workspace,
+            same_class: self.same_class,
+            same_output: self.same_output,
+            class: self.class.clone(),
+            pid: self.pid,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Backend {
+    /// Track `_NET_ACTIVE_WINDOW`, and activate windows with EWMH
+    /// messages.
+    #[default]
+    Ewmh,
+
+    /// Use the i3 IPC.
+    I3,
+
+    /// Track the input focus, and activate windows with `SetInputFocus`.
+    /// For window managers that don't update `_NET_ACTIVE_WINDOW`.
+    Focus,
+}
+
+/// Write the completions for `shell` to stdout.
+pub fn completions(shell: clap_complete::Shell) -> io::Result<()> {
+    use clap::CommandFactory;
+
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+
+    // `generate` panics if the output can't be written.
+    let mut buffer = Vec::new();
+    clap_complete::generate(shell, &mut command, name, &mut buffer);
+
+    io::stdout().write_all(&buffer)
+}
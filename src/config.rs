@@ -0,0 +1,210 @@
+//! Configuration file of the server.
+//!
+//! The default location is `$XDG_CONFIG_HOME/x11-alternate-focus/config.toml`.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{x11::Raise, Error};
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Rules to exclude windows from the history. See [`crate::rules`].
+    pub exclude: Vec<String>,
+
+    /// Window types not added to the history, like `dialog`,
+    /// `notification`, `splash`, or `menu`. Types are the names of the
+    /// `_NET_WM_WINDOW_TYPE_*` atoms, in lowercase and without the prefix.
+    pub skip_types: Vec<String>,
+
+    /// How transient windows, like dialogs, are recorded.
+    pub transient: Transient,
+
+    /// Maximum number of windows in the history, between 2 and 1000. The
+    /// default is 20.
+    pub history_size: Option<usize>,
+
+    /// Window removed when the history is full.
+    pub eviction: Eviction,
+
+    /// Commands executed when the focused window changes. See
+    /// [`crate::hooks`].
+    pub on_focus: Vec<String>,
+
+    /// Lua script to decide how windows are recorded. See
+    /// [`crate::policy`].
+    pub script: Option<PathBuf>,
+
+    /// Commands to execute the plugins. See [`crate::plugins`].
+    pub plugins: Vec<String>,
+
+    /// Milliseconds to wait until the modifiers are released. After it,
+    /// the focused window is added to the history even if some modifiers
+    /// are still pressed.
+    pub accept_timeout: Option<u64>,
+
+    /// Milliseconds that a window must be focused before it is added to
+    /// the history.
+    pub min_dwell: Option<u64>,
+
+    /// Milliseconds to return to the previous window in a A→B→A sequence,
+    /// like the ones caused by auto-raised popups. If the focus returns
+    /// before it, B is put back where it was in the history.
+    pub flicker_interval: Option<u64>,
+
+    /// Milliseconds without focus changes before the focused window is
+    /// read, so a burst of changes is recorded once. `0` disables it. The
+    /// default is [`crate::x11::DEFAULT_FOCUS_QUIET_TIME`].
+    pub focus_quiet_time: Option<u64>,
+
+    /// Show the windows in an overlay while they are cycled.
+    pub overlay: bool,
+
+    /// Add a focused window to the history only after a key or a pointer
+    /// button is pressed in it, for focus-follows-mouse setups.
+    pub ignore_hover: bool,
+
+    /// Move the pointer to the center of the activated windows.
+    pub warp_pointer: bool,
+
+    /// Never switch to a window in another desktop. Windows in other
+    /// desktops are skipped.
+    pub no_desktop_switch: bool,
+
+    /// Color of the border drawn around the activated windows, as a name or
+    /// as `#rrggbb`. No border is drawn if it is not set.
+    pub flash: Option<String>,
+
+    /// Modifiers that must be released before a focused window is added to
+    /// the history. See [`crate::x11::parse_modifier`] for the names.
+    pub modifiers: Option<Vec<String>>,
+
+    /// Key combination to switch windows, like `mod1+Tab`. See
+    /// [`crate::x11::parse_key_binding`] for the syntax.
+    pub bind: Option<String>,
+
+    /// Key combination to start a chord that activates a marked window,
+    /// like `mod4+g`. The next key, a letter or a digit, is the name of
+    /// the mark.
+    pub goto_chord: Option<String>,
+
+    /// Key combination to start a chord that marks the current window,
+    /// like `mod4+m`. The next key is the name of the mark.
+    pub mark_chord: Option<String>,
+
+    /// Maximum number of requests to the X server waiting for a reply. New
+    /// requests wait until a previous one is finished. The default is
+    /// [`crate::x11::DEFAULT_MAX_PENDING`].
+    pub max_pending_requests: Option<usize>,
+
+    /// X displays managed by the server. If it is empty, the server uses
+    /// the display in `$DISPLAY`.
+    pub displays: Vec<String>,
+
+    /// Activation options for the windows matched by a rule.
+    pub activation: Vec<ActivationRule>,
+}
+
+/// Options to activate the windows matched by a rule, like
+/// `{ match = "class=^zoom$", raise = "always" }`. The options that are
+/// not set keep the value of the server.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActivationRule {
+    /// Rule to match the windows, with the syntax of [`crate::rules`].
+    #[serde(rename = "match")]
+    pub rule: String,
+
+    /// Move the pointer to the monitor of the window before activating it.
+    pub focus_output: Option<bool>,
+
+    /// `auto`, `always`, or `never`.
+    pub raise: Option<Raise>,
+
+    /// Move the pointer to the center of the window.
+    pub warp_pointer: Option<bool>,
+
+    /// Switch to the desktop of the window.
+    pub change_desktop: Option<bool>,
+
+    /// Restore the window if it is minimized.
+    pub unminimize: Option<bool>,
+}
+
+/// How windows with `WM_TRANSIENT_FOR` are recorded in the history.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transient {
+    /// Like any other window.
+    #[default]
+    Record,
+
+    /// Not recorded.
+    Skip,
+
+    /// The main window is recorded instead.
+    Parent,
+}
+
+/// Window removed from a full history.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Eviction {
+    /// The least recently focused window.
+    #[default]
+    Oldest,
+
+    /// The window focused fewer times. On ties, the oldest one.
+    LeastUsed,
+}
+
+impl Config {
+    /// Load the configuration from `path`, or from the default location if
+    /// `path` is `None`.
+    ///
+    /// It is not an error if the file in the default location is missing.
+    pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(Error::Config(format!("{}: {e}", path.display()))),
+        };
+
+        toml::from_str(&source).map_err(|e| Error::Config(format!("{}: {e}", path.display())))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Directory with the executables for the lifecycle events of the server.
+/// See [`crate::hooks`].
+pub fn hooks_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("hooks"))
+}
+
+fn config_dir() -> Option<PathBuf> {
+    let mut path: PathBuf = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => dir.into(),
+        None => Path::new(&env::var_os("HOME")?).join(".config"),
+    };
+
+    path.push("x11-alternate-focus");
+    Some(path)
+}
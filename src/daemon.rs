@@ -0,0 +1,115 @@
+//! Run the server in the background.
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::{Path, PathBuf},
+};
+
+/// Detach the process from the terminal.
+///
+/// The process is forked twice, so the server is not a session leader and
+/// it can't acquire a controlling terminal. The original process waits
+/// until the server calls [`Daemon::ready`], and exits with an error if
+/// the server exits before it. Until then, the standard streams are kept,
+/// so startup errors are reported in the terminal.
+pub fn daemonize() -> io::Result<Daemon> {
+    let (reader, writer) = pipe()?;
+
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => drop(reader),
+        _ => {
+            drop(writer);
+            wait_ready(reader);
+        }
+    }
+
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    fork()?;
+
+    Ok(Daemon(Some(writer)))
+}
+
+/// Server running in the background, until it is ready.
+pub struct Daemon(Option<fs::File>);
+
+impl Daemon {
+    /// Let the original process exit, and redirect the standard streams to
+    /// `/dev/null`.
+    pub fn ready(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.0.take() {
+            writer.write_all(READY)?;
+        }
+
+        env::set_current_dir("/")?;
+        redirect_stdio()
+    }
+}
+
+/// Message sent by the server when it is ready.
+const READY: &[u8] = b"ready";
+
+/// Wait until the server writes [`READY`] in the pipe, and exit.
+fn wait_ready(mut reader: fs::File) -> ! {
+    let mut message = Vec::new();
+    let ready = reader.read_to_end(&mut message).is_ok() && message == READY;
+    unsafe { libc::_exit(if ready { 0 } else { 1 }) }
+}
+
+fn pipe() -> io::Result<(fs::File, fs::File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The descriptors are new, and owned by the returned files.
+    let (reader, writer) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    Ok((reader.into(), writer.into()))
+}
+
+/// Fork the process. The parent exits, and the child returns.
+fn fork() -> io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+fn redirect_stdio() -> io::Result<()> {
+    let null = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+
+    for target in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(null.as_raw_fd(), target) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// File with the PID of the server. It is removed when the value is
+/// dropped.
+pub struct PidFile(PathBuf);
+
+impl PidFile {
+    /// Write the PID of the current process in `path`.
+    pub fn create(path: &Path) -> io::Result<PidFile> {
+        fs::write(path, format!("{}\n", std::process::id()))?;
+        Ok(PidFile(path.to_owned()))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,184 @@
+//! Diagnostic of the environment.
+//!
+//! Check the capabilities of the window manager and of the X server needed
+//! by the server, and print hints for the failed checks.
+
+use std::error::Error;
+
+use xcb::x;
+
+use x11_alternate_focus::socket;
+
+/// Result of a check.
+struct Check {
+    ok: bool,
+    message: String,
+    hint: &'static str,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Check {
+        Check {
+            ok: true,
+            message: message.into(),
+            hint: "",
+        }
+    }
+
+    fn fail(message: impl Into<String>, hint: &'static str) -> Check {
+        Check {
+            ok: false,
+            message: message.into(),
+            hint,
+        }
+    }
+}
+
+pub async fn run() -> Result<(), Box<dyn Error>> {
+    let (conn, screen) =
+        xcb::Connection::connect_with_extensions(None, &[], &[xcb::Extension::Xkb])
+            .map_err(|e| format!("Can't connect to the display: {e}"))?;
+
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .ok_or("Invalid screen.")?
+        .root();
+
+    let mut checks = window_manager(&conn, root)?;
+    checks.push(xkb(&conn));
+    checks.push(server().await);
+
+    let mut failed = 0;
+    for check in &checks {
+        if check.ok {
+            println!("ok    {}", check.message);
+        } else {
+            failed += 1;
+            println!("FAIL  {}", check.message);
+            println!("      hint: {}", check.hint);
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        1 => Err("1 check failed.".into()),
+        n => Err(format!("{n} checks failed.").into()),
+    }
+}
+
+/// Check `_NET_SUPPORTING_WM_CHECK`, and the `_NET_ACTIVE_WINDOW` support in
+/// `_NET_SUPPORTED`.
+fn window_manager(conn: &xcb::Connection, root: x::Window) -> Result<Vec<Check>, Box<dyn Error>> {
+    let supporting_wm_check = atom(conn, "_NET_SUPPORTING_WM_CHECK")?;
+    let supported = atom(conn, "_NET_SUPPORTED")?;
+    let active_window = atom(conn, "_NET_ACTIVE_WINDOW")?;
+    let wm_name = atom(conn, "_NET_WM_NAME")?;
+
+    let mut checks = Vec::new();
+
+    let wm_window = property(conn, root, supporting_wm_check, x::ATOM_WINDOW)?
+        .value::<x::Window>()
+        .first()
+        .copied();
+
+    match wm_window {
+        Some(window) => match property(conn, window, wm_name, x::ATOM_ANY) {
+            Ok(reply) => {
+                let name = String::from_utf8_lossy(reply.value::<u8>());
+                checks.push(Check::ok(format!("EWMH window manager: {name}")));
+            }
+
+            Err(_) => checks.push(Check::fail(
+                "_NET_SUPPORTING_WM_CHECK points to a window that does not exist.",
+                "The window manager is not running, or it did not remove the property.",
+            )),
+        },
+
+        None => checks.push(Check::fail(
+            "No EWMH window manager: _NET_SUPPORTING_WM_CHECK is not set.",
+            "Use `--backend focus` with window managers that don't support EWMH.",
+        )),
+    }
+
+    let reply = property(conn, root, supported, x::ATOM_ATOM)?;
+    let atoms = match reply.format() {
+        32 => reply.value::<x::Atom>(),
+        _ => &[],
+    };
+
+    if atoms.contains(&active_window) {
+        checks.push(Check::ok("_NET_ACTIVE_WINDOW is supported."));
+    } else {
+        checks.push(Check::fail(
+            "_NET_ACTIVE_WINDOW is not in _NET_SUPPORTED.",
+            "Use `--backend focus`, or `--backend i3` in i3.",
+        ));
+    }
+
+    Ok(checks)
+}
+
+/// Check that the XKB extension is available.
+fn xkb(conn: &xcb::Connection) -> Check {
+    const HINT: &str = "XKB is needed to detect when the modifiers are released. \
+                        Check that the X server is not started with `-kb`.";
+
+    if !conn.active_extensions().any(|e| e == xcb::Extension::Xkb) {
+        return Check::fail("The XKB extension is not available.", HINT);
+    }
+
+    let req = xcb::xkb::UseExtension {
+        wanted_major: 1,
+        wanted_minor: 0,
+    };
+
+    match conn.wait_for_reply(conn.send_request(&req)) {
+        Ok(reply) if reply.supported() => Check::ok("XKB is available."),
+        Ok(_) => Check::fail("The version of XKB is not supported.", HINT),
+        Err(e) => Check::fail(format!("XKB: {e}"), HINT),
+    }
+}
+
+/// Check that a server is running in the display.
+async fn server() -> Check {
+    match socket::request(&socket::Request::Ping).await {
+        Ok(socket::Response::Ok) => Check::ok("The server is running."),
+        Ok(_) => Check::fail(
+            "Unexpected response from the server.",
+            "Restart the server, since it may be an older version.",
+        ),
+        Err(e) => Check::fail(
+            format!("The server is not running: {e}"),
+            "Start it with `x11-alternate-focus server`.",
+        ),
+    }
+}
+
+fn atom(conn: &xcb::Connection, name: &str) -> Result<x::Atom, xcb::Error> {
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: false,
+        name: name.as_bytes(),
+    });
+
+    Ok(conn.wait_for_reply(cookie)?.atom())
+}
+
+fn property(
+    conn: &xcb::Connection,
+    window: x::Window,
+    property: x::Atom,
+    r#type: x::Atom,
+) -> Result<x::GetPropertyReply, xcb::Error> {
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window,
+        property,
+        r#type,
+        long_offset: 0,
+        long_length: 1024,
+    });
+
+    conn.wait_for_reply(cookie)
+}
@@ -0,0 +1,149 @@
+//! Errors of the server.
+
+use std::{fmt, io};
+
+/// Error returned by the server APIs.
+#[derive(Debug)]
+pub enum Error {
+    /// The connection to the X server failed, or it was closed.
+    Connection(xcb::ConnError),
+
+    /// The X server returned an error for a request.
+    Protocol {
+        context: &'static str,
+        error: xcb::ProtocolError,
+    },
+
+    /// The window manager does not provide a feature needed by the server.
+    UnsupportedWm(String),
+
+    /// Communication with the window manager or with the clients failed.
+    Ipc {
+        context: &'static str,
+        error: io::Error,
+    },
+
+    /// Invalid configuration.
+    Config(String),
+
+    /// Another server is running on the same display.
+    AlreadyRunning,
+
+    /// The main loop was stopped before the reply to a request was
+    /// received.
+    Cancelled,
+
+    /// The reply to a request was not received before its deadline.
+    Timeout,
+
+    /// A property of a window has an unexpected type or value.
+    InvalidProperty(String),
+
+    /// Error from a [`DisplayBackend`](crate::backend::DisplayBackend).
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+
+    /// The thread of a [`DisplayThread`](crate::x11::DisplayThread) could
+    /// not be started, or it panicked.
+    Thread(String),
+}
+
+impl Error {
+    /// Replace the context of protocol and IPC errors.
+    pub fn context(self, context: &'static str) -> Self {
+        match self {
+            Error::Protocol { error, .. } => Error::Protocol { context, error },
+            Error::Ipc { error, .. } => Error::Ipc { context, error },
+            e => e,
+        }
+    }
+
+    /// Return `true` if the connection to the X server is lost.
+    pub fn is_connection(&self) -> bool {
+        matches!(self, Error::Connection(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connection(e) => write!(f, "X11 connection: {e}"),
+            Error::Protocol { context, error } => write!(f, "{context}: {error}"),
+            Error::UnsupportedWm(e) => write!(f, "Unsupported window manager: {e}"),
+            Error::Ipc { context, error } => write!(f, "{context}: {error}"),
+            Error::Config(e) => e.fmt(f),
+            Error::AlreadyRunning => {
+                f.write_str("A server is already running. Use --replace to take it over.")
+            }
+            Error::Cancelled => f.write_str("Request cancelled."),
+            Error::Timeout => f.write_str("The X server did not reply to a request."),
+            Error::InvalidProperty(e) => write!(f, "Invalid property: {e}"),
+            Error::Backend(e) => e.fmt(f),
+            Error::Thread(e) => write!(f, "Display thread: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Connection(e) => Some(e),
+            Error::Protocol { error, .. } => Some(error),
+            Error::Ipc { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<xcb::Error> for Error {
+    fn from(error: xcb::Error) -> Self {
+        match error {
+            xcb::Error::Connection(e) => Error::Connection(e),
+            xcb::Error::Protocol(e) => e.into(),
+        }
+    }
+}
+
+impl From<xcb::ConnError> for Error {
+    fn from(error: xcb::ConnError) -> Self {
+        Error::Connection(error)
+    }
+}
+
+impl From<xcb::ProtocolError> for Error {
+    fn from(error: xcb::ProtocolError) -> Self {
+        Error::Protocol {
+            context: "X11 request",
+            error,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Ipc {
+            context: "IPC",
+            error,
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for Error {
+    fn from(error: Box<dyn std::error::Error>) -> Self {
+        match error.downcast::<Error>() {
+            Ok(e) => *e,
+            // Only the message is kept, so the error is `Send`.
+            Err(e) => Error::Backend(e.to_string().into()),
+        }
+    }
+}
+
+/// Add a context to the errors in a `Result`.
+pub trait Context<T> {
+    fn context(self, context: &'static str) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn context(self, context: &'static str) -> Result<T, Error> {
+        self.map_err(|e| e.into().context(context))
+    }
+}
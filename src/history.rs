@@ -0,0 +1,415 @@
+//! List of focused windows, most recent first.
+
+use std::{collections::VecDeque, ops::RangeInclusive};
+
+use crate::config::Eviction;
+
+/// Maximum number of windows in the history, if it is not configured.
+pub const DEFAULT_CAPACITY: usize = 20;
+
+/// Valid values for the capacity. The history needs at least the current
+/// and the previous windows.
+pub const CAPACITY_RANGE: RangeInclusive<usize> = 2..=1000;
+
+/// Focus history. The first entry is the current window.
+///
+/// `W` is the identifier of the windows in the backend.
+pub struct History<W> {
+    entries: VecDeque<W>,
+
+    /// Number of times that each window in the history was focused.
+    uses: Vec<(W, u32)>,
+
+    /// Maximum number of windows.
+    capacity: usize,
+
+    /// Window removed when the history is full.
+    eviction: Eviction,
+
+    current_accepted: bool,
+
+    /// Position in the history of the current window before it was
+    /// focused. Used to put it back if it is not accepted.
+    current_origin: Option<usize>,
+}
+
+impl<W> Default for History<W> {
+    fn default() -> Self {
+        History {
+            entries: VecDeque::new(),
+            uses: Vec::new(),
+            capacity: DEFAULT_CAPACITY,
+            eviction: Eviction::default(),
+            current_accepted: false,
+            current_origin: None,
+        }
+    }
+}
+
+impl<W: Copy + PartialEq> History<W> {
+    pub fn current(&self) -> Option<W> {
+        self.entries.front().copied()
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.current_accepted
+    }
+
+    pub fn set_accepted(&mut self, accepted: bool) {
+        self.current_accepted = accepted;
+    }
+
+    /// Change the maximum number of windows, and how windows are removed
+    /// when it is reached. `capacity` is clamped to [`CAPACITY_RANGE`].
+    pub fn set_capacity(&mut self, capacity: usize, eviction: Eviction) {
+        self.capacity = capacity.clamp(*CAPACITY_RANGE.start(), *CAPACITY_RANGE.end());
+        self.eviction = eviction;
+        self.evict();
+    }
+
+    /// Put `window` at the front of the history.
+    ///
+    /// If the current window is not accepted, it is returned to its
+    /// previous position, so it does not displace the other windows.
+    pub fn push(&mut self, window: W) {
+        let entries = &mut self.entries;
+
+        if !self.current_accepted {
+            if let Some(current) = entries.pop_front() {
+                match self.current_origin {
+                    Some(origin) => entries.insert(origin.min(entries.len()), current),
+                    None => self.uses.retain(|&(w, _)| w != current),
+                }
+            }
+        }
+
+        let origin = entries.iter().position(|&w| w == window);
+        if let Some(origin) = origin {
+            entries.remove(origin);
+        }
+
+        entries.push_front(window);
+        self.current_origin = origin;
+
+        match self.uses.iter_mut().find(|(w, _)| *w == window) {
+            Some((_, uses)) => *uses = uses.saturating_add(1),
+            None => self.uses.push((window, 1)),
+        }
+
+        self.evict();
+    }
+
+    /// Remove windows until the history is within its capacity. The current
+    /// window is never removed.
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let index = match self.eviction {
+                Eviction::Oldest => self.entries.len() - 1,
+
+                // On ties, the oldest window is removed.
+                Eviction::LeastUsed => (1..self.entries.len())
+                    .rev()
+                    .min_by_key(|&i| self.uses(self.entries[i]))
+                    .unwrap_or(self.entries.len() - 1),
+            };
+
+            if let Some(window) = self.entries.remove(index) {
+                self.uses.retain(|&(w, _)| w != window);
+            }
+
+            // Keep the position where a rejected current window returns.
+            if let Some(origin) = &mut self.current_origin {
+                if *origin >= index {
+                    *origin = origin.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Number of times that `window` was focused.
+    fn uses(&self, window: W) -> u32 {
+        self.uses
+            .iter()
+            .find(|&&(w, _)| w == window)
+            .map_or(0, |&(_, uses)| uses)
+    }
+
+    /// Remove `window` from the history.
+    pub fn remove(&mut self, window: W) {
+        if self.current() == Some(window) {
+            self.current_accepted = true;
+        }
+
+        if let Some(index) = self.entries.iter().position(|&w| w == window) {
+            self.entries.remove(index);
+
+            // Keep the position where a rejected current window returns.
+            if let Some(origin) = &mut self.current_origin {
+                if *origin >= index {
+                    *origin = origin.saturating_sub(1);
+                }
+            }
+        }
+
+        self.uses.retain(|&(w, _)| w != window);
+    }
+
+    /// Return the `nth` window in the history, and move it to the front.
+    /// `nth` is the position among the windows accepted by `filter`.
+    ///
+    /// With `nth = 1`, it returns the `last` window, and swaps it with
+    /// `current`.
+    pub fn switch(&mut self, nth: usize, filter: impl Fn(W) -> bool) -> Option<W> {
+        let (index, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, &w)| filter(w))
+            .nth(nth)?;
+
+        let window = self.entries.remove(index)?;
+        self.entries.push_front(window);
+
+        Some(window)
+    }
+
+    /// Return the window that would be returned by [`switch`](Self::switch),
+    /// without changing the history.
+    pub fn peek(&self, nth: usize, filter: impl Fn(W) -> bool) -> Option<W> {
+        self.entries.iter().copied().filter(|&w| filter(w)).nth(nth)
+    }
+
+    /// Return the window `step` positions after the current one, without
+    /// changing the history. If `step` is negative, the window is before
+    /// the current one. `step` is counted among the windows accepted by
+    /// `filter`, and it wraps around the ends of the history.
+    ///
+    /// If the current window is not accepted, positions are counted from
+    /// its previous position, so repeated calls walk deeper into the
+    /// history while the modifiers are pressed.
+    pub fn cycle(&self, step: isize, filter: impl Fn(W) -> bool) -> Option<W> {
+        let current = self.current()?;
+
+        let entries: Vec<W> = self
+            .committed()
+            .into_iter()
+            .filter(|&w| w == current || filter(w))
+            .collect();
+
+        let start = entries.iter().position(|&w| w == current)?;
+        let index = (start as isize + step).rem_euclid(entries.len() as isize);
+        let window = entries[index as usize];
+
+        (window != current).then_some(window)
+    }
+
+    /// Return the current window. If it is not accepted, return the window
+    /// that was current before it.
+    pub fn accepted_current(&self) -> Option<W> {
+        self.committed().first().copied()
+    }
+
+    /// Return the windows in the order they would have if the current
+    /// window is not accepted, and is returned to its previous position.
+    pub fn committed(&self) -> Vec<W> {
+        let mut entries: Vec<W> = self.entries.iter().copied().collect();
+
+        if !self.current_accepted {
+            if let Some(origin) = self.current_origin {
+                let current = entries.remove(0);
+                entries.insert(origin.min(entries.len()), current);
+            }
+        }
+
+        entries
+    }
+
+    pub fn contains(&self, window: W) -> bool {
+        self.entries.contains(&window)
+    }
+
+    /// Return the windows, most recent first.
+    pub fn windows(&self) -> Vec<W> {
+        self.entries.iter().copied().collect()
+    }
+
+    /// Restore the windows received from a previous instance. It is ignored
+    /// if the history is not empty.
+    pub fn restore(&mut self, windows: &[W]) {
+        if !self.entries.is_empty() {
+            return;
+        }
+
+        self.entries.extend(windows.iter().take(self.capacity));
+        self.current_accepted = true;
+    }
+
+    /// Replace the history with `windows`. The current window is kept at
+    /// the front, since it is still focused.
+    pub fn import(&mut self, windows: &[W]) {
+        let current = self.current();
+
+        // Position of the current window in `windows`, used to put it back
+        // if it is not accepted.
+        let mut origin = None;
+
+        let mut entries = VecDeque::new();
+        for &window in windows {
+            if Some(window) == current {
+                origin.get_or_insert(entries.len());
+            } else if !entries.contains(&window) {
+                entries.push_back(window);
+            }
+        }
+
+        if let Some(current) = current {
+            entries.push_front(current);
+        }
+
+        entries.truncate(self.capacity);
+
+        self.entries = entries;
+        self.current_origin = origin;
+
+        let entries = &self.entries;
+        self.uses.retain(|&(w, _)| entries.contains(&w));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(windows: &[u32]) -> History<u32> {
+        let mut history = History::default();
+        for &window in windows.iter().rev() {
+            history.push(window);
+            history.set_accepted(true);
+        }
+
+        history
+    }
+
+    #[test]
+    fn push_accepted_windows() {
+        let mut history = history(&[3, 2, 1]);
+
+        history.push(1);
+        history.set_accepted(true);
+
+        assert_eq!(history.windows(), [1, 3, 2]);
+    }
+
+    #[test]
+    fn restore_origin_of_rejected_windows() {
+        let mut history = history(&[4, 3, 2, 1]);
+
+        for window in [2, 1] {
+            history.push(window);
+            history.set_accepted(false);
+        }
+
+        assert_eq!(history.windows(), [1, 4, 3, 2]);
+
+        history.set_accepted(true);
+        history.push(4);
+        assert_eq!(history.windows(), [4, 1, 3, 2]);
+    }
+
+    #[test]
+    fn switch_with_filter() {
+        let mut history = history(&[1, 2, 3, 4]);
+
+        assert_eq!(history.switch(1, |w| w % 2 == 1), Some(3));
+        assert_eq!(history.windows(), [3, 1, 2, 4]);
+
+        assert_eq!(history.switch(4, |_| true), None);
+    }
+
+    #[test]
+    fn cycle_from_previous_position() {
+        let mut history = history(&[4, 3, 2, 1]);
+
+        assert_eq!(history.cycle(1, |_| true), Some(3));
+
+        // 3 is focused, but not accepted.
+        history.push(3);
+        history.set_accepted(false);
+
+        assert_eq!(history.cycle(1, |_| true), Some(2));
+        assert_eq!(history.cycle(2, |_| true), Some(1));
+        assert_eq!(history.cycle(3, |_| true), Some(4));
+        assert_eq!(history.cycle(4, |_| true), None);
+        assert_eq!(history.cycle(-1, |_| true), Some(4));
+        assert_eq!(history.cycle(-2, |_| true), Some(1));
+        assert_eq!(history.accepted_current(), Some(4));
+    }
+
+    #[test]
+    fn remove_current_window() {
+        let mut history = history(&[2, 1]);
+
+        history.push(3);
+        history.remove(3);
+        assert!(history.is_accepted());
+        assert_eq!(history.windows(), [2, 1]);
+    }
+
+    #[test]
+    fn remove_before_origin_of_rejected_window() {
+        let mut history = history(&[5, 4, 3, 2, 1]);
+
+        history.push(3);
+        history.set_accepted(false);
+        history.remove(5);
+        assert_eq!(history.committed(), [4, 3, 2, 1]);
+
+        history.push(1);
+        assert_eq!(history.windows(), [1, 4, 3, 2]);
+    }
+
+    #[test]
+    fn restore_only_empty_history() {
+        let mut history = History::default();
+        history.restore(&[1, 2]);
+        history.restore(&[3]);
+
+        assert_eq!(history.windows(), [1, 2]);
+        assert!(history.is_accepted());
+    }
+
+    #[test]
+    fn evict_least_used_windows() {
+        let mut history = history(&[1, 2, 3, 4]);
+        history.set_capacity(3, Eviction::LeastUsed);
+        assert_eq!(history.windows(), [1, 2, 3]);
+
+        for window in [3, 1, 3, 1, 2] {
+            history.push(window);
+            history.set_accepted(true);
+        }
+
+        // 1 and 3 are focused three times, and 2 twice. 2 is removed, even
+        // if 3 is older.
+        history.push(5);
+        assert_eq!(history.windows(), [5, 1, 3]);
+
+        history.set_capacity(0, Eviction::Oldest);
+        assert_eq!(history.windows(), [5, 1]);
+    }
+
+    #[test]
+    fn import_keeps_current_window() {
+        let mut history = history(&[3, 2, 1]);
+
+        history.import(&[5, 2, 4, 5]);
+        assert_eq!(history.windows(), [3, 5, 2, 4]);
+
+        // A pending window returns to its imported position.
+        history.push(2);
+        history.set_accepted(false);
+        history.import(&[4, 2, 1]);
+        history.push(1);
+        assert_eq!(history.windows(), [1, 4, 2]);
+    }
+}
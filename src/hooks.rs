@@ -0,0 +1,96 @@
+//! Commands executed when the focused window changes, and in the lifecycle
+//! events of the server.
+//!
+//! The `on_focus` commands are executed with `sh -c`, after replacing the
+//! variables described in [`crate::template`]. Values are quoted, so they
+//! are always a single word for the shell.
+//!
+//! For the lifecycle events, the server executes the file with the name of
+//! the event in the hooks directory (see [`crate::config::hooks_dir`]), if
+//! it exists.
+
+use std::io;
+
+use tokio::{process::Command, sync::broadcast::error::RecvError};
+
+use crate::{backend::DisplayBackend, config, server::Server, template};
+
+/// Lifecycle events of the server.
+#[derive(Clone, Copy)]
+pub enum Event {
+    /// The server is ready. Executed again after reconnecting to the
+    /// display.
+    Startup,
+
+    /// The server is finished.
+    Shutdown,
+
+    /// A window was activated. The argument is the window id.
+    Switch,
+
+    /// An operation failed. The argument is the error message.
+    Error,
+}
+
+impl Event {
+    fn name(self) -> &'static str {
+        match self {
+            Event::Startup => "startup",
+            Event::Shutdown => "shutdown",
+            Event::Switch => "switch",
+            Event::Error => "error",
+        }
+    }
+}
+
+/// Execute the hook for `event`, without waiting for it.
+pub fn lifecycle(event: Event, args: &[&str]) {
+    let Some(path) = config::hooks_dir().map(|d| d.join(event.name())) else {
+        return;
+    };
+
+    match Command::new(&path).args(args).spawn() {
+        Ok(_) => tracing::debug!(hook = event.name(), "Hook"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => tracing::warn!("Can't execute hook {}: {e}", path.display()),
+    }
+}
+
+/// Run the `on_focus` commands for every window accepted in the history.
+pub async fn on_focus<B: DisplayBackend>(server: &Server<B>) {
+    let mut changes = server.subscribe();
+
+    loop {
+        let window = match changes.recv().await {
+            Ok((window, _)) => window,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+
+        let commands = server.on_focus_commands();
+        if commands.is_empty() {
+            continue;
+        }
+
+        let entry = server.entry(window).await;
+
+        for command in commands.iter() {
+            let command = template::render_with(command, &entry, quote);
+            spawn(&command);
+        }
+    }
+}
+
+/// Execute `command` with `sh -c`, without waiting for it.
+fn spawn(command: &str) {
+    tracing::debug!(command, "Hook");
+
+    if let Err(e) = Command::new("sh").arg("-c").arg(command).spawn() {
+        tracing::warn!("Can't execute hook {command:?}: {e}");
+    }
+}
+
+/// Quote `value` as a single word for the shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
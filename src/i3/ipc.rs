@@ -0,0 +1,82 @@
+//! Message framing of the i3 IPC protocol.
+//!
+//! <https://i3wm.org/docs/ipc.html>
+
+use std::{io, path::Path};
+
+use serde::de::DeserializeOwned;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+const MAGIC: &[u8] = b"i3-ipc";
+
+pub const RUN_COMMAND: u32 = 0;
+pub const SUBSCRIBE: u32 = 2;
+
+/// Bit set in the type of the event messages.
+pub const EVENT_MASK: u32 = 1 << 31;
+
+pub const EVENT_WINDOW: u32 = EVENT_MASK | 3;
+
+pub struct Connection(UnixStream);
+
+impl Connection {
+    pub async fn connect(path: &Path) -> io::Result<Self> {
+        Ok(Connection(UnixStream::connect(path).await?))
+    }
+
+    pub async fn send(&mut self, msg_type: u32, payload: &[u8]) -> io::Result<()> {
+        let length = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "payload too large"))?;
+
+        let mut msg = Vec::with_capacity(MAGIC.len() + 8 + payload.len());
+        msg.extend_from_slice(MAGIC);
+        msg.extend_from_slice(&length.to_ne_bytes());
+        msg.extend_from_slice(&msg_type.to_ne_bytes());
+        msg.extend_from_slice(payload);
+
+        self.0.write_all(&msg).await
+    }
+
+    /// Read the next message. Returns its type and its payload.
+    pub async fn receive(&mut self) -> io::Result<(u32, Vec<u8>)> {
+        let mut header = [0; MAGIC.len() + 8];
+        self.0.read_exact(&mut header).await?;
+
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid magic"));
+        }
+
+        let field = |n: usize| {
+            let offset = MAGIC.len() + n * 4;
+            u32::from_ne_bytes(header[offset..offset + 4].try_into().unwrap())
+        };
+
+        let (length, msg_type) = (field(0), field(1));
+
+        let mut payload = vec![0; length as usize];
+        self.0.read_exact(&mut payload).await?;
+
+        Ok((msg_type, payload))
+    }
+
+    /// Send a message, and parse the reply.
+    pub async fn request<T: DeserializeOwned>(
+        &mut self,
+        msg_type: u32,
+        payload: &[u8],
+    ) -> io::Result<T> {
+        self.send(msg_type, payload).await?;
+
+        loop {
+            let (reply_type, payload) = self.receive().await?;
+
+            // Events can be received before the reply.
+            if reply_type == msg_type {
+                return Ok(serde_json::from_slice(&payload)?);
+            }
+        }
+    }
+}
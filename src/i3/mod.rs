@@ -0,0 +1,336 @@
+//! Backend using the i3 IPC protocol.
+//!
+//! Focus changes are received from the `window` events, and windows are
+//! activated with `[con_id=…] focus` commands.
+
+mod ipc;
+
+#[cfg(feature = "sway")]
+pub mod sway;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    env,
+    error::Error,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use tokio::time::Instant;
+use xcb::{x, Xid, XidNew};
+
+use crate::{
+    backend::{CycleKey, DisplayBackend, FocusEvent, ScreenState, Stats, WindowInfo},
+    x11,
+};
+
+/// Time to wait before reconnecting to i3.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+pub struct Backend {
+    display: x11::DisplayServer,
+
+    socket: PathBuf,
+
+    events: Subscription,
+
+    /// Container ids of the focused windows.
+    containers: RefCell<HashMap<x::Window, i64>>,
+}
+
+/// Connection to receive events from the IPC socket.
+///
+/// If the connection is lost (for example, when i3 is restarted), it
+/// tries to connect again.
+struct Subscription {
+    socket: PathBuf,
+    events: &'static [u8],
+    connection: Cell<Option<ipc::Connection>>,
+}
+
+#[derive(Deserialize)]
+struct CommandReply {
+    success: bool,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WindowEvent {
+    change: String,
+    container: Container,
+}
+
+#[derive(Deserialize)]
+struct Container {
+    id: i64,
+    window: Option<u32>,
+
+    #[cfg_attr(not(feature = "sway"), allow(dead_code))]
+    name: Option<String>,
+
+    /// Application id of Wayland windows in sway.
+    #[cfg_attr(not(feature = "sway"), allow(dead_code))]
+    app_id: Option<String>,
+
+    #[cfg_attr(not(feature = "sway"), allow(dead_code))]
+    window_properties: Option<WindowProperties>,
+
+    /// Process of the window. Only sent by sway.
+    #[cfg_attr(not(feature = "sway"), allow(dead_code))]
+    pid: Option<u32>,
+
+    #[cfg_attr(not(feature = "sway"), allow(dead_code))]
+    #[serde(default)]
+    urgent: bool,
+}
+
+#[derive(Deserialize)]
+struct WindowProperties {
+    #[cfg_attr(not(feature = "sway"), allow(dead_code))]
+    class: Option<String>,
+}
+
+impl CommandReply {
+    fn check(self) -> io::Result<()> {
+        if self.success {
+            Ok(())
+        } else {
+            let error = self.error.unwrap_or_else(|| "command failed".into());
+            Err(io::Error::other(error))
+        }
+    }
+}
+
+impl Subscription {
+    fn new(socket: PathBuf, events: &'static [u8]) -> Self {
+        Subscription {
+            socket,
+            events,
+            connection: Default::default(),
+        }
+    }
+
+    /// Wait for the next event. Returns its type and its payload.
+    async fn receive(&self) -> (u32, Vec<u8>) {
+        loop {
+            let mut conn = match self.connection.take() {
+                Some(conn) => conn,
+                None => match self.connect().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::warn!("IPC: {e}");
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                },
+            };
+
+            match conn.receive().await {
+                Ok(msg) => {
+                    self.connection.set(Some(conn));
+                    return msg;
+                }
+
+                Err(e) => {
+                    tracing::warn!("IPC: {e}");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+
+    async fn connect(&self) -> io::Result<ipc::Connection> {
+        let mut conn = ipc::Connection::connect(&self.socket).await?;
+
+        conn.request::<CommandReply>(ipc::SUBSCRIBE, self.events)
+            .await?
+            .check()?;
+
+        Ok(conn)
+    }
+}
+
+/// Send `command` to the IPC socket in `socket`.
+async fn run_command(socket: &Path, command: &str) -> io::Result<()> {
+    let mut conn = ipc::Connection::connect(socket).await?;
+
+    let replies: Vec<CommandReply> = conn.request(ipc::RUN_COMMAND, command.as_bytes()).await?;
+    replies.into_iter().try_for_each(CommandReply::check)
+}
+
+impl Backend {
+    /// Find the IPC socket of i3, from the `I3SOCK` variable or from the
+    /// `I3_SOCKET_PATH` property of the root window.
+    pub async fn new(display: x11::DisplayServer) -> Result<Self, crate::Error> {
+        let socket: PathBuf = match env::var_os("I3SOCK") {
+            Some(path) => path.into(),
+            None => display
+                .text_property(display.roots()[0], display.atoms().i3_socket_path)
+                .await?
+                .ok_or_else(|| {
+                    crate::Error::UnsupportedWm("the i3 IPC socket is not found".into())
+                })?
+                .into(),
+        };
+
+        Ok(Backend {
+            display,
+            events: Subscription::new(socket.clone(), br#"["window"]"#),
+            socket,
+            containers: Default::default(),
+        })
+    }
+
+    /// Focus the container of `window`.
+    async fn focus(&self, window: x::Window) -> io::Result<()> {
+        let command = match self.containers.borrow().get(&window) {
+            Some(id) => format!("[con_id={id}] focus"),
+            None => format!("[id={}] focus", window.resource_id()),
+        };
+
+        run_command(&self.socket, &command).await
+    }
+}
+
+impl DisplayBackend for Backend {
+    type Window = x::Window;
+
+    async fn focus_event(&self) -> Result<FocusEvent<x::Window>, Box<dyn Error>> {
+        loop {
+            let (msg_type, payload) = self.events.receive().await;
+            if msg_type != ipc::EVENT_WINDOW {
+                continue;
+            }
+
+            let event: WindowEvent = match serde_json::from_slice(&payload) {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!("i3: {e}");
+                    continue;
+                }
+            };
+
+            let window = match event.container.window {
+                // SAFETY: the id is only used to send requests to the X server.
+                Some(w) => unsafe { x::Window::new(w) },
+                None => continue,
+            };
+
+            match event.change.as_str() {
+                "focus" => {
+                    self.containers
+                        .borrow_mut()
+                        .insert(window, event.container.id);
+
+                    return Ok(FocusEvent::Focused(window));
+                }
+
+                "close" => {
+                    self.containers.borrow_mut().remove(&window);
+                    return Ok(FocusEvent::Closed(window));
+                }
+
+                _ => (),
+            }
+        }
+    }
+
+    async fn modifiers_pressed(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.display.modifiers_pressed().await?)
+    }
+
+    async fn modifiers_released(&self) {
+        self.display.modifiers_released().await
+    }
+
+    async fn interaction(&self, since: Instant) {
+        self.display.interaction(since).await
+    }
+
+    async fn cycle_key(&self) -> Result<CycleKey, Box<dyn Error>> {
+        Ok(self.display.cycle_key().await?)
+    }
+
+    async fn show_overlay(
+        &self,
+        entries: &[(x::Window, String)],
+        selected: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.display.show_overlay(entries, selected).await?)
+    }
+
+    fn hide_overlay(&self) {
+        self.display.hide_overlay();
+    }
+
+    async fn flash(&self, window: x::Window, color: &str) -> Result<(), Box<dyn Error>> {
+        Ok(self.display.flash(window, color).await?)
+    }
+
+    async fn is_available(&self, window: x::Window) -> bool {
+        self.display.is_available(window).await
+    }
+
+    fn urgent_windows(&self) -> Vec<x::Window> {
+        self.display.urgent_windows()
+    }
+
+    async fn activate(&self, window: x::Window) -> Result<(), Box<dyn Error>> {
+        let options = self.display.activation_options(window).await;
+        self.display.prepare_activation(window, &options).await;
+        self.focus(window).await?;
+        self.display.finish_activation(window, &options).await;
+        Ok(())
+    }
+
+    async fn current_workspace(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self.display.current_desktop().await?)
+    }
+
+    async fn current_output(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self.display.pointer_output().await?)
+    }
+
+    async fn window_output(&self, window: x::Window) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self.display.window_output(window).await?)
+    }
+
+    async fn window_info(&self, window: x::Window) -> Result<WindowInfo, Box<dyn Error>> {
+        Ok(self.display.window_info(window).await?)
+    }
+
+    fn screens(&self) -> Option<usize> {
+        Some(self.display.roots().len())
+    }
+
+    async fn screen_states(&self) -> Vec<ScreenState> {
+        self.display.screen_states().await
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        Some(self.display.stats())
+    }
+
+    async fn publish_history(&self, windows: &[x::Window]) -> Result<(), Box<dyn Error>> {
+        Ok(self.display.publish_history(windows).await?)
+    }
+
+    async fn batch<F: Future>(&self, requests: F) -> F::Output {
+        self.display.batch(requests).await
+    }
+
+    fn window_id(window: x::Window) -> u64 {
+        window.resource_id().into()
+    }
+
+    fn window_from_id(id: u64) -> Option<x::Window> {
+        let id = u32::try_from(id).ok().filter(|&id| id != 0)?;
+
+        // SAFETY: the id is only used to send requests to the X server.
+        Some(unsafe { x::Window::new(id) })
+    }
+}
@@ -0,0 +1,180 @@
+//! Backend for sway, without any X11 connection.
+//!
+//! Windows are identified by their container ids.
+
+use std::{cell::RefCell, collections::HashMap, env, error::Error, path::PathBuf};
+
+use tokio::time::Instant;
+
+use super::{ipc, run_command, Subscription, WindowEvent};
+use crate::backend::{CycleKey, DisplayBackend, FocusEvent, ScreenState, Stats, WindowInfo};
+
+pub struct Sway {
+    socket: PathBuf,
+
+    events: Subscription,
+
+    /// Class and title of the focused windows, from the `window` events.
+    windows: RefCell<HashMap<i64, WindowInfo>>,
+
+    /// Windows demanding attention, most recent first, from the `urgent`
+    /// events.
+    urgent: RefCell<Vec<i64>>,
+}
+
+impl Sway {
+    /// Return the IPC socket of sway, if the process is running in a
+    /// Wayland session.
+    pub fn from_env() -> Option<Self> {
+        env::var_os("WAYLAND_DISPLAY")?;
+
+        let socket: PathBuf = env::var_os("SWAYSOCK")?.into();
+
+        Some(Sway {
+            events: Subscription::new(socket.clone(), br#"["window"]"#),
+            socket,
+            windows: Default::default(),
+            urgent: Default::default(),
+        })
+    }
+}
+
+impl DisplayBackend for Sway {
+    type Window = i64;
+
+    async fn focus_event(&self) -> Result<FocusEvent<i64>, Box<dyn Error>> {
+        loop {
+            let (msg_type, payload) = self.events.receive().await;
+            if msg_type != ipc::EVENT_WINDOW {
+                continue;
+            }
+
+            let event: WindowEvent = match serde_json::from_slice(&payload) {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!("sway: {e}");
+                    continue;
+                }
+            };
+
+            let container = event.container;
+            let id = container.id;
+
+            match event.change.as_str() {
+                "focus" => {
+                    let class = container
+                        .app_id
+                        .or_else(|| container.window_properties.and_then(|p| p.class));
+
+                    let info = WindowInfo {
+                        class,
+                        title: container.name,
+                        desktop: None,
+                        pid: container.pid,
+                        window_type: None,
+                        transient_for: None,
+                    };
+
+                    self.windows.borrow_mut().insert(id, info);
+                    return Ok(FocusEvent::Focused(id));
+                }
+
+                "close" => {
+                    self.windows.borrow_mut().remove(&id);
+                    self.urgent.borrow_mut().retain(|&w| w != id);
+                    return Ok(FocusEvent::Closed(id));
+                }
+
+                "urgent" => {
+                    let mut urgent = self.urgent.borrow_mut();
+                    urgent.retain(|&w| w != id);
+
+                    if container.urgent {
+                        urgent.insert(0, id);
+                    }
+                }
+
+                _ => (),
+            }
+        }
+    }
+
+    /// Modifiers are not available in the sway IPC, so every focus change
+    /// is accepted immediately.
+    async fn modifiers_pressed(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+
+    async fn modifiers_released(&self) {}
+
+    /// Input events are not available in the sway IPC.
+    async fn interaction(&self, _: Instant) {}
+
+    /// Keys can't be grabbed in the sway IPC.
+    async fn cycle_key(&self) -> Result<CycleKey, Box<dyn Error>> {
+        std::future::pending().await
+    }
+
+    /// There is no overlay in sway.
+    async fn show_overlay(&self, _: &[(i64, String)], _: usize) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn hide_overlay(&self) {}
+
+    /// Windows can't be decorated through the sway IPC.
+    async fn flash(&self, _: i64, _: &str) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Closed windows are removed from the `window` events.
+    async fn is_available(&self, _: i64) -> bool {
+        true
+    }
+
+    fn urgent_windows(&self) -> Vec<i64> {
+        self.urgent.borrow().clone()
+    }
+
+    async fn activate(&self, id: i64) -> Result<(), Box<dyn Error>> {
+        Ok(run_command(&self.socket, &format!("[con_id={id}] focus")).await?)
+    }
+
+    async fn current_workspace(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    async fn current_output(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    async fn window_output(&self, _: i64) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    /// The class is the `app_id` of Wayland windows, or `WM_CLASS` of
+    /// XWayland windows. Workspaces are not available.
+    async fn window_info(&self, id: i64) -> Result<WindowInfo, Box<dyn Error>> {
+        Ok(self.windows.borrow().get(&id).cloned().unwrap_or_default())
+    }
+
+    fn screens(&self) -> Option<usize> {
+        None
+    }
+
+    async fn screen_states(&self) -> Vec<ScreenState> {
+        Vec::new()
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        None
+    }
+
+    fn window_id(id: i64) -> u64 {
+        id as u64
+    }
+
+    fn window_from_id(id: u64) -> Option<i64> {
+        Some(id as i64)
+    }
+}
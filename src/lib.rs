@@ -0,0 +1,39 @@
+//! Track the focus history of the windows in a display, and activate the
+//! previous windows.
+//!
+//! The main components are:
+//!
+//! * [`x11::DisplayServer`], an asynchronous connection to the X server.
+//!   Replies are received with [`x11::DisplayServer::send_request`] while
+//!   [`x11::DisplayServer::main_loop`] is running.
+//! * [`backend::DisplayBackend`], the interface between the display system
+//!   and the server. It is implemented for X11 with EWMH, for i3, and for
+//!   sway.
+//! * [`tracker::FocusTracker`], which records the focus changes in a
+//!   [`history::History`].
+//! * [`server::Server`], which handles the [`socket`] requests from the
+//!   clients. Applications can read the accepted windows with
+//!   [`server::Server::watch_active_window`], without the socket.
+//!
+//! Everything runs in a single thread, so the futures must be executed in a
+//! [`tokio::task::LocalSet`]. Applications with a multi-threaded runtime can
+//! run the connection in its own thread with [`x11::DisplayThread`].
+
+pub mod backend;
+pub mod config;
+pub mod error;
+pub mod history;
+pub mod hooks;
+pub mod i3;
+pub mod plugins;
+pub mod policy;
+pub mod rules;
+pub mod server;
+pub mod socket;
+pub mod state;
+pub mod systemd;
+pub mod template;
+pub mod tracker;
+pub mod x11;
+
+pub use error::Error;
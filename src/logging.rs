@@ -0,0 +1,142 @@
+//! Diagnostic messages of the server.
+
+use std::{
+    ffi::CString,
+    io::{self, IsTerminal, Write},
+};
+
+use tracing::{Level, Metadata};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
+
+/// Destination of the messages.
+#[derive(Clone, Default, clap::ValueEnum)]
+pub enum Target {
+    /// Write the messages to stderr.
+    #[default]
+    Stderr,
+
+    /// Send the messages to the systemd journal.
+    Journald,
+
+    /// Send the messages to the syslog daemon, with the `LOG_USER`
+    /// facility.
+    Syslog,
+}
+
+/// Write the messages to `target`.
+///
+/// `directives` uses the same syntax as `RUST_LOG`. If it is `None`, the
+/// filter is read from `RUST_LOG`, and only messages with level `INFO` or
+/// higher are written if the variable is not set.
+pub fn init(directives: Option<&str>, target: &Target) -> Result<(), String> {
+    let builder = EnvFilter::builder().with_default_directive(LevelFilter::INFO.into());
+
+    let filter = match directives {
+        Some(directives) => builder
+            .parse(directives)
+            .map_err(|e| format!("Invalid log level: {e}"))?,
+
+        None => builder.from_env_lossy(),
+    };
+
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match target {
+        Target::Stderr => {
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(io::stderr)
+                .with_ansi(io::stderr().is_terminal());
+
+            registry.with(layer).init();
+        }
+
+        Target::Journald => {
+            let layer = tracing_journald::layer().map_err(|e| format!("journald: {e}"))?;
+            registry.with(layer).init();
+        }
+
+        Target::Syslog => {
+            unsafe {
+                libc::openlog(
+                    c"x11-alternate-focus".as_ptr(),
+                    libc::LOG_PID,
+                    libc::LOG_USER,
+                );
+            }
+
+            // The level and the timestamp are added by syslog.
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(Syslog)
+                .with_ansi(false)
+                .with_level(false)
+                .without_time();
+
+            registry.with(layer).init();
+        }
+    }
+
+    Ok(())
+}
+
+/// Send each message with `syslog(3)`, with a priority from its level.
+struct Syslog;
+
+impl<'a> MakeWriter<'a> for Syslog {
+    type Writer = SyslogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SyslogWriter::new(libc::LOG_INFO)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let priority = match *meta.level() {
+            Level::ERROR => libc::LOG_ERR,
+            Level::WARN => libc::LOG_WARNING,
+            Level::INFO => libc::LOG_INFO,
+            Level::DEBUG | Level::TRACE => libc::LOG_DEBUG,
+        };
+
+        SyslogWriter::new(priority)
+    }
+}
+
+/// Collect a message, and send it when the writer is dropped.
+struct SyslogWriter {
+    priority: libc::c_int,
+    buffer: Vec<u8>,
+}
+
+impl SyslogWriter {
+    fn new(priority: libc::c_int) -> Self {
+        SyslogWriter {
+            priority,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl Write for SyslogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend(buf.iter().filter(|&&b| b != 0));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogWriter {
+    fn drop(&mut self) {
+        let message = self.buffer.trim_ascii_end().to_vec();
+        if message.is_empty() {
+            return;
+        }
+
+        if let Ok(message) = CString::new(message) {
+            unsafe { libc::syslog(self.priority, c"%s".as_ptr(), message.as_ptr()) };
+        }
+    }
+}
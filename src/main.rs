@@ -1,103 +1,1074 @@
-use std::process::ExitCode;
+use std::{
+    future::Future,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-use tokio::task;
+use tokio::{
+    io::AsyncReadExt,
+    net::UnixListener,
+    task::{self, JoinSet},
+};
+use tracing::Instrument;
 use xcb::x;
 
-mod x11;
+use x11_alternate_focus::{
+    backend::{DisplayBackend, Timing},
+    config,
+    error::Context,
+    hooks, i3,
+    server::{Server, Settings},
+    socket, state, systemd, x11, Error,
+};
 
-enum Command {
-    Server,
-    Switch,
+mod bar;
+mod cli;
+mod daemon;
+mod doctor;
+mod logging;
+mod menu;
+mod prop;
+
+use clap::Parser;
+
+use cli::{Backend, Command, HistoryAction, ServerOptions};
+
+/// Connect to the X displays in `names`, or to the one in `$DISPLAY` if
+/// it is empty. It must be called in the runtime, since the connections
+/// are registered in its reactor.
+fn connect_displays(names: &[String]) -> Result<Vec<(Option<String>, x11::DisplayServer)>, Error> {
+    let names: Vec<Option<String>> = match names {
+        [] => vec![None],
+        names => names.iter().cloned().map(Some).collect(),
+    };
+
+    let mut displays = Vec::new();
+    for name in names {
+        match x11::DisplayServer::connect(name.as_deref()) {
+            Ok(c) => displays.push((name, c)),
+            Err(e) => {
+                let name = name.or_else(|| std::env::var("DISPLAY").ok());
+                tracing::error!(
+                    "Can't connect to the display {:?}.",
+                    name.unwrap_or_default()
+                );
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(displays)
+}
+
+/// Run a server for each display. If there are multiple displays, the
+/// messages of each server are in a span with the name of its display.
+async fn run_displays(
+    displays: Vec<(Option<String>, x11::DisplayServer)>,
+    cli_options: Rc<ServerOptions>,
+    options: ServerOptions,
+    settings: Settings,
+) -> Result<(), Error> {
+    let mut servers = JoinSet::new();
+    let multiple = displays.len() > 1;
+
+    for (name, display) in displays {
+        let span = match (&name, multiple) {
+            (Some(name), true) => tracing::info_span!("display", name),
+            _ => tracing::Span::none(),
+        };
+
+        let server = run_server_reconnect(
+            name,
+            display,
+            cli_options.clone(),
+            options.clone(),
+            settings.clone(),
+        );
+        servers.spawn_local(server.instrument(span));
+    }
+
+    // Stop all servers if any of them fails.
+    while let Some(result) = servers.join_next().await {
+        match result {
+            Ok(result) => result?,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    Ok(())
 }
 
-async fn switch_handler(display: x11::DisplayServer) {
-    // https://specifications.freedesktop.org/wm-spec/1.5/ar01s09.html#sourceindication
-    const SOURCE_PAGER: u32 = 2;
+/// Run the server, and connect again if the connection to the X server is
+/// lost.
+///
+/// `name` is the display of the server. If it is `None`, the server uses
+/// the display in `$DISPLAY`, and it can receive its socket from the
+/// service manager.
+///
+/// `cli_options` are the options in the command line, used to load the
+/// configuration again with the `reload` command.
+async fn run_server_reconnect(
+    name: Option<String>,
+    mut display: x11::DisplayServer,
+    cli_options: Rc<ServerOptions>,
+    options: ServerOptions,
+    settings: Settings,
+) -> Result<(), Error> {
+    const MIN_DELAY: Duration = Duration::from_secs(1);
+    const MAX_DELAY: Duration = Duration::from_secs(30);
+
+    let mut delay = MIN_DELAY;
 
     loop {
-        display.switch_command().notified().await;
-
-        if let Some(window) = display.switch_window() {
-            let root = display.roots()[0];
-
-            let event = x::ClientMessageEvent::new(
-                window,
-                display.atoms().net_active_window,
-                x::ClientMessageData::Data32([SOURCE_PAGER, 0, 0, 0, 0]),
-            );
-
-            let req = x::SendEvent {
-                propagate: false,
-                destination: x::SendEventDest::Window(root),
-                event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
-                event: &event,
-            };
+        let connected = Instant::now();
+
+        let server = run_server(
+            name.as_deref(),
+            display,
+            &cli_options,
+            &options,
+            settings.clone(),
+        );
+
+        match server.await {
+            Err(e) if e.is_connection() => {
+                tracing::error!("Connection to the X server lost. {e}");
+            }
+
+            result => return result,
+        }
+
+        if connected.elapsed() > MAX_DELAY {
+            delay = MIN_DELAY;
+        }
+
+        display = loop {
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_DELAY);
 
-            let _ = display.connection().send_and_check_request(&req);
+            match x11::DisplayServer::connect(name.as_deref()) {
+                Ok(display) => break display,
+                Err(e) => tracing::warn!("{e}"),
+            }
         };
     }
 }
 
-async fn run_server(display: x11::DisplayServer) -> Result<(), xcb::Error> {
-    tokio::task::spawn_local(switch_handler(display.clone()));
+async fn run_server(
+    name: Option<&str>,
+    display: x11::DisplayServer,
+    cli_options: &Rc<ServerOptions>,
+    options: &ServerOptions,
+    settings: Settings,
+) -> Result<(), Error> {
+    with_main_loop(&display, check_single_server(&display, options)).await?;
+
+    // Wait until the previous server, if any, releases the manager
+    // selection, and receive its history.
+    let history = with_main_loop(&display, display.acquire_manager()).await?;
+
+    let listener = match name {
+        Some(name) => socket::bind_at(&socket::path_for(name), options.replace),
+        None => socket::bind(options.replace),
+    };
+
+    let listener = listener.context("Socket")?;
+
+    let state_path = match name {
+        Some(name) => state::path_for(name),
+        None => state::path(),
+    };
+
+    // The interactions are also needed to detect focus steals, so they are
+    // selected when XInput is available, even without ignore_hover.
+    match with_main_loop(&display, display.listen_interactions()).await {
+        Ok(()) => (),
+        Err(e) if settings.ignore_hover => return Err(e.context("XInput")),
+        Err(e) => tracing::debug!("Focus steals are not detected: {e}"),
+    }
+
+    configure_display(&display, options, &settings)?;
+
+    if let Some(binding) = options.bind {
+        with_main_loop(&display, display.grab_key_binding(binding)).await?;
+    }
+
+    for (binding, chord) in [
+        (options.goto_chord, x11::Chord::Goto),
+        (options.mark_chord, x11::Chord::Mark),
+    ] {
+        if let Some(binding) = binding {
+            with_main_loop(&display, display.grab_chord(binding, chord)).await?;
+        }
+    }
+
+    match options.backend {
+        Backend::Ewmh | Backend::Focus => {
+            let tracking = match options.backend {
+                Backend::Focus => x11::Tracking::InputFocus,
+                _ => x11::Tracking::ActiveWindow,
+            };
+
+            with_main_loop(&display, display.listen_root_events(tracking)).await?;
+            serve(
+                display.clone(),
+                Rc::new(display),
+                listener,
+                cli_options,
+                options,
+                settings,
+                SavedHistory {
+                    handed_over: &history,
+                    state_path,
+                },
+            )
+            .await
+        }
+
+        Backend::I3 => {
+            let i3 = with_main_loop(&display, i3::Backend::new(display.clone())).await?;
+            with_main_loop(
+                &display,
+                display.listen_root_events(x11::Tracking::Disabled),
+            )
+            .await?;
+            serve(
+                display,
+                Rc::new(i3),
+                listener,
+                cli_options,
+                options,
+                settings,
+                SavedHistory {
+                    handed_over: &history,
+                    state_path,
+                },
+            )
+            .await
+        }
+    }
+}
+
+/// History received from a replaced server, and the file where the
+/// history is saved when the server quits.
+struct SavedHistory<'a> {
+    handed_over: &'a [x::Window],
+    state_path: Option<PathBuf>,
+}
+
+/// Run the server with `backend`, and process the commands received from
+/// the clients in `display` and in the socket.
+async fn serve<B>(
+    display: x11::DisplayServer,
+    backend: Rc<B>,
+    listener: UnixListener,
+    cli_options: &Rc<ServerOptions>,
+    options: &ServerOptions,
+    settings: Settings,
+    saved: SavedHistory<'_>,
+) -> Result<(), Error>
+where
+    B: DisplayBackend<Window = x::Window>,
+{
+    let mut commands = display.take_commands().ok_or(Error::AlreadyRunning)?;
+
+    let server = Server::new(backend);
+    server.set_per_workspace(options.per_workspace);
+    server.configure(settings);
+    server.restore(saved.handed_over);
+
+    let reload_display = display.clone();
+    let cli_options = cli_options.clone();
+    server.set_reload(move || {
+        let (options, settings) = load_settings(&cli_options)?;
+        configure_display(&reload_display, &options, &settings)?;
+        Ok(settings)
+    });
+
+    // Tasks are aborted when the main loop is finished.
+    let mut tasks = JoinSet::new();
+
+    let tracker = server.clone();
+    let tracker_display = display.clone();
+    tasks.spawn_local(async move {
+        if let Err(e) = tracker.track_focus().await {
+            tracing::error!("{e}");
+            tracker_display.stop();
+        }
+    });
+
+    // The socket from the service manager is kept, so it can start the
+    // server again.
+    let socket_path = match systemd::socket_activated() {
+        true => None,
+        false => listener
+            .local_addr()
+            .ok()
+            .and_then(|a| a.as_pathname().map(PathBuf::from)),
+    };
+
+    tasks.spawn_local(socket::listen(listener, server.clone()));
+
+    // The history from a replaced server is newer than the state file.
+    let state_path = saved.state_path;
+    if let Some(path) = state_path.clone().filter(|_| saved.handed_over.is_empty()) {
+        let server = server.clone();
+        tasks.spawn_local(async move {
+            if let Err(e) = state::restore(&server, &path).await {
+                tracing::warn!("{}: {e}", path.display());
+            }
+        });
+    }
+
+    let quit = server.clone();
+    let quit_display = display.clone();
+    tasks.spawn_local(async move {
+        quit.quit_requested().await;
+
+        if let Some(path) = socket_path {
+            if let Err(e) = std::fs::remove_file(&path) {
+                tracing::warn!("{}: {e}", path.display());
+            }
+        }
+
+        if let Some(path) = state_path {
+            if let Err(e) = state::save(&quit, &path).await {
+                tracing::warn!("{}: {e}", path.display());
+            }
+        }
+
+        quit_display.shutdown().await;
+    });
+    tasks.spawn_local(handle_signals(server.clone()));
+
+    let handler = display.clone();
+    tasks.spawn_local(async move {
+        while let Some(command) = commands.recv().await {
+            match command {
+                x11::Command::Switch(nth) => {
+                    if let Err(e) = server.switch(nth, &Default::default()).await {
+                        tracing::warn!("{e}");
+                    }
+                }
+
+                x11::Command::HandOver => handler.hand_over(&server.history()).await,
+
+                x11::Command::Text(text) => match socket::Request::parse(&text) {
+                    Ok(request) => handle_request(&server, request, &text).await,
+                    Err(e) => tracing::warn!("{e}"),
+                },
+
+                x11::Command::Goto(name) => {
+                    let text = format!("goto {name}");
+                    handle_request(&server, socket::Request::Goto { name }, &text).await;
+                }
+
+                x11::Command::Mark(name) => {
+                    let text = format!("mark {name}");
+                    handle_request(&server, socket::Request::Mark { name }, &text).await;
+                }
+            }
+        }
+    });
+
+    systemd::ready();
+    hooks::lifecycle(hooks::Event::Startup, &[]);
 
     display.main_loop().await
 }
 
-async fn run_switch(display: x11::DisplayServer) -> Result<(), xcb::Error> {
-    let root = display.roots()[0];
+/// Run the server with the sway IPC, in a Wayland session.
+#[cfg(feature = "sway")]
+async fn run_sway(
+    sway: i3::sway::Sway,
+    cli_options: Rc<ServerOptions>,
+    options: &ServerOptions,
+    settings: Settings,
+) -> Result<(), Error> {
+    check_sway_options(options)?;
+
+    if options.replace {
+        quit_previous_server().await;
+    }
+
+    let listener = socket::bind(options.replace).context("Socket")?;
+
+    let server = Server::new(Rc::new(sway));
+    server.configure(settings);
+    server.set_reload(move || {
+        let (options, settings) = load_settings(&cli_options)?;
+        check_sway_options(&options)?;
+        Ok(settings)
+    });
+
+    task::spawn_local(socket::listen(listener, server.clone()));
+    task::spawn_local(handle_signals(server.clone()));
+
+    let state_path = state::path();
+    if let Some(path) = state_path.clone() {
+        let server = server.clone();
+        task::spawn_local(async move {
+            if let Err(e) = state::restore(&server, &path).await {
+                tracing::warn!("{}: {e}", path.display());
+            }
+        });
+    }
+
+    systemd::ready();
+    hooks::lifecycle(hooks::Event::Startup, &[]);
+
+    tokio::select! {
+        r = server.track_focus() => Ok(r?),
+        _ = server.quit_requested() => {
+            // The state is saved before the socket is removed, so a server
+            // that replaces this one can read it.
+            if let Some(path) = state_path {
+                if let Err(e) = state::save(&server, &path).await {
+                    tracing::warn!("{}: {e}", path.display());
+                }
+            }
+
+            // The socket from the service manager is kept.
+            if !systemd::socket_activated() {
+                let _ = std::fs::remove_file(socket::path());
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Fail if the options need features that are not available with sway.
+#[cfg(feature = "sway")]
+fn check_sway_options(options: &ServerOptions) -> Result<(), Error> {
+    if options.per_workspace {
+        return Err(Error::Config(
+            "per_workspace is not supported with sway.".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Ask the server running in the session to quit, and wait until its
+/// socket is removed. Used by sway, since there is no manager selection.
+#[cfg(feature = "sway")]
+async fn quit_previous_server() {
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    if socket::request_ok(&socket::Request::Quit).await.is_err() {
+        return;
+    }
+
+    let path = socket::path();
+    let deadline = Instant::now() + TIMEOUT;
+
+    while path.exists() {
+        if Instant::now() > deadline {
+            tracing::warn!("Previous server did not remove its socket.");
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Send a request from the display to the server, and log the error in
+/// the response. `text` describes the request in the log.
+async fn handle_request<B: DisplayBackend>(
+    server: &Server<B>,
+    request: socket::Request,
+    text: &str,
+) {
+    if let socket::Response::Error(e) = server.handle(request).await {
+        tracing::warn!("{text}: {e}");
+    }
+}
+
+/// Handle the signals received by the process:
+///
+/// * `SIGHUP` reloads the settings.
+/// * `SIGUSR1` switches to the previous window.
+/// * `SIGTERM` quits the server, like the `quit` command.
+async fn handle_signals<B: DisplayBackend>(server: Rc<Server<B>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let signals = signal(SignalKind::hangup()).and_then(|hangup| {
+        let user1 = signal(SignalKind::user_defined1())?;
+        let terminate = signal(SignalKind::terminate())?;
+        Ok((hangup, user1, terminate))
+    });
 
-    let event = x::ClientMessageEvent::new(
-        root,
-        display.atoms().switch_command,
-        x::ClientMessageData::Data32(Default::default()),
+    let (mut hangup, mut user1, mut terminate) = match signals {
+        Ok(signals) => signals,
+        Err(e) => {
+            tracing::warn!("Signals: {e}");
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            Some(()) = hangup.recv() => {
+                tracing::debug!("SIGHUP received");
+
+                if let Err(e) = server.reload() {
+                    tracing::error!("Reload: {e}");
+                }
+            }
+
+            Some(()) = user1.recv() => {
+                tracing::debug!("SIGUSR1 received");
+
+                if let Err(e) = server.switch(1, &Default::default()).await {
+                    tracing::warn!("{e}");
+                }
+            }
+
+            Some(()) = terminate.recv() => {
+                tracing::debug!("SIGTERM received");
+                server.quit();
+            }
+
+            else => return,
+        }
+    }
+}
+
+/// Apply the options of the server to the display.
+fn configure_display(
+    display: &x11::DisplayServer,
+    options: &ServerOptions,
+    settings: &Settings,
+) -> Result<(), Error> {
+    display.set_focus_output(options.focus_output);
+    display.set_change_desktop(options.change_desktop);
+    display.set_unminimize(options.unminimize);
+    display.set_raise(options.raise());
+    display.set_warp_pointer(options.warp_pointer);
+    display.set_activation_rules(settings.activation.clone());
+    display.set_focus_quiet_time(
+        settings
+            .focus_quiet_time
+            .unwrap_or(x11::DEFAULT_FOCUS_QUIET_TIME),
     );
+    display.set_max_pending(
+        settings
+            .max_pending_requests
+            .unwrap_or(x11::DEFAULT_MAX_PENDING),
+    );
+
+    // The raw events are selected once, in run_server.
+    if settings.ignore_hover && !display.listening_interactions() {
+        return Err(Error::Config("ignore_hover needs XInput 2.".into()));
+    }
 
-    let req = x::SendEvent {
-        propagate: false,
-        destination: x::SendEventDest::Window(root),
-        event_mask: x::EventMask::STRUCTURE_NOTIFY,
-        event: &event,
+    let modifiers = match &options.modifiers {
+        Some(modifiers) => modifiers.iter().fold(x::ModMask::empty(), |m, &n| m | n),
+        None => x11::DEFAULT_MODIFIER_MASK,
     };
 
-    Ok(display.connection().send_and_check_request(&req)?)
+    display.set_modifier_mask(modifiers);
+
+    Ok(())
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> ExitCode {
-    // Parse CLI arguments.
-    let mut args = std::env::args();
-    let program_name = args.next();
+/// Fail if another server is running on the display, unless it is
+/// replaced.
+async fn check_single_server(
+    display: &x11::DisplayServer,
+    options: &ServerOptions,
+) -> Result<(), Error> {
+    if display.manager_owner().await?.is_some() && !options.replace {
+        return Err(Error::AlreadyRunning);
+    }
+
+    Ok(())
+}
+
+/// Run `future` with the main loop, which is needed to receive the
+/// replies from the X server.
+async fn with_main_loop<T, F>(display: &x11::DisplayServer, future: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    tokio::select! {
+        r = display.main_loop() => {
+            r?;
+            Err(Error::Cancelled)
+        }
+
+        r = future => r,
+    }
+}
+
+async fn run_switch(options: cli::SwitchOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let request = if options.urgent {
+        socket::Request::Urgent
+    } else if options.stolen {
+        socket::Request::Stolen
+    } else {
+        socket::Request::Switch {
+            nth: options.nth as usize,
+            filter: options.filter.filter(),
+            dry_run: options.dry_run,
+        }
+    };
+
+    match socket::request(&request).await? {
+        socket::Response::Ok => Ok(()),
+        socket::Response::Window(entry) => {
+            print_entry(entry);
+            Ok(())
+        }
+        socket::Response::Error(e) => Err(e.into()),
+        _ => Err("Unexpected response from the server.".into()),
+    }
+}
+
+async fn run_cycle(
+    step: isize,
+    filter: socket::SwitchFilter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Cycle { step, filter }).await
+}
 
-    let command = match (args.next().as_deref(), args.next()) {
-        (Some("server"), None) => Command::Server,
-        (Some("switch"), None) => Command::Switch,
-        _ => {
-            eprintln!("Usage: {} server|switch", program_name.unwrap_or_default());
+async fn run_peek(filter: socket::SwitchFilter) -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Peek { filter }).await
+}
+
+async fn run_focus(id: u64) -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Activate { id }).await
+}
+
+async fn run_mark(name: String) -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Mark { name }).await
+}
+
+async fn run_goto(name: String) -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Goto { name }).await
+}
+
+async fn run_marks(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let marks = match socket::request(&socket::Request::Marks).await? {
+        socket::Response::Marks(marks) => marks,
+        socket::Response::Error(e) => return Err(e.into()),
+        _ => return Err("Unexpected response from the server.".into()),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&marks)?);
+        return Ok(());
+    }
+
+    for mark in marks {
+        print!("{}\t", mark.name);
+        print_entry(mark.window);
+    }
+
+    Ok(())
+}
+
+async fn run_watch(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut watch = socket::watch().await?;
+
+    loop {
+        let change = watch.next().await?;
+        let mut stdout = std::io::stdout().lock();
+
+        if json {
+            serde_json::to_writer(&mut stdout, &change)?;
+            writeln!(stdout)?;
+        } else {
+            let info = change.window.info;
+            writeln!(
+                stdout,
+                "{}.{:03}\t0x{:08x}\t{}\t{}",
+                change.timestamp / 1000,
+                change.timestamp % 1000,
+                change.window.id,
+                info.class.unwrap_or_default(),
+                info.title.unwrap_or_default(),
+            )?;
+        }
+
+        // Flush every line, so the output can be used in pipes.
+        stdout.flush()?;
+    }
+}
+
+async fn run_ping() -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Ping).await
+}
+
+async fn run_pause(paused: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let request = match paused {
+        true => socket::Request::Pause,
+        false => socket::Request::Resume,
+    };
+
+    socket::request_ok(&request).await
+}
+
+async fn run_reload() -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Reload).await
+}
+
+async fn run_quit() -> Result<(), Box<dyn std::error::Error>> {
+    socket::request_ok(&socket::Request::Quit).await
+}
+
+async fn run_status(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let status = match socket::request(&socket::Request::Status).await? {
+        socket::Response::Status(status) => status,
+        socket::Response::Error(e) => return Err(e.into()),
+        _ => return Err("Unexpected response from the server.".into()),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&status)?);
+        return Ok(());
+    }
+
+    let window = |w: Option<u64>| match w {
+        Some(w) => format!("0x{:08x}", w),
+        None => "-".to_string(),
+    };
+
+    println!("uptime: {}s", status.uptime);
+    println!("current: {}", window(status.current));
+    println!("last: {}", window(status.last));
+    println!("history length: {}", status.history_length);
+
+    if let Some(screens) = status.screens {
+        println!("screens: {}", screens);
+    }
+
+    println!("waiting modifiers: {}", status.waiting_modifiers);
+    println!("paused: {}", status.paused);
+
+    Ok(())
+}
+
+async fn run_stats(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let metrics = match socket::request(&socket::Request::Stats).await? {
+        socket::Response::Stats(metrics) => metrics,
+        socket::Response::Error(e) => return Err(e.into()),
+        _ => return Err("Unexpected response from the server.".into()),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&metrics)?);
+        return Ok(());
+    }
+
+    let timing = |name: &str, t: Timing| {
+        println!(
+            "{name}: {} (average {:.2}ms, max {:.2}ms)",
+            t.count,
+            t.average_us() as f64 / 1000.0,
+            t.max_us as f64 / 1000.0,
+        );
+    };
+
+    timing("activations", metrics.activations);
+
+    if let Some(display) = metrics.display {
+        timing("main loop iterations", display.main_loop);
+        timing("requests", display.requests);
+        println!(
+            "pending requests: {} (max {})",
+            display.pending_requests, display.max_pending_requests
+        );
+        println!("events: {}", display.events);
+    }
+
+    Ok(())
+}
+
+async fn run_dump_state() -> Result<(), Box<dyn std::error::Error>> {
+    match socket::request(&socket::Request::DumpState).await? {
+        socket::Response::State(state) => {
+            println!("{}", serde_json::to_string_pretty(&state)?);
+            Ok(())
+        }
+
+        socket::Response::Error(e) => Err(e.into()),
+        _ => Err("Unexpected response from the server.".into()),
+    }
+}
+
+async fn run_history(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = socket::request_history().await?;
+
+    if json {
+        println!("{}", serde_json::to_string(&entries)?);
+        return Ok(());
+    }
+
+    for entry in entries {
+        print_entry(entry);
+    }
+
+    Ok(())
+}
+
+/// Send the history in stdin to the server.
+async fn run_import() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = String::new();
+    tokio::io::stdin().read_to_string(&mut input).await?;
+
+    let entries: Vec<socket::HistoryEntry> = serde_json::from_str(&input)?;
+    let ids: Vec<u64> = entries.iter().map(|e| e.id).collect();
+
+    let history = match socket::request(&socket::Request::Import { entries }).await? {
+        socket::Response::History(history) => history,
+        socket::Response::Error(e) => return Err(e.into()),
+        _ => return Err("Unexpected response from the server.".into()),
+    };
+
+    let missing = ids
+        .iter()
+        .filter(|&&id| !history.iter().any(|e| e.id == id))
+        .count();
+
+    if missing > 0 {
+        eprintln!("{missing} windows are not available.");
+    }
+
+    Ok(())
+}
+
+/// Print a line with the id, desktop, class, and title of a window.
+fn print_entry(entry: socket::HistoryEntry) {
+    let info = entry.info;
+
+    let desktop = match info.desktop {
+        Some(d) => d.to_string(),
+        None => "-".to_string(),
+    };
+
+    println!(
+        "0x{:08x}\t{}\t{}\t{}",
+        entry.id,
+        desktop,
+        info.class.unwrap_or_default(),
+        info.title.unwrap_or_default(),
+    );
+}
+
+fn main() -> ExitCode {
+    let cli = cli::Cli::parse();
+
+    // The display is read from the environment by the connection to the
+    // X server and by the socket path, and it is inherited by the hooks
+    // and the plugins.
+    if let Some(display) = &cli.display {
+        std::env::set_var("DISPLAY", display);
+    }
+
+    let cli_options = match cli.command {
+        Command::Server(options) => Rc::new(options),
+        Command::Switch(switch) => return exit_code(block_on(run_switch(switch))),
+        Command::CycleNext(filter) => return exit_code(block_on(run_cycle(1, filter.filter()))),
+        Command::CyclePrev(filter) => return exit_code(block_on(run_cycle(-1, filter.filter()))),
+        Command::Peek(filter) => return exit_code(block_on(run_peek(filter.filter()))),
+        Command::Focus { id } => return exit_code(block_on(run_focus(id))),
+        Command::Mark { name } => return exit_code(block_on(run_mark(name))),
+        Command::Goto { name } => return exit_code(block_on(run_goto(name))),
+        Command::Marks { json } => return exit_code(block_on(run_marks(json))),
+        Command::History { json, action } => {
+            let result = match action {
+                None => block_on(run_history(json)),
+                Some(HistoryAction::Export) => block_on(run_history(true)),
+                Some(HistoryAction::Import) => block_on(run_import()),
+            };
+
+            return exit_code(result);
+        }
+        Command::Status { json } => return exit_code(block_on(run_status(json))),
+        Command::DumpState => return exit_code(block_on(run_dump_state())),
+        Command::Stats { json } => return exit_code(block_on(run_stats(json))),
+        Command::Pause => return exit_code(block_on(run_pause(true))),
+        Command::Resume => return exit_code(block_on(run_pause(false))),
+        Command::Reload => return exit_code(block_on(run_reload())),
+        Command::Quit => return exit_code(block_on(run_quit())),
+        Command::Ping => return exit_code(block_on(run_ping())),
+        Command::Prop { window, action } => return exit_code(block_on(prop::run(window, action))),
+        Command::Doctor => return exit_code(block_on(doctor::run())),
+        Command::Watch { json } => return exit_code(block_on(run_watch(json))),
+        Command::Bar { format } => return exit_code(block_on(bar::run(&format))),
+        Command::Menu { cmd, format } => return exit_code(block_on(menu::run(&cmd, &format))),
+        Command::Completions { shell } => return exit_code(cli::completions(shell)),
+    };
+
+    if let Err(e) = logging::init(cli_options.log_level.as_deref(), &cli_options.log_target) {
+        eprintln!("{}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let (options, settings) = match load_settings(&cli_options) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::error!("{}", e);
             return ExitCode::FAILURE;
         }
     };
 
-    // Connect to X11.
-    let conn = match x11::DisplayServer::new() {
-        Ok(c) => c,
+    // The process has to be forked before the runtime is created. The PID
+    // file is written after checking that no other server is running.
+    let pidfile = match options.pidfile.as_deref().map(std::path::absolute) {
+        Some(Ok(path)) => Some(path),
+        Some(Err(e)) => {
+            tracing::error!("Can't write PID file: {}", e);
+            return ExitCode::FAILURE;
+        }
+        None => None,
+    };
+
+    let mut daemon = match options.daemon.then(daemon::daemonize).transpose() {
+        Ok(d) => d,
         Err(e) => {
-            eprintln!("Can't connect to X11: {}", e);
+            tracing::error!("Can't run in the background: {}", e);
             return ExitCode::FAILURE;
         }
     };
 
-    // Execute command from arguments.
-    let local = task::LocalSet::new();
+    // Write the PID file, and detach from the terminal.
+    let mut started = move || -> Result<Option<daemon::PidFile>, Error> {
+        let pidfile = pidfile
+            .as_deref()
+            .map(daemon::PidFile::create)
+            .transpose()
+            .context("Can't write PID file")?;
 
-    let task = async move {
-        match command {
-            Command::Server => run_server(conn).await,
-            Command::Switch => run_switch(conn).await,
+        if let Some(daemon) = &mut daemon {
+            daemon.ready().context("Daemon")?;
         }
+
+        Ok(pidfile)
     };
 
-    if let Err(e) = local.run_until(task).await {
+    // Use the sway IPC in Wayland sessions, unless an X display is
+    // given.
+    #[cfg(feature = "sway")]
+    if let Some(sway) = i3::sway::Sway::from_env().filter(|_| cli.display.is_none()) {
+        let result = block_on(with_lifecycle_hooks(async {
+            let _pidfile = started()?;
+            run_sway(sway, cli_options.clone(), &options, settings).await
+        }));
+        systemd::stopping();
+        return server_exit_code(result);
+    }
+
+    let result = block_on(with_lifecycle_hooks(async {
+        let displays = connect_displays(&options.displays)?;
+        for (_, display) in &displays {
+            with_main_loop(display, check_single_server(display, &options)).await?;
+        }
+
+        let _pidfile = started()?;
+        run_displays(displays, cli_options, options, settings).await
+    }));
+    systemd::stopping();
+    server_exit_code(result)
+}
+
+/// Read the configuration file, and merge it with the options in the
+/// command line, which take precedence. Return the merged options, and the
+/// settings for the server.
+fn load_settings(cli_options: &ServerOptions) -> Result<(ServerOptions, Settings), Error> {
+    let config = config::Config::load(cli_options.config.as_deref())?;
+    let mut settings = Settings::new(&config)?;
+    let mut options = cli_options.clone();
+
+    settings.overlay |= options.overlay;
+    settings.ignore_hover |= options.ignore_hover;
+    settings.no_desktop_switch |= options.no_desktop_switch;
+    options.warp_pointer |= config.warp_pointer;
+
+    if options.flash.is_some() {
+        settings.flash = options.flash.clone();
+    }
+
+    if let (None, Some(names)) = (&options.modifiers, &config.modifiers) {
+        let modifiers: Result<_, _> = names.iter().map(|n| x11::parse_modifier(n)).collect();
+        options.modifiers = Some(modifiers.map_err(Error::Config)?);
+    }
+
+    if let (None, Some(binding)) = (&options.bind, &config.bind) {
+        let binding = x11::parse_key_binding(binding).map_err(Error::Config)?;
+        options.bind = Some(binding);
+    }
+
+    if let (None, Some(binding)) = (&options.goto_chord, &config.goto_chord) {
+        let binding = x11::parse_key_binding(binding).map_err(Error::Config)?;
+        options.goto_chord = Some(binding);
+    }
+
+    if let (None, Some(binding)) = (&options.mark_chord, &config.mark_chord) {
+        let binding = x11::parse_key_binding(binding).map_err(Error::Config)?;
+        options.mark_chord = Some(binding);
+    }
+
+    if options.displays.is_empty() {
+        options.displays = config.displays;
+    }
+
+    Ok((options, settings))
+}
+
+/// Execute the hooks for the `shutdown` and `error` events when `server`
+/// is finished.
+async fn with_lifecycle_hooks<F>(server: F) -> Result<(), Error>
+where
+    F: Future<Output = Result<(), Error>>,
+{
+    let result = server.await;
+
+    if let Err(e) = &result {
+        hooks::lifecycle(hooks::Event::Error, &[&e.to_string()]);
+    }
+
+    hooks::lifecycle(hooks::Event::Shutdown, &[]);
+
+    result
+}
+
+/// Run `future` in a single-threaded runtime. Tasks are spawned in a
+/// [`task::LocalSet`].
+fn block_on<F, E>(future: F) -> Result<(), E>
+where
+    F: Future<Output = Result<(), E>>,
+    E: From<io::Error>,
+{
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let result = task::LocalSet::new().block_on(&runtime, future);
+
+    // Don't wait for blocking tasks, like reading from stdin.
+    runtime.shutdown_background();
+
+    result
+}
+
+/// Like [`exit_code`], but the error is written in the log of the server.
+fn server_exit_code(result: Result<(), Error>) -> ExitCode {
+    if let Err(e) = result {
+        tracing::error!("{}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn exit_code(result: Result<(), impl std::fmt::Display>) -> ExitCode {
+    if let Err(e) = result {
         eprintln!("{}", e);
         return ExitCode::FAILURE;
     }
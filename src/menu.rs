@@ -0,0 +1,56 @@
+//! Pick a window from the history with an external menu, like `rofi -dmenu`
+//! or `dmenu`.
+//!
+//! The menu receives a line for each window in stdin, and it prints the
+//! selected line in stdout.
+
+use std::{error::Error, process::Stdio};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use x11_alternate_focus::{socket, template};
+
+/// Command used if `--cmd` is not given.
+pub const DEFAULT_COMMAND: &str = "rofi -dmenu -i -p window";
+
+/// Format used if `--format` is not given.
+pub const DEFAULT_FORMAT: &str = "{class} — {title}";
+
+pub async fn run(command: &str, format: &str) -> Result<(), Box<dyn Error>> {
+    let entries = socket::request_history().await?;
+
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|entry| template::render(format, entry).replace('\n', " "))
+        .collect();
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let mut input = lines.join("\n");
+        input.push('\n');
+        stdin.write_all(input.as_bytes()).await?;
+    }
+
+    // The menu exits with an error if it is cancelled.
+    let output = child.wait_with_output().await?;
+    let selection = String::from_utf8_lossy(&output.stdout);
+    let selection = selection.trim_end_matches('\n');
+
+    if selection.is_empty() {
+        return Ok(());
+    }
+
+    let entry = lines
+        .iter()
+        .position(|line| line == selection)
+        .map(|index| &entries[index])
+        .ok_or("The selected window is not in the history.")?;
+
+    socket::request_ok(&socket::Request::Activate { id: entry.id }).await
+}
@@ -0,0 +1,89 @@
+//! External programs that observe the focus changes and send requests to
+//! the server.
+//!
+//! Each plugin is executed with `sh -c`. The server writes one JSON object
+//! per line to its stdin:
+//!
+//! * `{"focus": {...}}` when a window is accepted in the history, with the
+//!   same fields of the `watch` command.
+//! * `{"response": ...}` with the reply to a request from the plugin.
+//!
+//! The plugin writes requests to its stdout, one per line, with the same
+//! format of the messages in the socket (see [`crate::socket::Request`]).
+
+use std::{io, process::Stdio};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{ChildStdin, Command},
+    sync::broadcast::error::RecvError,
+};
+
+use crate::{
+    backend::DisplayBackend,
+    server::Server,
+    socket::{FocusChange, Request, Response},
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Message {
+    Focus(FocusChange),
+    Response(Response),
+}
+
+/// Execute `command`, and exchange messages with it until it exits.
+pub async fn run<B: DisplayBackend>(server: &Server<B>, command: &str) {
+    if let Err(e) = exchange(server, command).await {
+        tracing::warn!("Plugin {command:?}: {e}");
+    }
+}
+
+async fn exchange<B: DisplayBackend>(server: &Server<B>, command: &str) -> io::Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let (Some(mut stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        return Ok(());
+    };
+
+    let mut requests = BufReader::new(stdout).lines();
+    let mut changes = server.subscribe();
+
+    loop {
+        let message = tokio::select! {
+            change = changes.recv() => match change {
+                Ok((window, time)) => Message::Focus(server.focus_change(window, time).await),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return Ok(()),
+            },
+
+            line = requests.next_line() => match line? {
+                Some(line) => Message::Response(handle(server, &line).await),
+                None => return Err(io::Error::other("stdout closed")),
+            },
+        };
+
+        send(&mut stdin, &message).await?;
+    }
+}
+
+async fn handle<B: DisplayBackend>(server: &Server<B>, line: &str) -> Response {
+    match serde_json::from_str(line) {
+        Ok(Request::Watch) => Response::Error("Focus changes are always sent to plugins.".into()),
+        Ok(request) => server.handle(request).await,
+        Err(e) => Response::Error(format!("Invalid request: {e}")),
+    }
+}
+
+async fn send(stdin: &mut ChildStdin, message: &Message) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stdin.write_all(&line).await
+}
@@ -0,0 +1,121 @@
+//! Decide how a focused window is recorded in the history.
+//!
+//! With the `lua` feature, the decision can be made by a script. The script
+//! must define a global function `on_focus`, which receives a table with the
+//! fields `id`, `class`, `title`, `desktop`, and `type`. It can return:
+//!
+//! * `nil` or `true`, to record the window as usual.
+//! * `false`, to ignore the window.
+//! * A table with any of these fields:
+//!     * `record`: `false` to ignore the window.
+//!     * `history`: number of the history for the window, instead of the
+//!       current workspace. Only used with `--per-workspace`.
+//!     * `wait_modifiers`: `false` to accept the window immediately, even if
+//!       some keyboard modifiers are pressed.
+
+use std::path::Path;
+
+use crate::backend::WindowInfo;
+
+/// How a focused window is recorded.
+#[derive(Debug)]
+pub struct Decision {
+    /// If `false`, the window is not added to the history.
+    pub record: bool,
+
+    /// History for the window, instead of the current workspace.
+    pub history: Option<u32>,
+
+    /// If `false`, the window is accepted even if modifiers are pressed.
+    pub wait_modifiers: bool,
+}
+
+impl Default for Decision {
+    fn default() -> Self {
+        Decision {
+            record: true,
+            history: None,
+            wait_modifiers: true,
+        }
+    }
+}
+
+/// Lua script with the `on_focus` function.
+#[cfg(feature = "lua")]
+pub struct Script {
+    lua: mlua::Lua,
+}
+
+#[cfg(feature = "lua")]
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {e}", path.display());
+
+        let source = std::fs::read(path).map_err(|e| error(&e))?;
+
+        let lua = mlua::Lua::new();
+
+        // With '@', the name is used as a file name in the error messages.
+        lua.load(source)
+            .set_name(format!("@{}", path.display()))
+            .exec()
+            .map_err(|e| e.to_string())?;
+
+        lua.globals()
+            .get::<_, mlua::Function>("on_focus")
+            .map_err(|_| error(&"The function on_focus is not defined."))?;
+
+        Ok(Script { lua })
+    }
+
+    /// Call `on_focus` with the metadata of the window.
+    pub fn decide(&self, id: u64, info: &WindowInfo) -> Result<Decision, mlua::Error> {
+        let window = self.lua.create_table()?;
+        window.set("id", id)?;
+        window.set("class", info.class.as_deref())?;
+        window.set("title", info.title.as_deref())?;
+        window.set("desktop", info.desktop)?;
+        window.set("type", info.window_type.as_deref())?;
+
+        let on_focus: mlua::Function = self.lua.globals().get("on_focus")?;
+
+        let decision = match on_focus.call(window)? {
+            mlua::Value::Nil => Decision::default(),
+
+            mlua::Value::Boolean(record) => Decision {
+                record,
+                ..Decision::default()
+            },
+
+            mlua::Value::Table(t) => Decision {
+                record: t.get::<_, Option<bool>>("record")?.unwrap_or(true),
+                history: t.get("history")?,
+                wait_modifiers: t.get::<_, Option<bool>>("wait_modifiers")?.unwrap_or(true),
+            },
+
+            value => {
+                return Err(mlua::Error::runtime(format!(
+                    "Invalid value from on_focus: {}",
+                    value.type_name()
+                )))
+            }
+        };
+
+        Ok(decision)
+    }
+}
+
+/// Scripts are not available without the `lua` feature.
+#[cfg(not(feature = "lua"))]
+pub enum Script {}
+
+#[cfg(not(feature = "lua"))]
+impl Script {
+    pub fn load(_: &Path) -> Result<Script, String> {
+        Err("Lua scripts are not supported in this build.".into())
+    }
+
+    pub fn decide(&self, _: u64, _: &WindowInfo) -> Result<Decision, String> {
+        match *self {}
+    }
+}
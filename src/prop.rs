@@ -0,0 +1,200 @@
+//! Read and change the properties of the windows, like `xprop`, to debug
+//! the behavior of the window manager.
+
+use std::error::Error;
+
+use xcb::{x, Xid};
+
+use x11_alternate_focus::{
+    backend::DisplayBackend,
+    socket,
+    x11::{property, DisplayServer},
+};
+
+use crate::cli::{PropAction, PropType};
+
+pub async fn run(window: Option<u64>, action: PropAction) -> Result<(), Box<dyn Error>> {
+    let display = DisplayServer::new()?;
+
+    let window = match window {
+        Some(id) => DisplayServer::window_from_id(id).ok_or("Invalid window id.")?,
+        None => display.roots()[0],
+    };
+
+    // The main loop is needed to receive the replies.
+    tokio::select! {
+        r = display.main_loop() => {
+            r?;
+            Err("The connection was closed.".into())
+        }
+
+        r = execute(&display, window, action) => r,
+    }
+}
+
+async fn execute(
+    display: &DisplayServer,
+    window: x::Window,
+    action: PropAction,
+) -> Result<(), Box<dyn Error>> {
+    match action {
+        PropAction::Get { name } => get(display, window, &name).await,
+
+        PropAction::Set { name, kind, values } => {
+            let prop = intern(display, &name).await?;
+            set(display, window, prop, kind, &values).await?;
+            Ok(())
+        }
+
+        PropAction::Del { name } => {
+            let prop = lookup(display, &name).await?;
+            property::delete(display, window, prop).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Print the value of a property, with the syntax of `xprop`.
+async fn get(display: &DisplayServer, window: x::Window, name: &str) -> Result<(), Box<dyn Error>> {
+    let prop = lookup(display, name).await?;
+
+    // Read the type, without the value.
+    let req = x::GetProperty {
+        delete: false,
+        window,
+        property: prop,
+        r#type: x::ATOM_ANY,
+        long_offset: 0,
+        long_length: 0,
+    };
+
+    let r#type = display.send_request(&req).await?.r#type();
+    if r#type == x::ATOM_NONE {
+        return Err(format!("{name}: not found.").into());
+    }
+
+    let value = match r#type {
+        x::ATOM_STRING => property::get_string(display, window, prop)
+            .await?
+            .map(|s| format!("{s:?}")),
+
+        t if t == display.atoms().utf8_string => property::get_utf8(display, window, prop)
+            .await?
+            .map(|s| format!("{s:?}")),
+
+        x::ATOM_CARDINAL => property::get_cardinals(display, window, prop)
+            .await?
+            .map(|items| join(items.iter().map(u32::to_string))),
+
+        x::ATOM_WINDOW => property::get_windows(display, window, prop)
+            .await?
+            .map(|items| join(items.iter().map(|w| format!("0x{:x}", w.resource_id())))),
+
+        x::ATOM_ATOM => match property::get_atoms(display, window, prop).await? {
+            Some(atoms) => {
+                let mut names = Vec::with_capacity(atoms.len());
+                for atom in atoms {
+                    names.push(atom_name(display, atom).await?);
+                }
+
+                Some(names.join(", "))
+            }
+
+            None => None,
+        },
+
+        t => {
+            let type_name = atom_name(display, t).await?;
+            return Err(format!("{name}: unsupported type {type_name}.").into());
+        }
+    };
+
+    let type_name = atom_name(display, r#type).await?;
+    println!("{name}({type_name}) = {}", value.unwrap_or_default());
+
+    Ok(())
+}
+
+/// Replace the value of a property. Lists have an item for each value, and
+/// the values of text properties are joined with spaces.
+async fn set(
+    display: &DisplayServer,
+    window: x::Window,
+    prop: x::Atom,
+    kind: PropType,
+    values: &[String],
+) -> Result<(), Box<dyn Error>> {
+    match kind {
+        PropType::Utf8 => property::set_utf8(display, window, prop, &values.join(" ")).await?,
+
+        PropType::String => property::set_string(display, window, prop, &values.join(" ")).await?,
+
+        PropType::Cardinal => {
+            let items = values
+                .iter()
+                .map(|v| v.parse())
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|e| format!("Invalid number: {e}"))?;
+
+            property::set_cardinals(display, window, prop, &items).await?
+        }
+
+        PropType::Window => {
+            let items = values
+                .iter()
+                .map(|v| {
+                    socket::parse_window_id(v)
+                        .ok()
+                        .and_then(DisplayServer::window_from_id)
+                        .ok_or_else(|| format!("Invalid window id: {v}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            property::set_windows(display, window, prop, &items).await?
+        }
+
+        PropType::Atom => {
+            let mut items = Vec::with_capacity(values.len());
+            for value in values {
+                items.push(intern(display, value).await?);
+            }
+
+            property::set_atoms(display, window, prop, &items).await?
+        }
+    }
+
+    Ok(())
+}
+
+/// Return the atom for `name`, and create it if it does not exist.
+async fn intern(display: &DisplayServer, name: &str) -> Result<x::Atom, Box<dyn Error>> {
+    let req = x::InternAtom {
+        only_if_exists: false,
+        name: name.as_bytes(),
+    };
+
+    Ok(display.send_request(&req).await?.atom())
+}
+
+/// Return the atom for `name`, without creating it. A missing atom means
+/// that no window can have that property.
+async fn lookup(display: &DisplayServer, name: &str) -> Result<x::Atom, Box<dyn Error>> {
+    let req = x::InternAtom {
+        only_if_exists: true,
+        name: name.as_bytes(),
+    };
+
+    match display.send_request(&req).await?.atom() {
+        x::ATOM_NONE => Err(format!("{name}: not found.").into()),
+        atom => Ok(atom),
+    }
+}
+
+async fn atom_name(display: &DisplayServer, atom: x::Atom) -> Result<String, Box<dyn Error>> {
+    let reply = display.send_request(&x::GetAtomName { atom }).await?;
+    Ok(reply.name().to_string())
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,213 @@
+//! Rules to exclude windows from the history, and to change how they are
+//! activated.
+//!
+//! Each rule is a regular expression. It is tested against the class and
+//! the title of the window, unless it has a `class=` or `title=` prefix.
+//! With the `type=` prefix, it is tested against the window type, like
+//! `dialog` or `splash`.
+
+use regex::Regex;
+
+use crate::{backend::WindowInfo, config::ActivationRule, x11::Raise};
+
+#[derive(Clone, Default)]
+pub struct Rules(Vec<Rule>);
+
+#[derive(Clone)]
+struct Rule {
+    field: Field,
+    regex: Regex,
+}
+
+#[derive(Clone)]
+enum Field {
+    Any,
+    Class,
+    Title,
+    Type,
+}
+
+impl Rules {
+    pub fn new(rules: &[String]) -> Result<Rules, String> {
+        let rules = rules
+            .iter()
+            .map(|rule| Rule::new(rule))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Rules(rules))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Return `true` if any rule matches the window.
+    pub fn excludes(&self, info: &WindowInfo) -> bool {
+        self.0.iter().any(|rule| rule.matches(info))
+    }
+}
+
+impl Rule {
+    fn new(rule: &str) -> Result<Rule, String> {
+        let (field, pattern) = if let Some(p) = rule.strip_prefix("class=") {
+            (Field::Class, p)
+        } else if let Some(p) = rule.strip_prefix("title=") {
+            (Field::Title, p)
+        } else if let Some(p) = rule.strip_prefix("type=") {
+            (Field::Type, p)
+        } else {
+            (Field::Any, rule)
+        };
+
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(Rule { field, regex }),
+            Err(e) => Err(format!("Invalid rule {rule:?}: {e}")),
+        }
+    }
+
+    fn matches(&self, info: &WindowInfo) -> bool {
+        let matches =
+            |value: &Option<String>| value.as_deref().is_some_and(|v| self.regex.is_match(v));
+
+        match self.field {
+            Field::Any => matches(&info.class) || matches(&info.title),
+            Field::Class => matches(&info.class),
+            Field::Title => matches(&info.title),
+            Field::Type => matches(&info.window_type),
+        }
+    }
+}
+
+/// Rules to change the activation of some windows.
+#[derive(Clone, Default)]
+pub struct ActivationRules(Vec<(Rule, ActivationOverrides)>);
+
+/// Options of the activation changed by a rule. `None` keeps the option
+/// of the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ActivationOverrides {
+    /// Move the pointer to the monitor of the window before activating it.
+    pub focus_output: Option<bool>,
+
+    /// Change the stacking order after activating the window.
+    pub raise: Option<Raise>,
+
+    /// Move the pointer to the center of the window after activating it.
+    pub warp_pointer: Option<bool>,
+
+    /// Switch to the desktop of the window before activating it.
+    pub change_desktop: Option<bool>,
+
+    /// Restore the window if it is minimized.
+    pub unminimize: Option<bool>,
+}
+
+impl ActivationRules {
+    pub fn new(rules: &[ActivationRule]) -> Result<ActivationRules, String> {
+        let rules = rules
+            .iter()
+            .map(|r| {
+                let overrides = ActivationOverrides {
+                    focus_output: r.focus_output,
+                    raise: r.raise,
+                    warp_pointer: r.warp_pointer,
+                    change_desktop: r.change_desktop,
+                    unminimize: r.unminimize,
+                };
+
+                Ok((Rule::new(&r.rule)?, overrides))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(ActivationRules(rules))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Return the options for the window. If multiple rules match it, the
+    /// first one that sets an option wins.
+    pub fn overrides(&self, info: &WindowInfo) -> ActivationOverrides {
+        let mut result = ActivationOverrides::default();
+
+        for (_, o) in self.0.iter().filter(|(rule, _)| rule.matches(info)) {
+            result.focus_output = result.focus_output.or(o.focus_output);
+            result.raise = result.raise.or(o.raise);
+            result.warp_pointer = result.warp_pointer.or(o.warp_pointer);
+            result.change_desktop = result.change_desktop.or(o.change_desktop);
+            result.unminimize = result.unminimize.or(o.unminimize);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn info(class: &str) -> WindowInfo {
+        WindowInfo {
+            class: Some(class.into()),
+            ..WindowInfo::default()
+        }
+    }
+
+    #[test]
+    fn activation_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+            [[activation]]
+            match = "class=^zoom$"
+            raise = "always"
+
+            [[activation]]
+            match = "class=^(zoom|mpv)$"
+            raise = "never"
+            warp_pointer = false
+            "#,
+        )
+        .unwrap();
+
+        let rules = ActivationRules::new(&config.activation).unwrap();
+
+        assert_eq!(
+            rules.overrides(&info("zoom")),
+            ActivationOverrides {
+                raise: Some(Raise::Always),
+                warp_pointer: Some(false),
+                ..ActivationOverrides::default()
+            }
+        );
+
+        assert_eq!(rules.overrides(&info("mpv")).raise, Some(Raise::Never));
+        assert_eq!(
+            rules.overrides(&info("xterm")),
+            ActivationOverrides::default()
+        );
+    }
+
+    #[test]
+    fn unminimize_override() {
+        let config: Config = toml::from_str(
+            r#"
+            [[activation]]
+            match = "class=^mpv$"
+            unminimize = false
+
+            [[activation]]
+            match = "class=^(mpv|zoom)$"
+            unminimize = true
+            "#,
+        )
+        .unwrap();
+
+        let rules = ActivationRules::new(&config.activation).unwrap();
+
+        assert_eq!(rules.overrides(&info("mpv")).unminimize, Some(false));
+        assert_eq!(rules.overrides(&info("zoom")).unminimize, Some(true));
+        assert_eq!(rules.overrides(&info("xterm")).unminimize, None);
+    }
+}
@@ -0,0 +1,1005 @@
+//! Server logic, independent of the display backend.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    error::Error,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+use tokio::{
+    sync::{broadcast, Notify},
+    task::JoinSet,
+};
+
+use tracing::Instrument;
+
+use crate::{
+    backend::{CycleKey, DisplayBackend, Timing},
+    config::{Config, Eviction, Transient},
+    history::{CAPACITY_RANGE, DEFAULT_CAPACITY},
+    hooks, plugins,
+    policy::Script,
+    rules::{ActivationRules, Rules},
+    socket::{
+        FocusChange, HistoryEntry, Mark, Metrics, Request, Response, State, StateEntry, Status,
+        SwitchFilter, PROTOCOL_VERSION,
+    },
+    tracker::FocusTracker,
+};
+
+/// Settings of the server, from the configuration file.
+#[derive(Clone, Default)]
+pub struct Settings {
+    /// Windows excluded from the history.
+    pub rules: Rules,
+
+    /// Commands executed when the focused window changes.
+    pub on_focus: Rc<[String]>,
+
+    /// Script to decide how windows are recorded.
+    pub script: Option<Rc<Script>>,
+
+    /// Commands to execute the plugins.
+    pub plugins: Rc<[String]>,
+
+    /// Maximum time to wait until the modifiers are released.
+    pub accept_timeout: Option<Duration>,
+
+    /// Minimum time that a window must be focused to be accepted.
+    pub min_dwell: Option<Duration>,
+
+    /// Maximum time of a A→B→A sequence to ignore B.
+    pub flicker_interval: Option<Duration>,
+
+    /// Time without focus changes before the focused window is read.
+    pub focus_quiet_time: Option<Duration>,
+
+    /// Show the windows in an overlay while they are cycled.
+    pub overlay: bool,
+
+    /// Ignore windows focused without a key or a button press.
+    pub ignore_hover: bool,
+
+    /// Only switch to windows in the current desktop.
+    pub no_desktop_switch: bool,
+
+    /// Color of the border drawn around the activated windows.
+    pub flash: Option<String>,
+
+    /// How transient windows are recorded.
+    pub transient: Transient,
+
+    /// Maximum number of windows in the history. The default is
+    /// [`DEFAULT_CAPACITY`].
+    pub history_size: Option<usize>,
+
+    /// Window removed when the history is full.
+    pub eviction: Eviction,
+
+    /// Maximum number of requests waiting for a reply from the display
+    /// server.
+    pub max_pending_requests: Option<usize>,
+
+    /// Activation options for some windows.
+    pub activation: ActivationRules,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Result<Settings, crate::Error> {
+        // Skipped types are exact matches of the window type.
+        let skip_types = config
+            .skip_types
+            .iter()
+            .map(|t| format!("type=^{}$", regex::escape(t)));
+
+        let exclude: Vec<String> = config.exclude.iter().cloned().chain(skip_types).collect();
+
+        if let Some(size) = config.history_size {
+            if !CAPACITY_RANGE.contains(&size) {
+                return Err(crate::Error::Config(format!(
+                    "history_size must be between {} and {}.",
+                    CAPACITY_RANGE.start(),
+                    CAPACITY_RANGE.end(),
+                )));
+            }
+        }
+
+        if config.max_pending_requests == Some(0) {
+            return Err(crate::Error::Config(
+                "max_pending_requests must be greater than 0.".into(),
+            ));
+        }
+
+        Ok(Settings {
+            rules: Rules::new(&exclude).map_err(crate::Error::Config)?,
+            on_focus: config.on_focus.as_slice().into(),
+            script: match &config.script {
+                Some(path) => Some(Rc::new(Script::load(path).map_err(crate::Error::Config)?)),
+                None => None,
+            },
+            plugins: config.plugins.as_slice().into(),
+            accept_timeout: config.accept_timeout.map(Duration::from_millis),
+            min_dwell: config.min_dwell.map(Duration::from_millis),
+            flicker_interval: config.flicker_interval.map(Duration::from_millis),
+            focus_quiet_time: config.focus_quiet_time.map(Duration::from_millis),
+            overlay: config.overlay,
+            ignore_hover: config.ignore_hover,
+            no_desktop_switch: config.no_desktop_switch,
+            flash: config.flash.clone(),
+            transient: config.transient,
+            history_size: config.history_size,
+            eviction: config.eviction,
+            max_pending_requests: config.max_pending_requests,
+            activation: ActivationRules::new(&config.activation).map_err(crate::Error::Config)?,
+        })
+    }
+}
+
+/// Stream of the windows accepted in the history. Returned by
+/// [`Server::watch_active_window`].
+pub struct ActiveWindows<'a, B: DisplayBackend> {
+    server: &'a Server<B>,
+    changes: broadcast::Receiver<(B::Window, SystemTime)>,
+}
+
+impl<B: DisplayBackend> ActiveWindows<'_, B> {
+    /// Wait for the next window accepted in the history. It returns `None`
+    /// when the tracker is dropped.
+    ///
+    /// If the stream is not read fast enough, the oldest changes are
+    /// skipped. The future is not cancellation safe, since a change is
+    /// lost if it is dropped while the metadata is read.
+    pub async fn next(&mut self) -> Option<FocusChange> {
+        loop {
+            match self.changes.recv().await {
+                Ok((window, time)) => return Some(self.server.focus_change(window, time).await),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Time after a cycle request to accept the activated window.
+pub const CYCLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How the window activated by a request is selected.
+#[derive(Clone, Copy)]
+enum Target {
+    /// The `nth` window in the history. It is moved to the front.
+    Switch(usize),
+
+    /// A window relative to the current one. The history is not changed.
+    Cycle(isize),
+
+    /// The window with this identifier. It is moved to the front, if it is
+    /// in the history.
+    Window(u64),
+
+    /// The previous window, until the modifiers are released. The history
+    /// is not changed.
+    Peek,
+}
+
+pub struct Server<B: DisplayBackend> {
+    backend: Rc<B>,
+    tracker: FocusTracker<B>,
+    started: Instant,
+    on_focus: RefCell<Rc<[String]>>,
+    plugins: RefCell<Rc<[String]>>,
+    overlay: Cell<bool>,
+    no_desktop_switch: Cell<bool>,
+    overlay_visible: Rc<Cell<bool>>,
+    flash: RefCell<Option<Rc<str>>>,
+    reload: RefCell<Option<Box<Reload>>>,
+    quit: Notify,
+    activations: RefCell<Timing>,
+}
+
+/// Function to load the settings again, for [`Server::reload`].
+type Reload = dyn Fn() -> Result<Settings, crate::Error>;
+
+impl<B: DisplayBackend> Server<B> {
+    pub fn new(backend: Rc<B>) -> Rc<Self> {
+        let tracker = FocusTracker::new(backend.clone());
+        Rc::new(Server {
+            backend,
+            tracker,
+            started: Instant::now(),
+            on_focus: RefCell::new(Rc::new([])),
+            plugins: RefCell::new(Rc::new([])),
+            overlay: Cell::new(false),
+            no_desktop_switch: Cell::new(false),
+            overlay_visible: Default::default(),
+            flash: Default::default(),
+            reload: Default::default(),
+            quit: Default::default(),
+            activations: Default::default(),
+        })
+    }
+
+    /// Send the focus changes from the backend to the tracker, and run the
+    /// hooks and the plugins for the accepted windows.
+    pub async fn track_focus(self: &Rc<Self>) -> Result<(), Box<dyn Error>> {
+        let tracker = self
+            .tracker
+            .run()
+            .instrument(tracing::info_span!("tracker"));
+
+        // Plugins are killed when the tracker is finished.
+        let mut plugins = JoinSet::new();
+        for command in self.plugins.borrow().iter() {
+            let server = self.clone();
+            let command = command.clone();
+            plugins.spawn_local(async move { plugins::run(&server, &command).await });
+        }
+
+        tokio::select! {
+            r = tracker => r,
+            _ = hooks::on_focus(self) => Ok(()),
+            _ = self.publish_history() => Ok(()),
+        }
+    }
+
+    /// Publish the history in the backend every time it is modified.
+    async fn publish_history(&self) {
+        let mut changes = self.tracker.watch_history();
+
+        loop {
+            changes.mark_unchanged();
+            if let Err(e) = self.backend.publish_history(&self.history()).await {
+                tracing::warn!("Publish history: {e}");
+            }
+
+            if changes.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Keep a separate history for each workspace.
+    pub fn set_per_workspace(&self, enabled: bool) {
+        self.tracker.set_per_workspace(enabled);
+    }
+
+    /// Apply the settings from the configuration file.
+    pub fn configure(&self, settings: Settings) {
+        self.tracker.set_rules(settings.rules);
+        self.tracker.set_script(settings.script);
+        self.tracker.set_accept_timeout(settings.accept_timeout);
+        self.tracker.set_min_dwell(settings.min_dwell);
+        self.tracker.set_flicker_interval(settings.flicker_interval);
+        self.tracker.set_transient(settings.transient);
+        self.tracker.set_ignore_hover(settings.ignore_hover);
+        self.tracker.set_capacity(
+            settings.history_size.unwrap_or(DEFAULT_CAPACITY),
+            settings.eviction,
+        );
+        self.on_focus.replace(settings.on_focus);
+        self.plugins.replace(settings.plugins);
+        self.overlay.set(settings.overlay);
+        self.no_desktop_switch.set(settings.no_desktop_switch);
+        self.flash.replace(settings.flash.map(Rc::from));
+    }
+
+    /// Set the function to load the settings for [`reload`](Self::reload).
+    pub fn set_reload(&self, reload: impl Fn() -> Result<Settings, crate::Error> + 'static) {
+        self.reload.replace(Some(Box::new(reload)));
+    }
+
+    /// Load the settings again, and apply them. If they can't be loaded,
+    /// the current ones are kept.
+    ///
+    /// Plugins are not restarted, and the key binding is not changed.
+    pub fn reload(&self) -> Result<(), Box<dyn Error>> {
+        let settings = match &*self.reload.borrow() {
+            Some(reload) => reload()?,
+            None => return Err("The settings can't be reloaded.".into()),
+        };
+
+        self.configure(settings);
+        tracing::info!("Settings reloaded");
+        Ok(())
+    }
+
+    /// Ask the server to exit.
+    pub fn quit(&self) {
+        tracing::info!("Quit requested");
+        self.quit.notify_one();
+    }
+
+    /// Wait until [`quit`](Self::quit) is called.
+    pub async fn quit_requested(&self) {
+        self.quit.notified().await;
+    }
+
+    /// Commands to execute when the focused window changes.
+    pub fn on_focus_commands(&self) -> Rc<[String]> {
+        self.on_focus.borrow().clone()
+    }
+
+    /// Activate the `nth` window in the history, among the windows
+    /// accepted by `filter`.
+    pub async fn switch(&self, nth: usize, filter: &SwitchFilter) -> Result<(), Box<dyn Error>> {
+        self.activate_target(Target::Switch(nth), filter).await
+    }
+
+    /// Activate the window `step` positions after the current one, or
+    /// before it if `step` is negative, without changing the history.
+    ///
+    /// The activated window is accepted when the cycle ends: after
+    /// [`CYCLE_TIMEOUT`] without more cycle requests, and when the
+    /// modifiers are released. While the modifiers are pressed, Enter
+    /// ends the cycle, and Escape cancels it.
+    pub async fn cycle(&self, step: isize, filter: &SwitchFilter) -> Result<(), Box<dyn Error>> {
+        self.tracker.extend_cycle(CYCLE_TIMEOUT);
+        self.activate_target(Target::Cycle(step), filter).await
+    }
+
+    /// Activate the previous window while the modifiers are pressed, and
+    /// return to the current one when they are released. Enter keeps the
+    /// previous window, like a switch.
+    pub async fn peek(&self, filter: &SwitchFilter) -> Result<(), Box<dyn Error>> {
+        if !self.backend.modifiers_pressed().await? {
+            return Err("No modifiers are pressed.".into());
+        }
+
+        self.activate_target(Target::Peek, filter).await
+    }
+
+    /// Activate the most recent window demanding attention. A
+    /// [`switch`](Self::switch) returns to the previous window.
+    pub async fn switch_urgent(&self) -> Result<(), Box<dyn Error>> {
+        for window in self.backend.urgent_windows() {
+            if self.backend.is_available(window).await {
+                return self.activate(B::window_id(window)).await;
+            }
+        }
+
+        Err("No window demands attention.".into())
+    }
+
+    /// Activate the window that was current when a new window took the
+    /// focus without a key or a button press.
+    pub async fn switch_stolen(&self) -> Result<(), Box<dyn Error>> {
+        let window = self
+            .tracker
+            .take_stolen()
+            .ok_or("No window lost the focus.")?;
+
+        if !self.backend.is_available(window).await {
+            return Err("The window is not available.".into());
+        }
+
+        self.activate(B::window_id(window)).await
+    }
+
+    /// Stop or resume the recording of focus changes.
+    pub fn pause(&self, paused: bool) {
+        match paused {
+            true => tracing::info!("Tracking paused"),
+            false => tracing::info!("Tracking resumed"),
+        }
+
+        self.tracker.set_paused(paused);
+    }
+
+    /// Mark the current window with `name`.
+    pub fn mark(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let window = self.tracker.mark(name).ok_or("No current window.")?;
+        tracing::debug!(?window, name, "Marked");
+        Ok(())
+    }
+
+    /// Activate the window marked with `name`, and move it to the front of
+    /// the history.
+    pub async fn goto(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let window = self
+            .tracker
+            .marked(name)
+            .ok_or_else(|| format!("No window marked as {name:?}."))?;
+
+        self.activate(B::window_id(window)).await
+    }
+
+    /// Return the marks, sorted by name, with the marked windows.
+    pub fn marks(&self) -> Vec<(String, B::Window)> {
+        self.tracker.marks()
+    }
+
+    /// Activate the window with the identifier `id`, and move it to the
+    /// front of the history. Windows not in the history are activated
+    /// too, and the tracker adds them like any other focus change.
+    pub async fn activate(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.activate_target(Target::Window(id), &SwitchFilter::default())
+            .await
+    }
+
+    async fn activate_target(
+        &self,
+        target: Target,
+        filter: &SwitchFilter,
+    ) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        let result = self.try_activate(target, filter).await;
+        self.activations.borrow_mut().record(started.elapsed());
+
+        match result {
+            Ok(window) => {
+                let id = format!("0x{:08x}", B::window_id(window));
+                hooks::lifecycle(hooks::Event::Switch, &[&id]);
+
+                if let Some(color) = self.flash.borrow().clone() {
+                    let backend = self.backend.clone();
+                    tokio::task::spawn_local(async move {
+                        if let Err(e) = backend.flash(window, &color).await {
+                            tracing::warn!("Flash: {e}");
+                        }
+                    });
+                }
+
+                Ok(())
+            }
+
+            Err(e) => {
+                hooks::lifecycle(hooks::Event::Error, &[&e.to_string()]);
+                Err(e)
+            }
+        }
+    }
+
+    async fn try_activate(
+        &self,
+        target: Target,
+        filter: &SwitchFilter,
+    ) -> Result<B::Window, Box<dyn Error>> {
+        let (workspace, candidates) = self.scope(filter).await?;
+
+        // If the modifiers are pressed, the history is not changed until
+        // they are released, like with Alt+Tab. The tracker accepts the
+        // last activated window, and the next switch starts from it.
+        let target = match target {
+            Target::Switch(nth) if self.backend.modifiers_pressed().await? => {
+                Target::Cycle(nth as isize)
+            }
+
+            target => target,
+        };
+
+        match target {
+            Target::Cycle(_) => self.tracker.grab_cycle_keys(CycleKey::Commit),
+            Target::Peek => self.tracker.grab_cycle_keys(CycleKey::Cancel),
+            _ => (),
+        }
+
+        // Windows that can't be activated are removed, and the next one
+        // in the history is used.
+        let window = loop {
+            let filter = |w| candidates.as_ref().is_none_or(|c| c.contains(&w));
+
+            let window = match target {
+                Target::Switch(nth) => self
+                    .tracker
+                    .switch(nth, workspace, filter)
+                    .ok_or_else(|| format!("No window at position {nth} in the history."))?,
+
+                Target::Cycle(step) => self
+                    .tracker
+                    .cycle(step, workspace, filter)
+                    .ok_or("No other window in the history.")?,
+
+                Target::Peek => self
+                    .tracker
+                    .cycle(1, workspace, filter)
+                    .ok_or("No other window in the history.")?,
+
+                Target::Window(id) => self
+                    .tracker
+                    .switch(0, None, |w| B::window_id(w) == id)
+                    .or_else(|| B::window_from_id(id))
+                    .ok_or_else(|| format!("Invalid window id 0x{id:08x}."))?,
+            };
+
+            if self.backend.is_available(window).await {
+                break window;
+            }
+
+            self.tracker.remove(window);
+
+            if let Target::Window(id) = target {
+                return Err(format!("Window 0x{id:08x} can't be activated.").into());
+            }
+        };
+
+        self.backend
+            .activate(window)
+            .await
+            .map_err(|e| format!("Failed to activate {window:?}: {e}"))?;
+
+        match target {
+            Target::Switch(_) => self.tracker.switched(window),
+
+            // A window not in the history is notified when the tracker
+            // accepts it.
+            Target::Window(_) if self.tracker.current() == Some(window) => {
+                self.tracker.switched(window)
+            }
+
+            Target::Window(_) => (),
+            Target::Cycle(_) if self.overlay.get() => self.show_overlay(window).await,
+            Target::Cycle(_) | Target::Peek => (),
+        }
+
+        Ok(window)
+    }
+
+    /// Show the windows of the cycle, with `selected` highlighted, until
+    /// the cycle is finished.
+    async fn show_overlay(&self, selected: B::Window) {
+        let windows = self.tracker.cycle_windows();
+
+        let mut entries = Vec::with_capacity(windows.len());
+        for &window in &windows {
+            let info = self.backend.window_info(window).await.unwrap_or_default();
+            let line = format!(
+                "{}  {}",
+                info.class.unwrap_or_default(),
+                info.title.unwrap_or_default()
+            );
+
+            entries.push((window, line));
+        }
+
+        let selected = windows.iter().position(|&w| w == selected).unwrap_or(0);
+
+        if let Err(e) = self.backend.show_overlay(&entries, selected).await {
+            tracing::warn!("Overlay: {e}");
+            return;
+        }
+
+        if self.overlay_visible.replace(true) {
+            return;
+        }
+
+        let tracker = self.tracker.clone();
+        let backend = self.backend.clone();
+        let visible = self.overlay_visible.clone();
+        tokio::task::spawn_local(async move {
+            tracker.cycle_finished().await;
+            backend.hide_overlay();
+            visible.set(false);
+        });
+    }
+
+    /// Return the window that would be activated by
+    /// [`switch`](Self::switch), without activating it.
+    pub async fn switch_target(
+        &self,
+        nth: usize,
+        filter: &SwitchFilter,
+    ) -> Result<B::Window, Box<dyn Error>> {
+        let (workspace, candidates) = self.scope(filter).await?;
+
+        // Windows that can't be activated are skipped, but they are not
+        // removed from the history.
+        let mut unavailable = HashSet::new();
+
+        loop {
+            let filter =
+                |w| !unavailable.contains(&w) && candidates.as_ref().is_none_or(|c| c.contains(&w));
+
+            let window = self
+                .tracker
+                .peek(nth, workspace, filter)
+                .ok_or_else(|| format!("No window at position {nth} in the history."))?;
+
+            if self.backend.is_available(window).await {
+                return Ok(window);
+            }
+
+            unavailable.insert(window);
+        }
+    }
+
+    /// Return the workspace of the history, if it is per-workspace, and
+    /// the candidates for `filter`.
+    async fn scope(
+        &self,
+        filter: &SwitchFilter,
+    ) -> Result<(Option<u32>, Option<HashSet<B::Window>>), Box<dyn Error>> {
+        let per_workspace = self.tracker.per_workspace();
+        let no_desktop_switch = self.no_desktop_switch.get() && !filter.same_workspace;
+
+        let workspace = match per_workspace || filter.same_workspace || no_desktop_switch {
+            true => self.backend.current_workspace().await?,
+            false => None,
+        };
+
+        // `no_desktop_switch` is ignored if the backend does not know the
+        // current desktop.
+        let restricted;
+        let filter = match no_desktop_switch && workspace.is_some() {
+            true => {
+                restricted = SwitchFilter {
+                    same_workspace: true,
+                    ..filter.clone()
+                };
+
+                &restricted
+            }
+
+            false => filter,
+        };
+
+        let candidates = self.candidates(filter, workspace).await?;
+        Ok((workspace.filter(|_| per_workspace), candidates))
+    }
+
+    /// Windows in the history accepted by `filter`. Returns `None` if
+    /// there are no conditions in the filter.
+    async fn candidates(
+        &self,
+        filter: &SwitchFilter,
+        workspace: Option<u32>,
+    ) -> Result<Option<HashSet<B::Window>>, Box<dyn Error>> {
+        // Value of `_NET_WM_DESKTOP` for windows in all desktops.
+        const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+        if !filter.same_workspace
+            && !filter.same_class
+            && !filter.same_output
+            && filter.class.is_none()
+            && filter.pid.is_none()
+        {
+            return Ok(None);
+        }
+
+        let class_regex = match &filter.class {
+            Some(pattern) => {
+                Some(Regex::new(pattern).map_err(|e| format!("Invalid class {pattern:?}: {e}"))?)
+            }
+            None => None,
+        };
+
+        let history = self.history();
+
+        let workspace = match filter.same_workspace {
+            true => Some(workspace.ok_or("The current workspace is unknown.")?),
+            false => None,
+        };
+
+        // The class is taken from the current window.
+        let class = match (filter.same_class, history.first()) {
+            (false, _) => None,
+            (true, None) => return Err("The history is empty.".into()),
+            (true, Some(&current)) => Some(
+                self.backend
+                    .window_info(current)
+                    .await?
+                    .class
+                    .ok_or("The current window has no class.")?,
+            ),
+        };
+
+        let output = match filter.same_output {
+            true => Some(
+                self.backend
+                    .current_output()
+                    .await?
+                    .ok_or("The current output is unknown.")?,
+            ),
+            false => None,
+        };
+
+        let mut candidates = HashSet::new();
+
+        // With a class pattern or a process, the current window is always a
+        // candidate, so positions are counted from it, and `nth = 1` is the
+        // last matching window that is not the current one.
+        if class_regex.is_some() || filter.pid.is_some() {
+            candidates.extend(history.first());
+        }
+
+        for window in history {
+            // The class is taken from the tracker, so the window is queried
+            // only if it is not known, or to get its desktop or process.
+            let known_class = self.tracker.class(window);
+
+            let info = match (workspace, filter.pid, &known_class) {
+                (None, None, Some(_)) => None,
+                _ => match self.backend.window_info(window).await {
+                    Ok(info) => Some(info),
+                    Err(_) => continue,
+                },
+            };
+
+            if let Some(workspace) = workspace {
+                match info.as_ref().and_then(|i| i.desktop) {
+                    Some(d) if d == workspace || d == ALL_DESKTOPS => (),
+                    _ => continue,
+                }
+            }
+
+            if let Some(pid) = filter.pid {
+                if info.as_ref().and_then(|i| i.pid) != Some(pid) {
+                    continue;
+                }
+            }
+
+            let window_class = known_class.or_else(|| info.and_then(|i| i.class));
+
+            if class.is_some() && window_class != class {
+                continue;
+            }
+
+            if let Some(regex) = &class_regex {
+                if !window_class.as_deref().is_some_and(|c| regex.is_match(c)) {
+                    continue;
+                }
+            }
+
+            if let Some(output) = output {
+                match self.backend.window_output(window).await {
+                    Ok(Some(o)) if o == output => (),
+                    _ => continue,
+                }
+            }
+
+            candidates.insert(window);
+        }
+
+        Ok(Some(candidates))
+    }
+
+    /// Execute a request from a client.
+    pub async fn handle(&self, request: Request) -> Response {
+        tracing::debug!(?request, "Request");
+
+        match request {
+            Request::Hello { .. } => Response::Hello {
+                version: PROTOCOL_VERSION,
+            },
+
+            Request::Switch {
+                nth,
+                filter,
+                dry_run: true,
+            } => match self.switch_target(nth, &filter).await {
+                Ok(window) => Response::Window(self.entry(window).await),
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Switch { nth, filter, .. } => match self.switch(nth, &filter).await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Urgent => match self.switch_urgent().await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Stolen => match self.switch_stolen().await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Peek { filter } => match self.peek(&filter).await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Cycle { step, filter } => match self.cycle(step, &filter).await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Activate { id } => match self.activate(id).await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Mark { name } => match self.mark(&name) {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Goto { name } => match self.goto(&name).await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Marks => {
+                let mut marks = Vec::new();
+                for (name, window) in self.marks() {
+                    let window = self.entry(window).await;
+                    marks.push(Mark { name, window });
+                }
+
+                Response::Marks(marks)
+            }
+
+            Request::History => Response::History(self.history_entries().await),
+
+            // Handled by the socket, since it needs multiple responses.
+            Request::Watch => Response::Error("Unexpected watch request.".into()),
+
+            Request::Import { entries } => {
+                self.import(&entries).await;
+                Response::History(self.history_entries().await)
+            }
+
+            Request::Status => Response::Status(self.status()),
+
+            Request::DumpState => Response::State(self.state().await),
+
+            Request::Pause => {
+                self.pause(true);
+                Response::Ok
+            }
+
+            Request::Resume => {
+                self.pause(false);
+                Response::Ok
+            }
+
+            Request::Reload => match self.reload() {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.to_string()),
+            },
+
+            Request::Quit => {
+                self.quit();
+                Response::Ok
+            }
+
+            Request::Stats => Response::Stats(self.metrics()),
+
+            Request::Ping => Response::Ok,
+        }
+    }
+
+    /// Return the metadata of `window`, to send it to the clients.
+    pub async fn entry(&self, window: B::Window) -> HistoryEntry {
+        // Windows can be destroyed after they are added to the history.
+        let info = self.backend.window_info(window).await.unwrap_or_default();
+
+        HistoryEntry {
+            id: B::window_id(window),
+            info,
+        }
+    }
+
+    /// Return the metadata of the windows in the history.
+    pub async fn history_entries(&self) -> Vec<HistoryEntry> {
+        let mut entries = Vec::new();
+        for window in self.history() {
+            entries.push(self.entry(window).await);
+        }
+
+        entries
+    }
+
+    /// Return the metadata of a window accepted in the history at `time`.
+    pub async fn focus_change(&self, window: B::Window, time: SystemTime) -> FocusChange {
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_millis() as u64);
+
+        FocusChange {
+            timestamp,
+            window: self.entry(window).await,
+        }
+    }
+
+    /// Receive the windows accepted in the history.
+    pub fn subscribe(&self) -> broadcast::Receiver<(B::Window, SystemTime)> {
+        self.tracker.subscribe()
+    }
+
+    /// Return a stream of the windows accepted in the history, with their
+    /// metadata. Only the changes after this call are received.
+    pub fn watch_active_window(&self) -> ActiveWindows<'_, B> {
+        ActiveWindows {
+            server: self,
+            changes: self.subscribe(),
+        }
+    }
+
+    fn status(&self) -> Status {
+        let history = self.history();
+
+        Status {
+            uptime: self.started.elapsed().as_secs(),
+            current: history.first().map(|&w| B::window_id(w)),
+            last: history.get(1).map(|&w| B::window_id(w)),
+            history_length: history.len(),
+            screens: self.backend.screens(),
+            waiting_modifiers: self.tracker.waiting_modifiers(),
+            paused: self.tracker.is_paused(),
+        }
+    }
+
+    /// Return the latency metrics.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            activations: *self.activations.borrow(),
+            display: self.backend.stats(),
+        }
+    }
+
+    /// Return the internal state, for bug reports.
+    async fn state(&self) -> State {
+        let history = self.history();
+
+        let mut entries = Vec::with_capacity(history.len());
+        for (index, &window) in history.iter().enumerate() {
+            entries.push(StateEntry {
+                window: self.entry(window).await,
+                accepted: index > 0 || self.tracker.is_accepted(),
+                workspace: self.tracker.workspace(window),
+                recorded_class: self.tracker.class(window),
+            });
+        }
+
+        let ids = |windows: Vec<B::Window>| windows.into_iter().map(B::window_id).collect();
+
+        State {
+            status: self.status(),
+            history: entries,
+            committed: ids(self.tracker.cycle_windows()),
+            per_workspace: self.tracker.per_workspace(),
+            cycling: self.tracker.is_cycling(),
+            marks: self
+                .marks()
+                .into_iter()
+                .map(|(name, window)| (name, B::window_id(window)))
+                .collect(),
+            urgent: ids(self.backend.urgent_windows()),
+            stolen: self.tracker.stolen().map(B::window_id),
+            roots: self.backend.screen_states().await,
+        }
+    }
+
+    /// Return the windows in the history, most recent first.
+    pub fn history(&self) -> Vec<B::Window> {
+        self.tracker.windows()
+    }
+
+    /// Restore the history received from a previous instance.
+    pub fn restore(&self, windows: &[B::Window]) {
+        self.tracker.restore(windows);
+    }
+
+    /// Replace the history with the windows of `entries` that are still
+    /// available. An entry with a class is skipped if its window has a
+    /// different class, since the identifier may be reused.
+    pub async fn import(&self, entries: &[HistoryEntry]) {
+        let mut windows = Vec::with_capacity(entries.len());
+
+        for entry in entries {
+            let Some(window) = B::window_from_id(entry.id) else {
+                continue;
+            };
+
+            if windows.contains(&window) || !self.backend.is_available(window).await {
+                continue;
+            }
+
+            if entry.info.class.is_some() {
+                match self.backend.window_info(window).await {
+                    Ok(info) if info.class == entry.info.class => (),
+                    _ => continue,
+                }
+            }
+
+            windows.push(window);
+        }
+
+        tracing::debug!(?windows, "Import history");
+        self.tracker.import(&windows);
+    }
+}
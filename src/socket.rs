@@ -0,0 +1,820 @@
+//! Unix socket to send commands to the server.
+//!
+//! Each message is a JSON document, prefixed by its length as a 32-bit
+//! integer in native byte order. The client sends a [`Request`], and the
+//! server replies with a [`Response`]. A connection can be used for
+//! multiple requests.
+//!
+//! The first request from the client is [`Request::Hello`], to verify that
+//! both sides use the same version of the protocol.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+};
+use tracing::Instrument;
+
+use crate::{
+    backend::{DisplayBackend, ScreenState, Stats, Timing},
+    server::Server,
+};
+
+/// Maximum size of a message.
+const MAX_MESSAGE_SIZE: u32 = 1 << 20;
+
+/// Version of the protocol. It must be incremented when the messages are
+/// changed in an incompatible way.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Time to wait for the reply of the server.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Send the protocol version of the client. The reply is
+    /// [`Response::Hello`], with the version of the server.
+    Hello { version: u32 },
+
+    /// Activate the `nth` window in the history.
+    ///
+    /// If `dry_run` is `true`, the window is not activated, and the reply
+    /// is [`Response::Window`].
+    Switch {
+        nth: usize,
+
+        #[serde(flatten)]
+        filter: SwitchFilter,
+
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// Activate the window `step` positions after the current one, or
+    /// before it if `step` is negative. The history is reordered when the
+    /// cycle ends.
+    Cycle {
+        step: isize,
+
+        #[serde(flatten)]
+        filter: SwitchFilter,
+    },
+
+    /// Activate the most recent window demanding attention.
+    Urgent,
+
+    /// Activate the window that lost the focus when a new window took it
+    /// without a key or a button press.
+    Stolen,
+
+    /// Activate the previous window until the modifiers are released, and
+    /// then return to the current one.
+    Peek {
+        #[serde(flatten)]
+        filter: SwitchFilter,
+    },
+
+    /// Activate the window with the `id` of a [`HistoryEntry`], and move
+    /// it to the front of the history. Other windows can be activated
+    /// with their X window id.
+    Activate { id: u64 },
+
+    /// Mark the current window with `name`.
+    Mark { name: String },
+
+    /// Activate the window marked with `name`.
+    Goto { name: String },
+
+    /// Return the marked windows.
+    Marks,
+
+    /// Return the windows in the history.
+    History,
+
+    /// Replace the history with the windows in `entries`, like the ones
+    /// returned by [`Request::History`]. The current window is kept at the
+    /// front. The reply is the new history.
+    Import { entries: Vec<HistoryEntry> },
+
+    /// Stop recording the focus changes until [`Request::Resume`].
+    Pause,
+
+    /// Record the focus changes again after [`Request::Pause`].
+    Resume,
+
+    /// Read the configuration file again, and apply it. The reply is an
+    /// error if the file is not valid.
+    Reload,
+
+    /// Stop the server. The reply is sent before it exits.
+    Quit,
+
+    /// Return the state of the server.
+    Status,
+
+    /// Return the latency metrics of the server.
+    Stats,
+
+    /// Return the internal state of the server, for bug reports.
+    DumpState,
+
+    /// Check that the server is running. The reply is [`Response::Ok`].
+    Ping,
+
+    /// Send a [`Response::Focus`] for every window accepted in the history,
+    /// until the connection is closed.
+    Watch,
+}
+
+impl Request {
+    /// Parse a command written as text, like `switch 2` or `goto mail`.
+    /// Used for the commands written to the `_X11_ALTERNATE_FOCUS_COMMAND`
+    /// property of the root windows.
+    ///
+    /// The commands are `switch [NTH]`, `cycle-next`, `cycle-prev`,
+    /// `urgent`, `stolen`, `peek`, `focus ID`, `mark NAME`, `goto NAME`,
+    /// `pause`, `resume`, `reload`, and `quit`.
+    pub fn parse(text: &str) -> Result<Request, String> {
+        let text = text.trim();
+        let (command, arg) = match text.split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, Some(arg.trim())),
+            None => (text, None),
+        };
+
+        let request = match (command, arg) {
+            ("switch", nth) => Request::Switch {
+                nth: match nth {
+                    Some(nth) => nth
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("Invalid position: {nth}"))?,
+                    None => 1,
+                },
+                filter: SwitchFilter::default(),
+                dry_run: false,
+            },
+
+            ("cycle-next", None) => Request::Cycle {
+                step: 1,
+                filter: SwitchFilter::default(),
+            },
+
+            ("cycle-prev", None) => Request::Cycle {
+                step: -1,
+                filter: SwitchFilter::default(),
+            },
+
+            ("urgent", None) => Request::Urgent,
+            ("stolen", None) => Request::Stolen,
+
+            ("peek", None) => Request::Peek {
+                filter: SwitchFilter::default(),
+            },
+
+            ("focus", Some(id)) => Request::Activate {
+                id: parse_window_id(id).map_err(|_| format!("Invalid window id: {id}"))?,
+            },
+
+            ("mark", Some(name)) => Request::Mark { name: name.into() },
+            ("goto", Some(name)) => Request::Goto { name: name.into() },
+            ("pause", None) => Request::Pause,
+            ("resume", None) => Request::Resume,
+            ("reload", None) => Request::Reload,
+            ("quit", None) => Request::Quit,
+            _ => return Err(format!("Invalid command: {text}")),
+        };
+
+        Ok(request)
+    }
+}
+
+/// Parse a window id, in hexadecimal with the `0x` prefix, or in decimal.
+pub fn parse_window_id(id: &str) -> Result<u64, String> {
+    let parsed = match id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => id.parse(),
+    };
+
+    parsed.map_err(|e| format!("{e}"))
+}
+
+/// Conditions for the target of a `switch` request.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SwitchFilter {
+    /// Only windows in the current workspace.
+    #[serde(default)]
+    pub same_workspace: bool,
+
+    /// Only windows with the same class as the current one.
+    #[serde(default)]
+    pub same_class: bool,
+
+    /// Only windows in the output where the pointer is.
+    #[serde(default)]
+    pub same_output: bool,
+
+    /// Only windows with a class matched by this regular expression.
+    #[serde(default)]
+    pub class: Option<String>,
+
+    /// Only windows of this process, from `_NET_WM_PID`.
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    Hello { version: u32 },
+    History(Vec<HistoryEntry>),
+    Window(HistoryEntry),
+    Marks(Vec<Mark>),
+    Status(Status),
+    State(State),
+    Stats(Metrics),
+    Focus(FocusChange),
+    Error(String),
+}
+
+/// Window accepted in the history.
+#[derive(Serialize, Deserialize)]
+pub struct FocusChange {
+    /// Time of the change, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+
+    #[serde(flatten)]
+    pub window: HistoryEntry,
+}
+
+/// Window marked with a name.
+#[derive(Serialize, Deserialize)]
+pub struct Mark {
+    pub name: String,
+
+    #[serde(flatten)]
+    pub window: HistoryEntry,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+
+    #[serde(flatten)]
+    pub info: crate::backend::WindowInfo,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Status {
+    /// Seconds since the server was started.
+    pub uptime: u64,
+
+    /// Current window.
+    pub current: Option<u64>,
+
+    /// Previous window, activated by `switch`.
+    pub last: Option<u64>,
+
+    pub history_length: usize,
+
+    /// Number of screens in the display, if the backend has them.
+    pub screens: Option<usize>,
+
+    /// `true` if the current window is not added to the history until the
+    /// keyboard modifiers are released.
+    pub waiting_modifiers: bool,
+
+    /// `true` if the focus changes are not recorded.
+    #[serde(default)]
+    pub paused: bool,
+}
+
+/// Latency metrics of the server.
+#[derive(Serialize, Deserialize)]
+pub struct Metrics {
+    /// Time to activate the windows requested by the clients, from the
+    /// request to the activation.
+    pub activations: Timing,
+
+    /// Metrics of the connection to the display server, if the backend
+    /// records them.
+    pub display: Option<Stats>,
+}
+
+/// Internal state of the server.
+#[derive(Serialize, Deserialize)]
+pub struct State {
+    #[serde(flatten)]
+    pub status: Status,
+
+    /// Windows in the history, most recent first.
+    pub history: Vec<StateEntry>,
+
+    /// Order of the history if the current window is not accepted.
+    pub committed: Vec<u64>,
+
+    /// `true` if the history is kept for each workspace.
+    pub per_workspace: bool,
+
+    /// `true` if a cycle is in progress.
+    pub cycling: bool,
+
+    /// Marked windows, by name.
+    pub marks: BTreeMap<String, u64>,
+
+    /// Windows demanding attention, most recent first.
+    pub urgent: Vec<u64>,
+
+    /// Window that lost the focus in the last focus steal.
+    pub stolen: Option<u64>,
+
+    /// State of each screen, if the backend has them.
+    pub roots: Vec<ScreenState>,
+}
+
+/// Window in the history, with the data recorded by the tracker.
+#[derive(Serialize, Deserialize)]
+pub struct StateEntry {
+    #[serde(flatten)]
+    pub window: HistoryEntry,
+
+    /// `false` if the window is not accepted yet. Only the current window
+    /// can be pending.
+    pub accepted: bool,
+
+    /// Workspace where the window was focused, in per-workspace histories.
+    pub workspace: Option<u32>,
+
+    /// Class recorded when the window was focused.
+    pub recorded_class: Option<String>,
+}
+
+/// Path of the socket for the current display.
+pub fn path() -> PathBuf {
+    path_for(&display())
+}
+
+/// Path of the socket for `display`.
+///
+/// It is in `$XDG_RUNTIME_DIR`, and its name includes the display, so
+/// multiple servers can run in the same session. Without
+/// `$XDG_RUNTIME_DIR`, it is in a directory of the user in the temporary
+/// directory, created by the server with mode `0700`.
+pub fn path_for(display: &str) -> PathBuf {
+    let mut path: PathBuf = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => dir.into(),
+        None => fallback_dir(),
+    };
+
+    path.push(format!(
+        "x11-alternate-focus{}{}.sock",
+        if display.is_empty() { "" } else { "-" },
+        display.replace('/', "_"),
+    ));
+
+    path
+}
+
+/// Directory for the sockets when `$XDG_RUNTIME_DIR` is not set.
+fn fallback_dir() -> PathBuf {
+    env::temp_dir().join(format!("x11-alternate-focus-{}", uid()))
+}
+
+fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// Create `dir` with mode `0700`, and fail if it already exists but it is
+/// not a directory of the user, or other users can access it.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => (),
+    }
+
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid() || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory.", dir.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Name of the display used by the server.
+pub(crate) fn display() -> String {
+    env::var("DISPLAY")
+        .or_else(|_| env::var("WAYLAND_DISPLAY"))
+        .unwrap_or_default()
+}
+
+/// Create the socket for the server.
+///
+/// If the server was started by socket activation, the socket from the
+/// service manager is used. Its path must be the same returned by [`path`].
+///
+/// If there is a server listening in the same path, it fails unless
+/// `replace` is `true`.
+pub fn bind(replace: bool) -> io::Result<UnixListener> {
+    if let Some(listener) = crate::systemd::listener() {
+        let listener = listener?;
+        listener.set_nonblocking(true)?;
+        return UnixListener::from_std(listener);
+    }
+
+    bind_at(&path(), replace)
+}
+
+/// Create the socket for the server in `path`, without socket activation.
+pub fn bind_at(path: &Path, replace: bool) -> io::Result<UnixListener> {
+    // The temporary directory is shared with other users.
+    if let Some(dir) = path.parent().filter(|&d| d == fallback_dir()) {
+        create_private_dir(dir)?;
+    }
+
+    if path.exists() {
+        if !replace && std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "A server is already running. Use --replace to take it over.",
+            ));
+        }
+
+        std::fs::remove_file(path)?;
+    }
+
+    UnixListener::bind(path)
+}
+
+/// Accept connections from the clients, and send their requests to
+/// `server`. Only clients of the same user are accepted.
+pub async fn listen<B: DisplayBackend>(listener: UnixListener, server: Rc<Server<B>>) {
+    // Pause after an error, since some of them, like EMFILE, are repeated
+    // until a client is closed.
+    const ERROR_DELAY: Duration = Duration::from_millis(100);
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("Socket: {e}");
+                tokio::time::sleep(ERROR_DELAY).await;
+                continue;
+            }
+        };
+
+        let pid = match stream.peer_cred() {
+            Ok(cred) if cred.uid() == uid() => cred.pid(),
+
+            Ok(cred) => {
+                tracing::warn!("Client rejected: user {}", cred.uid());
+                continue;
+            }
+
+            Err(e) => {
+                tracing::warn!("Client rejected: {e}");
+                continue;
+            }
+        };
+
+        let span = tracing::debug_span!("client", pid);
+
+        let server = server.clone();
+        let client = async move {
+            if let Err(e) = serve_client(stream, &server).await {
+                tracing::warn!("Socket: {e}");
+            }
+        };
+
+        tokio::task::spawn_local(client.instrument(span));
+    }
+}
+
+async fn serve_client<B: DisplayBackend>(
+    mut stream: UnixStream,
+    server: &Server<B>,
+) -> io::Result<()> {
+    while let Some(payload) = receive_payload(&mut stream).await? {
+        // Requests from newer clients can't be parsed, but the client
+        // receives the error, and the connection is kept open.
+        let request = match serde_json::from_slice(&payload) {
+            Ok(request) => request,
+            Err(e) => {
+                let response = Response::Error(format!("Invalid request: {e}"));
+                send(&mut stream, &response).await?;
+                continue;
+            }
+        };
+
+        if let Request::Watch = request {
+            return send_focus_changes(stream, server).await;
+        }
+
+        let response = server.handle(request).await;
+        send(&mut stream, &response).await?;
+    }
+
+    Ok(())
+}
+
+/// Send the focus changes to the client, until the connection is closed.
+async fn send_focus_changes<B: DisplayBackend>(
+    mut stream: UnixStream,
+    server: &Server<B>,
+) -> io::Result<()> {
+    let mut changes = server.watch_active_window();
+
+    while let Some(change) = changes.next().await {
+        send(&mut stream, &Response::Focus(change)).await?;
+    }
+
+    Ok(())
+}
+
+/// Send `request` to the server, and wait for its response.
+pub async fn request(request: &Request) -> Result<Response, Box<dyn Error>> {
+    let mut stream = connect().await?;
+
+    match tokio::time::timeout(REPLY_TIMEOUT, exchange(&mut stream, request)).await {
+        Ok(result) => result,
+        Err(_) => Err("The server did not reply.".into()),
+    }
+}
+
+/// Send a request that expects [`Response::Ok`], and return the error
+/// from the server if it fails.
+pub async fn request_ok(req: &Request) -> Result<(), Box<dyn Error>> {
+    match request(req).await? {
+        Response::Ok => Ok(()),
+        Response::Error(e) => Err(e.into()),
+        _ => Err("Unexpected response from the server.".into()),
+    }
+}
+
+/// Send a [`Request::History`] to the server, and return its entries.
+pub async fn request_history() -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    match request(&Request::History).await? {
+        Response::History(entries) => Ok(entries),
+        Response::Error(e) => Err(e.into()),
+        _ => Err("Unexpected response from the server.".into()),
+    }
+}
+
+/// Send a [`Request::Watch`] to the server.
+pub async fn watch() -> Result<Watch, Box<dyn Error>> {
+    let mut stream = connect().await?;
+    send(&mut stream, &Request::Watch).await?;
+    Ok(Watch(stream))
+}
+
+/// Connection to receive the focus changes from the server.
+pub struct Watch(UnixStream);
+
+impl Watch {
+    /// Wait for the next focus change.
+    ///
+    /// The future is not cancellation safe, since a message can be
+    /// partially read.
+    pub async fn next(&mut self) -> Result<FocusChange, Box<dyn Error>> {
+        loop {
+            match receive(&mut self.0).await? {
+                Some(Response::Focus(change)) => return Ok(change),
+                Some(_) => continue,
+                None => return Err("Connection closed by the server.".into()),
+            }
+        }
+    }
+}
+
+/// Connect to the server, and verify that it uses the same version of the
+/// protocol.
+async fn connect() -> Result<UnixStream, Box<dyn Error>> {
+    let path = path();
+
+    let mut stream = match UnixStream::connect(&path).await {
+        Ok(stream) => stream,
+
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Err(match display() {
+                d if d.is_empty() => "No server running.".into(),
+                d => format!("No server running on {d}.").into(),
+            });
+        }
+
+        Err(e) => {
+            return Err(format!("Can't connect to the server at {}: {e}", path.display()).into())
+        }
+    };
+
+    match tokio::time::timeout(REPLY_TIMEOUT, hello(&mut stream)).await {
+        Ok(result) => result.map(|_| stream),
+        Err(_) => Err("The server did not reply.".into()),
+    }
+}
+
+/// Send a [`Request::Hello`], and verify the version in the reply.
+async fn hello(stream: &mut UnixStream) -> Result<(), Box<dyn Error>> {
+    const OLDER_SERVER: &str =
+        "The server uses an older version of the protocol. Restart the server with --replace.";
+
+    let hello = Request::Hello {
+        version: PROTOCOL_VERSION,
+    };
+
+    send(stream, &hello).await?;
+
+    // Servers without `Hello` close the connection when they can't parse
+    // the request.
+    let reply = match receive(stream).await {
+        Ok(Some(reply)) => reply,
+        Ok(None) => return Err(OLDER_SERVER.into()),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Err(OLDER_SERVER.into()),
+        Err(e) => return Err(e.into()),
+    };
+
+    match reply {
+        Response::Hello { version } if version == PROTOCOL_VERSION => Ok(()),
+
+        Response::Hello { version } => Err(format!(
+            "The server uses the protocol version {version}, but the client uses \
+             {PROTOCOL_VERSION}. Restart the server with --replace."
+        )
+        .into()),
+
+        _ => Err(OLDER_SERVER.into()),
+    }
+}
+
+/// Send `request`, and wait for its response.
+async fn exchange(stream: &mut UnixStream, request: &Request) -> Result<Response, Box<dyn Error>> {
+    send(stream, request).await?;
+
+    match receive(stream).await? {
+        Some(response) => Ok(response),
+        None => Err("No reply from the server.".into()),
+    }
+}
+
+async fn send<T: Serialize>(stream: &mut UnixStream, message: &T) -> io::Result<()> {
+    let payload = serde_json::to_vec(message)?;
+
+    let length = u32::try_from(payload.len())
+        .ok()
+        .filter(|&l| l <= MAX_MESSAGE_SIZE)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+
+    let mut msg = Vec::with_capacity(4 + payload.len());
+    msg.extend_from_slice(&length.to_ne_bytes());
+    msg.extend_from_slice(&payload);
+
+    stream.write_all(&msg).await
+}
+
+/// Read the next message. Returns `None` if the connection is closed.
+async fn receive<T: DeserializeOwned>(stream: &mut UnixStream) -> io::Result<Option<T>> {
+    match receive_payload(stream).await? {
+        Some(payload) => Ok(Some(serde_json::from_slice(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+/// Read the payload of the next message, without parsing it. Returns
+/// `None` if the connection is closed.
+async fn receive_payload(stream: &mut UnixStream) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    match stream.read_exact(&mut length).await {
+        Ok(_) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let length = u32::from_ne_bytes(length);
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too large",
+        ));
+    }
+
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload).await?;
+
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("x11-alternate-focus-test-{}", std::process::id()));
+
+        create_private_dir(&dir).unwrap();
+        create_private_dir(&dir).unwrap();
+
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // Accessible by other users.
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let error = create_private_dir(&dir).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_text_commands() {
+        assert!(matches!(
+            Request::parse("switch"),
+            Ok(Request::Switch { nth: 1, .. })
+        ));
+
+        assert!(matches!(
+            Request::parse("switch 2"),
+            Ok(Request::Switch { nth: 2, .. })
+        ));
+
+        assert!(matches!(
+            Request::parse("cycle-prev"),
+            Ok(Request::Cycle { step: -1, .. })
+        ));
+
+        assert!(matches!(
+            Request::parse(" focus 0x2 "),
+            Ok(Request::Activate { id: 2 })
+        ));
+
+        assert!(matches!(
+            Request::parse("focus 0X1F"),
+            Ok(Request::Activate { id: 0x1f })
+        ));
+
+        assert!(matches!(
+            Request::parse("goto  mail"),
+            Ok(Request::Goto { name }) if name == "mail"
+        ));
+
+        assert!(Request::parse("switch 0").is_err());
+        assert!(Request::parse("focus window").is_err());
+        assert!(Request::parse("pause now").is_err());
+        assert!(Request::parse("mark").is_err());
+        assert!(Request::parse("").is_err());
+    }
+
+    #[tokio::test]
+    async fn hello_to_server_without_hello() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+
+        // Like a server that can't parse the request.
+        let server = tokio::spawn(async move {
+            let _ = receive_payload(&mut server).await;
+        });
+
+        let error = hello(&mut client).await.unwrap_err();
+        server.await.unwrap();
+
+        assert!(error.to_string().contains("older version"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn hello_with_another_version() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+
+        tokio::spawn(async move {
+            let _ = receive_payload(&mut server).await;
+            let reply = Response::Hello {
+                version: PROTOCOL_VERSION + 1,
+            };
+
+            send(&mut server, &reply).await.unwrap();
+        });
+
+        let error = hello(&mut client).await.unwrap_err();
+        assert!(error.to_string().contains("protocol version"), "{error}");
+    }
+}
@@ -0,0 +1,66 @@
+//! History saved by the `quit` command, and restored when the server starts
+//! again.
+//!
+//! The file has the same format as `history export`: a JSON array of
+//! [`HistoryEntry`]. The default location is
+//! `$XDG_STATE_HOME/x11-alternate-focus/history-$DISPLAY.json`.
+//!
+//! The history received from a replaced server takes precedence over the
+//! file. The file is deleted after it is restored, so it is used only once.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{backend::DisplayBackend, server::Server, socket::HistoryEntry};
+
+/// Path of the state file for the current display.
+pub fn path() -> Option<PathBuf> {
+    path_for(&crate::socket::display())
+}
+
+/// Path of the state file for `display`.
+pub fn path_for(display: &str) -> Option<PathBuf> {
+    let mut path: PathBuf = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) => dir.into(),
+        None => Path::new(&env::var_os("HOME")?).join(".local/state"),
+    };
+
+    path.push("x11-alternate-focus");
+    path.push(format!(
+        "history{}{}.json",
+        if display.is_empty() { "" } else { "-" },
+        display.replace('/', "_"),
+    ));
+
+    Some(path)
+}
+
+/// Write the history of `server` in `path`.
+pub async fn save<B: DisplayBackend>(server: &Server<B>, path: &Path) -> io::Result<()> {
+    let entries = server.history_entries().await;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, serde_json::to_vec(&entries)?)
+}
+
+/// Import the history in `path`, and delete the file. It is not an error
+/// if the file does not exist.
+pub async fn restore<B: DisplayBackend>(server: &Server<B>, path: &Path) -> io::Result<()> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    fs::remove_file(path)?;
+
+    let entries: Vec<HistoryEntry> = serde_json::from_slice(&data)?;
+    server.import(&entries).await;
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! Integration with the service manager.
+//!
+//! Notifications are sent for units with `Type=notify` (see `sd_notify(3)`),
+//! and the socket for the clients can be received from a socket unit (see
+//! `sd_listen_fds(3)`).
+
+use std::{
+    env, io,
+    os::{
+        fd::BorrowedFd,
+        unix::net::{SocketAddr, UnixDatagram, UnixListener},
+    },
+    process,
+};
+
+/// First file descriptor passed by the service manager.
+const LISTEN_FDS_START: i32 = 3;
+
+/// The server is ready to track the focus and to accept clients.
+pub fn ready() {
+    notify("READY=1\n");
+}
+
+/// The server is shutting down.
+pub fn stopping() {
+    notify("STOPPING=1\n");
+}
+
+/// Send `state` to the socket in `$NOTIFY_SOCKET`. Nothing is sent if the
+/// variable is not set.
+fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    if let Err(e) = send(path.as_encoded_bytes(), state) {
+        tracing::warn!("NOTIFY_SOCKET: {e}");
+    }
+}
+
+fn send(path: &[u8], state: &str) -> io::Result<()> {
+    let addr = match path {
+        // Sockets in the abstract namespace start with '@'.
+        [b'@', name @ ..] => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name)?
+        }
+
+        _ => {
+            use std::os::unix::ffi::OsStrExt;
+            SocketAddr::from_pathname(std::ffi::OsStr::from_bytes(path))?
+        }
+    };
+
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+
+    Ok(())
+}
+
+/// Return `true` if the process was started by socket activation.
+pub fn socket_activated() -> bool {
+    let var = |name| env::var(name).ok().and_then(|v| v.parse::<u32>().ok());
+
+    match (var("LISTEN_PID"), var("LISTEN_FDS")) {
+        (Some(pid), Some(fds)) => pid == process::id() && fds >= 1,
+        _ => false,
+    }
+}
+
+/// Return the listening socket passed by the service manager, if the
+/// process was started by socket activation.
+///
+/// The socket is duplicated, so it can be used again if the server is
+/// restarted after losing the connection to the display.
+pub fn listener() -> Option<io::Result<UnixListener>> {
+    if !socket_activated() {
+        return None;
+    }
+
+    let fd = unsafe { BorrowedFd::borrow_raw(LISTEN_FDS_START) };
+    Some(fd.try_clone_to_owned().map(UnixListener::from))
+}
@@ -0,0 +1,59 @@
+//! Templates to format the metadata of a window.
+//!
+//! The variables are `{id}`, `{class}`, `{title}`, and `{desktop}`. Any other
+//! text is copied verbatim.
+
+use crate::socket::HistoryEntry;
+
+/// Replace the variables in `template` with the values from `entry`.
+pub fn render(template: &str, entry: &HistoryEntry) -> String {
+    render_with(template, entry, str::to_owned)
+}
+
+/// Like [`render`], but the values are transformed by `escape`.
+pub fn render_with(
+    template: &str,
+    entry: &HistoryEntry,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let variable = rest
+            .find('}')
+            .and_then(|end| Some((end, variable(&rest[1..end], entry)?)));
+
+        match variable {
+            Some((end, value)) => {
+                output.push_str(&escape(&value));
+                rest = &rest[end + 1..];
+            }
+
+            None => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn variable(name: &str, entry: &HistoryEntry) -> Option<String> {
+    let info = &entry.info;
+
+    let value = match name {
+        "id" => format!("0x{:08x}", entry.id),
+        "class" => info.class.clone().unwrap_or_default(),
+        "title" => info.title.clone().unwrap_or_default(),
+        "desktop" => info.desktop.map(|d| d.to_string()).unwrap_or_default(),
+        _ => return None,
+    };
+
+    Some(value)
+}
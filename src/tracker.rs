@@ -0,0 +1,853 @@
+use std::{
+    cell::{Cell, RefCell, RefMut},
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::{
+    sync::{broadcast, watch, Notify},
+    time::Instant,
+};
+use tracing::Instrument;
+
+use crate::{
+    backend::{CycleKey, DisplayBackend, FocusEvent, WindowInfo},
+    config::{Eviction, Transient},
+    history::History,
+    policy::{Decision, Script},
+    rules::Rules,
+};
+
+/// Time before a focus change where a key or button press is considered
+/// the cause of the change.
+const INTERACTION_MARGIN: Duration = Duration::from_millis(100);
+
+pub struct FocusTracker<B: DisplayBackend>(Rc<FocusTrackerInner<B>>);
+
+impl<B: DisplayBackend> Clone for FocusTracker<B> {
+    fn clone(&self) -> Self {
+        FocusTracker(self.0.clone())
+    }
+}
+
+struct FocusTrackerInner<B: DisplayBackend> {
+    backend: Rc<B>,
+    cookie: Cell<usize>,
+    history: RefCell<History<B::Window>>,
+
+    /// If `true`, record the workspace where each window was focused.
+    per_workspace: Cell<bool>,
+
+    /// Workspace of the windows in the history.
+    workspaces: RefCell<HashMap<B::Window, u32>>,
+
+    /// Class of the windows in the history.
+    classes: RefCell<HashMap<B::Window, String>>,
+
+    /// Windows marked with a name.
+    marks: RefCell<BTreeMap<String, B::Window>>,
+
+    /// Windows excluded from the history.
+    rules: RefCell<Rules>,
+
+    /// Script to decide how windows are recorded.
+    script: RefCell<Option<Rc<Script>>>,
+
+    /// Maximum time to wait until the modifiers are released.
+    accept_timeout: Cell<Option<Duration>>,
+
+    /// Minimum time that a window must be focused to be accepted.
+    min_dwell: Cell<Option<Duration>>,
+
+    /// Maximum time to return to the previous window in an A→B→A sequence,
+    /// to put B back where it was.
+    flicker_interval: Cell<Option<Duration>>,
+
+    /// Window that was current before the last one was added, and the
+    /// time when it was added.
+    previous: Cell<Option<(B::Window, Instant)>>,
+
+    /// Window that was current when a new window took the focus without
+    /// a key or button press.
+    stolen: Cell<Option<B::Window>>,
+
+    /// If `true`, the focus changes are not recorded.
+    paused: Cell<bool>,
+
+    /// How transient windows are recorded.
+    transient: Cell<Transient>,
+
+    /// If `true`, windows are accepted only after a key or a button is
+    /// pressed in them.
+    ignore_hover: Cell<bool>,
+
+    /// End of the current cycle. Windows focused before it are accepted
+    /// when it expires.
+    cycle_end: Cell<Option<Instant>>,
+
+    /// If `true`, the keys to end a cycle are grabbed.
+    cycle_keys: Cell<bool>,
+
+    /// Notified when the keys of a cycle are released, or when the cycle
+    /// is finished by them.
+    cycle_changed: Notify,
+
+    /// Windows accepted in the history, with the time of the change.
+    changes: broadcast::Sender<(B::Window, SystemTime)>,
+
+    /// Marked as changed every time the list of windows is modified.
+    history_changed: watch::Sender<()>,
+}
+
+impl<B: DisplayBackend> FocusTrackerInner<B> {
+    fn set_accepted(&self, accepted: bool) {
+        self.history.borrow_mut().set_accepted(accepted);
+    }
+
+    /// Mark the new `window` as accepted, and notify the subscribers.
+    fn accept(&self, window: B::Window) {
+        self.set_accepted(true);
+        self.notify(window);
+    }
+
+    fn notify(&self, window: B::Window) {
+        let _ = self.changes.send((window, SystemTime::now()));
+    }
+
+    /// Borrow the history to modify its windows, and notify the watchers
+    /// of [`FocusTracker::watch_history`].
+    fn history_mut(&self) -> RefMut<'_, History<B::Window>> {
+        self.history_changed.send_replace(());
+        self.history.borrow_mut()
+    }
+}
+
+impl<B: DisplayBackend> FocusTracker<B> {
+    pub fn new(backend: Rc<B>) -> Self {
+        FocusTracker(Rc::new(FocusTrackerInner {
+            backend,
+            cookie: Cell::new(0),
+            history: Default::default(),
+            per_workspace: Cell::new(false),
+            workspaces: Default::default(),
+            classes: Default::default(),
+            marks: Default::default(),
+            rules: Default::default(),
+            script: Default::default(),
+            accept_timeout: Default::default(),
+            min_dwell: Default::default(),
+            flicker_interval: Default::default(),
+            previous: Default::default(),
+            stolen: Default::default(),
+            paused: Default::default(),
+            transient: Default::default(),
+            ignore_hover: Default::default(),
+            cycle_end: Default::default(),
+            cycle_keys: Default::default(),
+            cycle_changed: Default::default(),
+            changes: broadcast::channel(16).0,
+            history_changed: watch::channel(()).0,
+        }))
+    }
+
+    pub fn set_rules(&self, rules: Rules) {
+        self.0.rules.replace(rules);
+    }
+
+    pub fn set_script(&self, script: Option<Rc<Script>>) {
+        self.0.script.replace(script);
+    }
+
+    /// Accept the focused window after `timeout`, even if the modifiers
+    /// are not released.
+    pub fn set_accept_timeout(&self, timeout: Option<Duration>) {
+        self.0.accept_timeout.set(timeout);
+    }
+
+    /// Accept a focused window only if it is still focused after
+    /// `duration`. Brief focus changes, like popups, don't displace the
+    /// previous window.
+    pub fn set_min_dwell(&self, duration: Option<Duration>) {
+        self.0.min_dwell.set(duration);
+    }
+
+    /// Change the maximum number of windows in the history, and how they
+    /// are removed when it is full.
+    pub fn set_capacity(&self, capacity: usize, eviction: Eviction) {
+        let history = &mut *self.0.history_mut();
+        history.set_capacity(capacity, eviction);
+
+        self.0
+            .workspaces
+            .borrow_mut()
+            .retain(|&w, _| history.contains(w));
+        self.0
+            .classes
+            .borrow_mut()
+            .retain(|&w, _| history.contains(w));
+    }
+
+    /// Accept a focused window only after a key or a button is pressed,
+    /// so windows focused by moving the pointer over them are ignored.
+    pub fn set_ignore_hover(&self, enabled: bool) {
+        self.0.ignore_hover.set(enabled);
+    }
+
+    /// If the focus returns to the previous window before `interval`, the
+    /// window focused between them is put back where it was, so brief
+    /// focus changes (A→B→A) don't modify the history.
+    pub fn set_flicker_interval(&self, interval: Option<Duration>) {
+        self.0.flicker_interval.set(interval);
+    }
+
+    /// Skip transient windows, or record their main window instead.
+    pub fn set_transient(&self, transient: Transient) {
+        self.0.transient.set(transient);
+    }
+
+    /// Start a cycle, or extend the current one, so the focused windows
+    /// are not accepted until `duration` is elapsed.
+    pub fn extend_cycle(&self, duration: Duration) {
+        self.0.cycle_end.set(Some(Instant::now() + duration));
+    }
+
+    /// Grab the keys to end the cycle, if the modifiers are pressed.
+    ///
+    /// Until they are released, Enter accepts the current window, and
+    /// Escape activates the window that was current before the cycle.
+    /// Releasing the modifiers has the effect of `released`.
+    pub fn grab_cycle_keys(&self, released: CycleKey) {
+        if self.0.cycle_keys.replace(true) {
+            return;
+        }
+
+        let origin = self.0.history.borrow().accepted_current();
+        let span = tracing::debug_span!("cycle", ?origin);
+        let task = cycle_keys(self.0.clone(), origin, released);
+        tokio::task::spawn_local(task.instrument(span));
+    }
+
+    /// Wait until the current cycle is finished, if any.
+    pub async fn cycle_finished(&self) {
+        loop {
+            let changed = self.0.cycle_changed.notified();
+
+            if self.0.cycle_keys.get() {
+                changed.await;
+                continue;
+            }
+
+            match self.0.cycle_end.get() {
+                Some(end) if end > Instant::now() => {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(end) => (),
+                        _ = changed => (),
+                    }
+                }
+
+                _ => return,
+            }
+        }
+    }
+
+    /// Stop or resume the recording of focus changes. Closed windows are
+    /// removed from the history while it is paused.
+    pub fn set_paused(&self, paused: bool) {
+        self.0.paused.set(paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.get()
+    }
+
+    /// Keep a separate history for each workspace.
+    pub fn set_per_workspace(&self, enabled: bool) {
+        self.0.per_workspace.set(enabled);
+    }
+
+    /// Receive the focus events from the backend, until it fails.
+    pub async fn run(&self) -> Result<(), Box<dyn Error>> {
+        loop {
+            match self.0.backend.focus_event().await? {
+                FocusEvent::Focused(window) if self.0.paused.get() => {
+                    tracing::debug!(?window, "Focused while paused");
+                }
+
+                FocusEvent::Focused(window) => {
+                    tracing::debug!(?window, "Focused");
+                    self.track(window);
+                }
+
+                FocusEvent::Closed(window) => {
+                    tracing::debug!(?window, "Closed");
+                    self.remove(window);
+                }
+            }
+        }
+    }
+
+    fn track(&self, window: B::Window) {
+        let ft = self.0.clone();
+
+        let cookie = ft.cookie.get() + 1;
+        ft.cookie.set(cookie);
+
+        let span = tracing::debug_span!("track", ?window, cookie);
+        tokio::task::spawn_local(track(cookie, window, ft).instrument(span));
+    }
+
+    /// Return the `nth` window in the history, and move it to the front.
+    ///
+    /// Only the windows accepted by `filter` are considered. If `workspace`
+    /// is not `None`, they must also be focused in that workspace.
+    pub fn switch(
+        &self,
+        nth: usize,
+        workspace: Option<u32>,
+        filter: impl Fn(B::Window) -> bool,
+    ) -> Option<B::Window> {
+        let mut history = self.0.history_mut();
+        let workspaces = self.0.workspaces.borrow();
+
+        history.switch(nth, |w| {
+            filter(w) && workspace.is_none_or(|ws| workspaces.get(&w) == Some(&ws))
+        })
+    }
+
+    /// Return the window that would be returned by [`switch`](Self::switch),
+    /// without changing the history.
+    pub fn peek(
+        &self,
+        nth: usize,
+        workspace: Option<u32>,
+        filter: impl Fn(B::Window) -> bool,
+    ) -> Option<B::Window> {
+        let history = self.0.history.borrow();
+        let workspaces = self.0.workspaces.borrow();
+
+        history.peek(nth, |w| {
+            filter(w) && workspace.is_none_or(|ws| workspaces.get(&w) == Some(&ws))
+        })
+    }
+
+    /// Return the window `step` positions after the current one, without
+    /// changing the history. See [`History::cycle`].
+    pub fn cycle(
+        &self,
+        step: isize,
+        workspace: Option<u32>,
+        filter: impl Fn(B::Window) -> bool,
+    ) -> Option<B::Window> {
+        let history = self.0.history.borrow();
+        let workspaces = self.0.workspaces.borrow();
+
+        history.cycle(step, |w| {
+            filter(w) && workspace.is_none_or(|ws| workspaces.get(&w) == Some(&ws))
+        })
+    }
+
+    pub fn per_workspace(&self) -> bool {
+        self.0.per_workspace.get()
+    }
+
+    /// Return the workspace where a window in the history was focused, if
+    /// the history is per-workspace.
+    pub fn workspace(&self, window: B::Window) -> Option<u32> {
+        self.0.workspaces.borrow().get(&window).copied()
+    }
+
+    /// Return `true` if the current window is accepted in the history.
+    pub fn is_accepted(&self) -> bool {
+        self.0.history.borrow().is_accepted()
+    }
+
+    /// Return `true` if a cycle is in progress.
+    pub fn is_cycling(&self) -> bool {
+        self.0.cycle_keys.get()
+            || self
+                .0
+                .cycle_end
+                .get()
+                .is_some_and(|end| end > Instant::now())
+    }
+
+    /// Return the tracked windows, most recent first.
+    pub fn windows(&self) -> Vec<B::Window> {
+        self.0.history.borrow().windows()
+    }
+
+    /// Return the class of a window in the history, if it was known when
+    /// the window was focused.
+    pub fn class(&self, window: B::Window) -> Option<String> {
+        self.0.classes.borrow().get(&window).cloned()
+    }
+
+    /// Return the current window, even if it is not accepted yet.
+    pub fn current(&self) -> Option<B::Window> {
+        self.0.history.borrow().current()
+    }
+
+    /// Return the windows in the order used by [`cycle`](Self::cycle).
+    pub fn cycle_windows(&self) -> Vec<B::Window> {
+        self.0.history.borrow().committed()
+    }
+
+    /// Receive the windows accepted in the history.
+    pub fn subscribe(&self) -> broadcast::Receiver<(B::Window, SystemTime)> {
+        self.0.changes.subscribe()
+    }
+
+    /// Return a receiver that is marked as changed every time the windows
+    /// of the history are modified: when a window is focused, closed,
+    /// switched, or when the history is imported or resized.
+    pub fn watch_history(&self) -> watch::Receiver<()> {
+        self.0.history_changed.subscribe()
+    }
+
+    /// Notify the subscribers that `window` was activated by a switch.
+    pub fn switched(&self, window: B::Window) {
+        self.0.notify(window);
+    }
+
+    /// Return `true` if the current window is not accepted yet, because
+    /// the tracker is waiting until the modifiers are released.
+    pub fn waiting_modifiers(&self) -> bool {
+        let history = self.0.history.borrow();
+        history.current().is_some() && !history.is_accepted()
+    }
+
+    /// Return the window that lost the focus in the last focus steal, if
+    /// any, and forget it.
+    pub fn take_stolen(&self) -> Option<B::Window> {
+        self.0.stolen.take()
+    }
+
+    /// Return the window that lost the focus in the last focus steal.
+    pub fn stolen(&self) -> Option<B::Window> {
+        self.0.stolen.get()
+    }
+
+    /// Mark the current window with `name`, and return it. A previous
+    /// window with the same mark loses it.
+    pub fn mark(&self, name: &str) -> Option<B::Window> {
+        let window = self.0.history.borrow().current()?;
+        self.0.marks.borrow_mut().insert(name.to_string(), window);
+        Some(window)
+    }
+
+    /// Return the window marked with `name`.
+    pub fn marked(&self, name: &str) -> Option<B::Window> {
+        self.0.marks.borrow().get(name).copied()
+    }
+
+    /// Return the marks, sorted by name.
+    pub fn marks(&self) -> Vec<(String, B::Window)> {
+        let marks = self.0.marks.borrow();
+        marks.iter().map(|(n, &w)| (n.clone(), w)).collect()
+    }
+
+    /// Remove a window from the history, and its marks.
+    pub fn remove(&self, window: B::Window) {
+        self.0.history_mut().remove(window);
+        self.0.workspaces.borrow_mut().remove(&window);
+        self.0.classes.borrow_mut().remove(&window);
+        self.0.marks.borrow_mut().retain(|_, &mut w| w != window);
+
+        if self.0.stolen.get() == Some(window) {
+            self.0.stolen.set(None);
+        }
+    }
+
+    /// Restore the windows received from a previous instance.
+    pub fn restore(&self, windows: &[B::Window]) {
+        self.0.history_mut().restore(windows);
+    }
+
+    /// Replace the history with `windows`, except the current window. See
+    /// [`History::import`].
+    pub fn import(&self, windows: &[B::Window]) {
+        let history = &mut *self.0.history_mut();
+        history.import(windows);
+
+        self.0
+            .workspaces
+            .borrow_mut()
+            .retain(|&w, _| history.contains(w));
+        self.0
+            .classes
+            .borrow_mut()
+            .retain(|&w, _| history.contains(w));
+    }
+}
+
+async fn track<B: DisplayBackend>(
+    cookie: usize,
+    active_window: B::Window,
+    ft: Rc<FocusTrackerInner<B>>,
+) {
+    macro_rules! cookie {
+        () => {
+            if cookie != ft.cookie.get() {
+                return;
+            }
+        };
+    }
+
+    let focused_at = Instant::now();
+
+    // If the `active_window` is the current one, just mark it
+    // as accepted.
+    if ft.history.borrow().current() == Some(active_window) {
+        ft.set_accepted(true);
+        return;
+    }
+
+    // Check the modifiers, so we don't need to wait for changes if
+    // none of them are active.
+    let (modifiers_pressed, workspace, info) = ft
+        .backend
+        .batch(async {
+            tokio::join!(
+                check_modifiers(&ft),
+                current_workspace(&ft),
+                window_info(&ft, active_window),
+            )
+        })
+        .await;
+
+    let Some(mut modifiers_pressed) = modifiers_pressed else {
+        return;
+    };
+
+    cookie!();
+
+    let is_transient = info.as_ref().is_some_and(|i| i.transient_for.is_some());
+
+    let (active_window, info) = match ft.transient.get() {
+        Transient::Parent if is_transient => {
+            let (window, info) = main_window(&ft, active_window, info).await;
+            cookie!();
+
+            if ft.history.borrow().current() == Some(window) {
+                ft.set_accepted(true);
+                return;
+            }
+
+            (window, info)
+        }
+
+        _ => (active_window, info),
+    };
+
+    let decision = match ft.transient.get() {
+        Transient::Skip if is_transient => Decision {
+            record: false,
+            ..Decision::default()
+        },
+
+        _ => decide(&ft, active_window, info.as_ref()),
+    };
+
+    if !decision.record {
+        tracing::debug!("Excluded from the history");
+        return;
+    }
+
+    let workspace = decision.history.or(workspace);
+
+    // A new window focused without a key or a button press steals the
+    // focus from the current one.
+    let is_new = !ft.history.borrow().contains(active_window);
+    let stolen = is_new && !interacted(&ft, focused_at).await;
+    cookie!();
+
+    // Register the new window. The current one is kept in the
+    // history only if it is accepted.
+    {
+        let mut history = ft.history_mut();
+
+        // In a A→B→A sequence, B is treated like a rejected window.
+        let flicker = ft.previous.get().zip(ft.flicker_interval.get());
+        if let Some(((previous, time), interval)) = flicker {
+            if previous == active_window && time.elapsed() < interval {
+                tracing::debug!("Return to the previous window");
+                history.set_accepted(false);
+            }
+        }
+
+        let previous = history.accepted_current();
+        ft.previous.set(previous.map(|w| (w, Instant::now())));
+
+        if let Some(previous) = previous.filter(|_| stolen) {
+            tracing::debug!(?previous, "Focus stolen");
+            ft.stolen.set(Some(previous));
+        }
+
+        history.push(active_window);
+
+        let mut workspaces = ft.workspaces.borrow_mut();
+        if let Some(workspace) = workspace {
+            workspaces.insert(active_window, workspace);
+        }
+
+        workspaces.retain(|&w, _| history.contains(w));
+
+        let mut classes = ft.classes.borrow_mut();
+        if let Some(class) = info.and_then(|i| i.class) {
+            classes.insert(active_window, class);
+        }
+
+        classes.retain(|&w, _| history.contains(w));
+    }
+
+    // The window is accepted only if it is still focused after the
+    // minimum time, after the current cycle, and after an interaction if
+    // hovered windows are ignored. The modifiers are checked again after
+    // it.
+    let hold = [
+        ft.min_dwell.get().map(|d| Instant::now() + d),
+        ft.cycle_end.get().filter(|&end| end > Instant::now()),
+    ];
+
+    let hold = hold.into_iter().flatten().max();
+
+    if hold.is_some() || ft.ignore_hover.get() {
+        ft.set_accepted(false);
+
+        if let Some(until) = hold {
+            tokio::time::sleep_until(until).await;
+            cookie!();
+        }
+
+        if ft.ignore_hover.get() {
+            ft.backend.interaction(interaction_start(focused_at)).await;
+            cookie!();
+        }
+
+        let Some(pressed) = check_modifiers(&ft).await else {
+            return;
+        };
+
+        modifiers_pressed = pressed;
+        cookie!();
+    }
+
+    // If there are no modifiers, notify the change.
+    if !modifiers_pressed || !decision.wait_modifiers {
+        ft.accept(active_window);
+        return;
+    }
+
+    ft.set_accepted(false);
+    tracing::trace!("Waiting until modifiers are released");
+
+    // Mark the new window as `accepted` only when all
+    // keyboard modifiers are released, or when the timeout expires.
+    let released = ft.backend.modifiers_released();
+
+    match ft.accept_timeout.get() {
+        Some(timeout) => {
+            if tokio::time::timeout(timeout, released).await.is_err() {
+                tracing::debug!("Modifiers not released before the timeout");
+            }
+        }
+
+        None => released.await,
+    }
+
+    cookie!();
+
+    // If the keys of a cycle are grabbed, that task decides if the window
+    // is accepted.
+    if ft.cycle_keys.get() {
+        return;
+    }
+
+    ft.accept(active_window);
+    tracing::trace!("Accepted");
+}
+
+/// Wait until a key ends the cycle, or until the modifiers are released.
+async fn cycle_keys<B: DisplayBackend>(
+    ft: Rc<FocusTrackerInner<B>>,
+    origin: Option<B::Window>,
+    released: CycleKey,
+) {
+    let key = match check_modifiers(&ft).await {
+        Some(true) => {
+            let key = async {
+                match ft.backend.cycle_key().await {
+                    Ok(key) => key,
+                    Err(err) => {
+                        tracing::warn!("{}", err);
+                        std::future::pending().await
+                    }
+                }
+            };
+
+            tokio::select! {
+                key = key => Some(key),
+                _ = ft.backend.modifiers_released() => Some(released),
+            }
+        }
+
+        _ => None,
+    };
+
+    ft.cycle_keys.set(false);
+
+    let Some(key) = key else {
+        ft.cycle_changed.notify_waiters();
+        return;
+    };
+
+    tracing::debug!(?key, "Cycle finished");
+
+    // Pending `track` tasks are discarded.
+    ft.cycle_end.set(None);
+    ft.cookie.set(ft.cookie.get() + 1);
+    ft.cycle_changed.notify_waiters();
+
+    let current = ft.history.borrow().current();
+
+    match key {
+        CycleKey::Commit => {
+            if let Some(current) = current {
+                if !ft.history.borrow().is_accepted() {
+                    ft.accept(current);
+                }
+            }
+        }
+
+        CycleKey::Cancel => {
+            if let Some(origin) = origin.filter(|&o| Some(o) != current) {
+                if let Err(err) = ft.backend.activate(origin).await {
+                    tracing::warn!("{}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Earliest time of an interaction that can cause a focus change at
+/// `focused_at`. The window manager changes the focus after the click.
+fn interaction_start(focused_at: Instant) -> Instant {
+    focused_at
+        .checked_sub(INTERACTION_MARGIN)
+        .unwrap_or(focused_at)
+}
+
+/// Return `true` if a key or a button was pressed just before the focus
+/// change at `focused_at`.
+///
+/// If the backend can't detect them (like the X11 backend without XInput),
+/// no window is considered a focus steal.
+async fn interacted<B: DisplayBackend>(ft: &FocusTrackerInner<B>, focused_at: Instant) -> bool {
+    tokio::select! {
+        biased;
+        _ = ft.backend.interaction(interaction_start(focused_at)) => true,
+        _ = std::future::ready(()) => false,
+    }
+}
+
+/// Return `true` if any modifier is pressed, or `None` if the backend
+/// fails.
+async fn check_modifiers<B: DisplayBackend>(ft: &FocusTrackerInner<B>) -> Option<bool> {
+    match ft.backend.modifiers_pressed().await {
+        Ok(pressed) => Some(pressed),
+        Err(err) => {
+            tracing::warn!("{}", err);
+            None
+        }
+    }
+}
+
+/// Workspace of a new active window. Only needed if the history is
+/// per-workspace.
+async fn current_workspace<B: DisplayBackend>(ft: &FocusTrackerInner<B>) -> Option<u32> {
+    if !ft.per_workspace.get() {
+        return None;
+    }
+
+    match ft.backend.current_workspace().await {
+        Ok(workspace) => workspace,
+        Err(err) => {
+            tracing::warn!("{}", err);
+            None
+        }
+    }
+}
+
+/// Follow `WM_TRANSIENT_FOR` from `window` to its main window. Return the
+/// main window and its metadata.
+async fn main_window<B: DisplayBackend>(
+    ft: &FocusTrackerInner<B>,
+    mut window: B::Window,
+    mut info: Option<WindowInfo>,
+) -> (B::Window, Option<WindowInfo>) {
+    // Limit to avoid loops in the chain of transients.
+    const MAX_DEPTH: usize = 8;
+
+    for _ in 0..MAX_DEPTH {
+        let parent = info.as_ref().and_then(|i| i.transient_for);
+        let Some(parent) = parent.and_then(B::window_from_id) else {
+            break;
+        };
+
+        tracing::debug!(?parent, "Transient window");
+        window = parent;
+        info = window_info(ft, parent).await;
+    }
+
+    (window, info)
+}
+
+/// Metadata of a new active window, or `None` if the backend fails.
+async fn window_info<B: DisplayBackend>(
+    ft: &FocusTrackerInner<B>,
+    window: B::Window,
+) -> Option<WindowInfo> {
+    match ft.backend.window_info(window).await {
+        Ok(info) => Some(info),
+        Err(err) => {
+            tracing::warn!("{}", err);
+            None
+        }
+    }
+}
+
+/// Decide how the window is recorded, from the exclusion rules and the
+/// script.
+fn decide<B: DisplayBackend>(
+    ft: &FocusTrackerInner<B>,
+    window: B::Window,
+    info: Option<&WindowInfo>,
+) -> Decision {
+    let script = ft.script.borrow().clone();
+
+    if ft.rules.borrow().is_empty() && script.is_none() {
+        return Decision::default();
+    }
+
+    let Some(info) = info else {
+        return Decision::default();
+    };
+
+    if ft.rules.borrow().excludes(info) {
+        return Decision {
+            record: false,
+            ..Decision::default()
+        };
+    }
+
+    match script.map(|s| s.decide(B::window_id(window), info)) {
+        Some(Ok(decision)) => decision,
+        Some(Err(err)) => {
+            tracing::warn!("Script: {}", err);
+            Decision::default()
+        }
+        None => Decision::default(),
+    }
+}
@@ -0,0 +1,106 @@
+use std::{error::Error, future::Future};
+
+use tokio::time::Instant;
+use xcb::{x, Xid, XidNew};
+
+use crate::backend::{CycleKey, DisplayBackend, FocusEvent, ScreenState, Stats, WindowInfo};
+
+/// Track `_NET_ACTIVE_WINDOW`, and activate windows with EWMH messages.
+impl DisplayBackend for super::DisplayServer {
+    type Window = x::Window;
+
+    async fn focus_event(&self) -> Result<FocusEvent<x::Window>, Box<dyn Error>> {
+        Ok(self.next_focus_event().await?)
+    }
+
+    async fn modifiers_pressed(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.modifiers_pressed().await?)
+    }
+
+    async fn modifiers_released(&self) {
+        self.modifiers_released().await
+    }
+
+    async fn interaction(&self, since: Instant) {
+        self.interaction(since).await
+    }
+
+    async fn cycle_key(&self) -> Result<CycleKey, Box<dyn Error>> {
+        Ok(self.cycle_key().await?)
+    }
+
+    async fn show_overlay(
+        &self,
+        entries: &[(x::Window, String)],
+        selected: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.show_overlay(entries, selected).await?)
+    }
+
+    fn hide_overlay(&self) {
+        self.hide_overlay();
+    }
+
+    async fn flash(&self, window: x::Window, color: &str) -> Result<(), Box<dyn Error>> {
+        Ok(self.flash(window, color).await?)
+    }
+
+    async fn is_available(&self, window: x::Window) -> bool {
+        self.is_available(window).await
+    }
+
+    fn urgent_windows(&self) -> Vec<x::Window> {
+        self.urgent_windows()
+    }
+
+    async fn activate(&self, window: x::Window) -> Result<(), Box<dyn Error>> {
+        Ok(self.activate(window).await?)
+    }
+
+    async fn current_workspace(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self.current_desktop().await?)
+    }
+
+    async fn current_output(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self.pointer_output().await?)
+    }
+
+    async fn window_output(&self, window: x::Window) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self.window_output(window).await?)
+    }
+
+    async fn window_info(&self, window: x::Window) -> Result<WindowInfo, Box<dyn Error>> {
+        Ok(self.window_info(window).await?)
+    }
+
+    fn screens(&self) -> Option<usize> {
+        Some(self.roots().len())
+    }
+
+    async fn screen_states(&self) -> Vec<ScreenState> {
+        self.screen_states().await
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        Some(self.stats())
+    }
+
+    async fn publish_history(&self, windows: &[x::Window]) -> Result<(), Box<dyn Error>> {
+        Ok(self.publish_history(windows).await?)
+    }
+
+    async fn batch<F: Future>(&self, requests: F) -> F::Output {
+        self.batch(requests).await
+    }
+
+    fn window_id(window: x::Window) -> u64 {
+        window.resource_id().into()
+    }
+
+    fn window_from_id(id: u64) -> Option<x::Window> {
+        let id = u32::try_from(id).ok().filter(|&id| id != 0)?;
+
+        // SAFETY: the id is only used to send requests to the X server.
+        Some(unsafe { x::Window::new(id) })
+    }
+}
@@ -0,0 +1,147 @@
+//! Colored border drawn around an activated window for a moment.
+//!
+//! The border is made of four override-redirect windows, one for each
+//! side, so the content of the window is not covered.
+
+use std::time::Duration;
+
+use xcb::x;
+
+use super::{windowinfo, DisplayServer};
+use crate::Error;
+
+/// Width of the border, in pixels.
+const BORDER: u16 = 4;
+
+/// Time that the border is visible.
+const DURATION: Duration = Duration::from_millis(300);
+
+/// Draw a border around `window`, with the `color` name or `#rrggbb`
+/// value, and remove it after a moment.
+pub(super) async fn flash(
+    display: &DisplayServer,
+    window: x::Window,
+    color: &str,
+) -> Result<(), Error> {
+    let frame = windowinfo::top_level(display, window).await?;
+
+    let req = x::GetGeometry {
+        drawable: x::Drawable::Window(frame),
+    };
+
+    let geometry = display.send_request(&req).await?;
+
+    let conn = display.connection();
+    let Some(screen) = conn
+        .get_setup()
+        .roots()
+        .find(|s| s.root() == geometry.root())
+    else {
+        return Ok(());
+    };
+
+    let pixel = alloc_color(display, screen.default_colormap(), color).await?;
+
+    // Outer size of the window, including the border set by the window
+    // manager.
+    let (x, y) = (geometry.x(), geometry.y());
+    let width = geometry.width() + 2 * geometry.border_width();
+    let height = geometry.height() + 2 * geometry.border_width();
+
+    let sides = [
+        (x, y, width, BORDER),
+        (x, y + height.saturating_sub(BORDER) as i16, width, BORDER),
+        (x, y, BORDER, height),
+        (x + width.saturating_sub(BORDER) as i16, y, BORDER, height),
+    ];
+
+    // The windows are destroyed when the guard is dropped, even if the
+    // future is cancelled before the end of the flash.
+    let mut guard = Sides {
+        display: display.clone(),
+        windows: Vec::with_capacity(sides.len()),
+    };
+
+    for (x, y, width, height) in sides {
+        let side = conn.generate_id();
+        display
+            .send_void_request(&x::CreateWindow {
+                depth: x::COPY_FROM_PARENT as u8,
+                wid: side,
+                parent: geometry.root(),
+                x,
+                y,
+                width: width.max(1),
+                height: height.max(1),
+                border_width: 0,
+                class: x::WindowClass::InputOutput,
+                visual: x::COPY_FROM_PARENT,
+                value_list: &[x::Cw::BackPixel(pixel), x::Cw::OverrideRedirect(true)],
+            })
+            .await?;
+
+        guard.windows.push(side);
+    }
+
+    let cookies: Vec<_> = guard
+        .windows
+        .iter()
+        .map(|&window| conn.send_request_checked(&x::MapWindow { window }))
+        .collect();
+
+    display.check_requests(cookies).await?;
+
+    tokio::time::sleep(DURATION).await;
+
+    Ok(())
+}
+
+/// Windows of the border, destroyed when it is dropped.
+struct Sides {
+    display: DisplayServer,
+    windows: Vec<x::Window>,
+}
+
+impl Drop for Sides {
+    fn drop(&mut self) {
+        for &window in &self.windows {
+            self.display
+                .send_request_discarded(&x::DestroyWindow { window });
+        }
+
+        let _ = self.display.connection().flush();
+    }
+}
+
+/// Return the pixel for `color` in `colormap`.
+async fn alloc_color(
+    display: &DisplayServer,
+    cmap: x::Colormap,
+    color: &str,
+) -> Result<u32, Error> {
+    if let Some(hex) = color.strip_prefix('#') {
+        let rgb = match u32::from_str_radix(hex, 16) {
+            Ok(rgb) if hex.len() == 6 => rgb,
+            _ => return Err(Error::Config(format!("Invalid color {color:?}."))),
+        };
+
+        // Components are 16 bits.
+        let component = |shift: u32| ((rgb >> shift) & 0xff) as u16 * 0x101;
+
+        let req = x::AllocColor {
+            cmap,
+            red: component(16),
+            green: component(8),
+            blue: component(0),
+        };
+
+        return Ok(display.send_request(&req).await?.pixel());
+    }
+
+    let req = x::AllocNamedColor {
+        cmap,
+        name: color.as_bytes(),
+    };
+
+    Ok(display.send_request(&req).await?.pixel())
+}
@@ -0,0 +1,98 @@
+//! Tracking of the input focus, for window managers that don't update
+//! `_NET_ACTIVE_WINDOW`.
+//!
+//! The top-level windows are selected for `FocusChange` events. When one of
+//! them, or one of its descendants, receives the focus, the focused window
+//! is read with `GetInputFocus`.
+
+use xcb::{x, Xid};
+
+use super::{windowinfo, DisplayServer, Raise};
+use crate::Error;
+
+/// Source of the focus events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tracking {
+    /// Focus events are not generated by the display server.
+    #[default]
+    Disabled,
+
+    /// Changes in `_NET_ACTIVE_WINDOW`.
+    ActiveWindow,
+
+    /// `FocusIn` events in the top-level windows.
+    InputFocus,
+}
+
+/// Watch the current children of the root windows. New children are
+/// watched when their `CreateNotify` event is received.
+pub(super) async fn watch_children(display: DisplayServer) {
+    for &root in display.roots() {
+        match display.send_request(&x::QueryTree { window: root }).await {
+            Ok(tree) => {
+                for &child in tree.children() {
+                    display.watch_window(child).await;
+                }
+            }
+
+            Err(e) => tracing::warn!("QueryTree: {e}"),
+        }
+    }
+}
+
+/// Return `true` if the event is a change of the input focus, and not a
+/// temporary change caused by a keyboard grab.
+pub(super) fn is_focus_change(event: &x::FocusInEvent) -> bool {
+    matches!(
+        event.mode(),
+        x::NotifyMode::Normal | x::NotifyMode::WhileGrabbed
+    )
+}
+
+/// Return the focused window, and its root window.
+pub(super) async fn input_focus(
+    display: &DisplayServer,
+) -> Result<Option<(x::Window, x::Window)>, Error> {
+    // Values of the focus that are not a window: None and PointerRoot.
+    const POINTER_ROOT: u32 = 1;
+
+    let focus = display.send_request(&x::GetInputFocus {}).await?.focus();
+    if focus.resource_id() <= POINTER_ROOT || display.is_root(focus) {
+        return Ok(None);
+    }
+
+    let req = x::GetGeometry {
+        drawable: x::Drawable::Window(focus),
+    };
+
+    let root = display.send_request(&req).await?.root();
+    Ok(Some((focus, root)))
+}
+
+/// Give the input focus to `window`, and raise its top-level window unless
+/// `raise` is `Never`.
+pub(super) async fn set_input_focus(
+    display: &DisplayServer,
+    window: x::Window,
+    raise: Raise,
+) -> Result<(), Error> {
+    let timestamp = display.server_time().await?;
+
+    if raise != Raise::Never {
+        let frame = windowinfo::top_level(display, window).await?;
+        display
+            .send_void_request(&x::ConfigureWindow {
+                window: frame,
+                value_list: &[x::ConfigWindow::StackMode(x::StackMode::Above)],
+            })
+            .await?;
+    }
+
+    let req = x::SetInputFocus {
+        revert_to: x::InputFocus::PointerRoot,
+        focus: window,
+        time: timestamp,
+    };
+
+    display.send_void_request(&req).await
+}
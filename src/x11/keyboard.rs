@@ -0,0 +1,444 @@
+//! Keys grabbed by the server: the key bindings to switch windows and to
+//! start a chord, and the keys that end a cycle.
+//!
+//! A chord is a key binding followed by a letter or a digit, like `mod4+g`
+//! and then `m`. The second key is the name of a mark. It is read with a
+//! temporary grab of the whole keyboard.
+
+use std::{collections::HashMap, time::Duration};
+
+use xcb::x;
+
+use super::{Command, DisplayServer};
+use crate::{backend::CycleKey, error::Context, Error};
+
+/// Time to press the second key of a chord.
+const CHORD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Keysym of the key that cancels a chord.
+const ESCAPE: u32 = 0xff1b;
+
+/// Keysyms of the keys that end a cycle.
+const CYCLE_KEYSYMS: [(u32, CycleKey); 3] = [
+    (0xff1b, CycleKey::Cancel), // Escape
+    (0xff0d, CycleKey::Commit), // Return
+    (0xff8d, CycleKey::Commit), // KP_Enter
+];
+
+/// Keysyms for the names accepted in [`parse_key_binding`], in addition to
+/// letters, digits, and function keys.
+const KEY_NAMES: &[(&str, u32)] = &[
+    ("tab", 0xff09),
+    ("return", 0xff0d),
+    ("escape", 0xff1b),
+    ("space", 0x0020),
+    ("backspace", 0xff08),
+    ("grave", 0x0060),
+    ("minus", 0x002d),
+    ("equal", 0x003d),
+    ("comma", 0x002c),
+    ("period", 0x002e),
+    ("slash", 0x002f),
+    ("semicolon", 0x003b),
+    ("apostrophe", 0x0027),
+    ("bracketleft", 0x005b),
+    ("bracketright", 0x005d),
+    ("backslash", 0x005c),
+];
+
+/// Key combination grabbed by the server to switch windows.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyBinding {
+    modifiers: x::ModMask,
+    keysym: u32,
+}
+
+/// Action of the chords started with a key binding. The second key of the
+/// chord is the name of the mark.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chord {
+    /// Activate the window with the mark.
+    Goto,
+
+    /// Mark the current window.
+    Mark,
+}
+
+/// Action of a key binding grabbed in the root windows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum KeyAction {
+    Switch,
+    Chord(Chord),
+}
+
+/// Key grabbed with [`grab_binding`].
+pub(super) struct BoundKey {
+    keycode: x::Keycode,
+    modifiers: x::ModMask,
+    action: KeyAction,
+}
+
+/// Parse a key combination, like `mod1+Tab`.
+///
+/// The last item is the key, which can be a letter, a digit, a function
+/// key (`F1` to `F12`), a name like `Tab` or `space`, or a keysym in
+/// hexadecimal (`0xff09`). The other items are modifiers, with the names
+/// of [`parse_modifier`](super::parse_modifier).
+pub fn parse_key_binding(binding: &str) -> Result<KeyBinding, String> {
+    let mut items: Vec<&str> = binding.split('+').map(str::trim).collect();
+    let key = items.pop().unwrap_or_default();
+
+    let mut modifiers = x::ModMask::empty();
+    for name in items {
+        modifiers |= super::parse_modifier(name)?;
+    }
+
+    let keysym = parse_keysym(key).ok_or_else(|| format!("Invalid key {key:?} in {binding:?}."))?;
+
+    Ok(KeyBinding { modifiers, keysym })
+}
+
+fn parse_keysym(key: &str) -> Option<u32> {
+    let lower = key.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+
+    if let &[c] = lower.as_bytes() {
+        return c.is_ascii_alphanumeric().then_some(u32::from(c));
+    }
+
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u32>().ok()) {
+        return (1..=12).contains(&n).then_some(0xffbe + n - 1);
+    }
+
+    KEY_NAMES
+        .iter()
+        .find(|(name, _)| *name == lower)
+        .map(|&(_, keysym)| keysym)
+}
+
+/// Grab `binding` in all root windows.
+///
+/// Caps Lock and Num Lock are ignored, so the binding works when they are
+/// active.
+pub(super) async fn grab_binding(
+    display: &DisplayServer,
+    binding: KeyBinding,
+    action: KeyAction,
+) -> Result<(), Error> {
+    const LOCKS: [x::ModMask; 4] = [
+        x::ModMask::empty(),
+        x::ModMask::LOCK,
+        x::ModMask::N2,
+        x::ModMask::LOCK.union(x::ModMask::N2),
+    ];
+
+    let keycodes: Vec<_> = keycodes(display, &[binding.keysym])
+        .await?
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+
+    if keycodes.is_empty() {
+        return Err(Error::Config(format!(
+            "No key for the keysym 0x{:x}.",
+            binding.keysym
+        )));
+    }
+
+    for &key in &keycodes {
+        for &root in display.roots() {
+            for locks in LOCKS {
+                let req = x::GrabKey {
+                    owner_events: false,
+                    grab_window: root,
+                    modifiers: binding.modifiers | locks,
+                    key,
+                    pointer_mode: x::GrabMode::Async,
+                    keyboard_mode: x::GrabMode::Async,
+                };
+
+                // The request fails if another client grabbed the key.
+                display
+                    .send_void_request(&req)
+                    .await
+                    .context("Grab the key binding")?;
+            }
+        }
+    }
+
+    display
+        .0
+        .bound_keys
+        .borrow_mut()
+        .extend(keycodes.into_iter().map(|keycode| BoundKey {
+            keycode,
+            modifiers: binding.modifiers,
+            action,
+        }));
+
+    Ok(())
+}
+
+/// Return the action of the key binding pressed in `event`, if any.
+pub(super) fn bound_action(display: &DisplayServer, event: &x::KeyPressEvent) -> Option<KeyAction> {
+    // Caps Lock and Num Lock are ignored, like in `grab_binding`.
+    let locks = x::ModMask::LOCK | x::ModMask::N2;
+    let modifiers = x::ModMask::from_bits_truncate(event.state().bits()) - locks;
+
+    display
+        .0
+        .bound_keys
+        .borrow()
+        .iter()
+        .find(|k| k.keycode == event.detail() && k.modifiers == modifiers)
+        .map(|k| k.action)
+}
+
+/// Read the second key of a chord started at `time`, and send the command
+/// for the mark.
+pub(super) async fn read_chord(display: DisplayServer, chord: Chord, time: x::Timestamp) {
+    let name = match chord_key(&display, time).await {
+        Ok(Some(name)) => name,
+        Ok(None) => {
+            tracing::debug!("Chord cancelled");
+            return;
+        }
+
+        Err(e) => {
+            tracing::warn!("Chord: {e}");
+            return;
+        }
+    };
+
+    let command = match chord {
+        Chord::Goto => Command::Goto(name),
+        Chord::Mark => Command::Mark(name),
+    };
+
+    let _ = display.0.commands.send(command);
+}
+
+/// Grab the keyboard, and return the mark name for the next key. It
+/// returns `None` if the key is not a letter or a digit, or if no key is
+/// pressed before [`CHORD_TIMEOUT`].
+async fn chord_key(display: &DisplayServer, time: x::Timestamp) -> Result<Option<String>, Error> {
+    // The keys are received by `handle_key_press` while the guard is alive.
+    let _grab = KeyboardGrab::new(display);
+
+    let req = x::GrabKeyboard {
+        owner_events: false,
+        grab_window: display.roots()[0],
+        time,
+        pointer_mode: x::GrabMode::Async,
+        keyboard_mode: x::GrabMode::Async,
+    };
+
+    let status = display.send_request(&req).await?.status();
+    if status != x::GrabStatus::Success {
+        tracing::warn!("Chord: the keyboard can't be grabbed ({status:?}).");
+        return Ok(None);
+    }
+
+    let next_key = async {
+        loop {
+            display.0.chord_key_pressed.notified().await;
+
+            let Some(keycode) = display.0.chord_key.take() else {
+                continue;
+            };
+
+            match keysym(display, keycode).await? {
+                // Modifiers pressed after the first key.
+                keysym if is_modifier(keysym) => continue,
+                ESCAPE => return Ok(None),
+                keysym => return Ok(mark_name(keysym)),
+            }
+        }
+    };
+
+    tokio::time::timeout(CHORD_TIMEOUT, next_key)
+        .await
+        .unwrap_or(Ok(None))
+}
+
+/// Keyboard grabbed to read a chord. It is released when the value is
+/// dropped.
+struct KeyboardGrab(DisplayServer);
+
+impl KeyboardGrab {
+    fn new(display: &DisplayServer) -> Self {
+        display.0.chord_key.set(None);
+        display.0.chord_active.set(true);
+        KeyboardGrab(display.clone())
+    }
+}
+
+impl Drop for KeyboardGrab {
+    fn drop(&mut self) {
+        self.0 .0.chord_active.set(false);
+        self.0.send_request_discarded(&x::UngrabKeyboard {
+            time: x::CURRENT_TIME,
+        });
+
+        let _ = self.0.connection().flush();
+    }
+}
+
+/// Return the first keysym of `keycode`.
+async fn keysym(display: &DisplayServer, keycode: x::Keycode) -> Result<u32, Error> {
+    let req = x::GetKeyboardMapping {
+        first_keycode: keycode,
+        count: 1,
+    };
+
+    let reply = display.send_request(&req).await?;
+    Ok(reply.keysyms().first().copied().unwrap_or(0))
+}
+
+/// Return `true` if `keysym` is a modifier, like `Shift_L` or
+/// `ISO_Level3_Shift`.
+fn is_modifier(keysym: u32) -> bool {
+    (0xffe1..=0xffee).contains(&keysym) || (0xfe01..=0xfe0f).contains(&keysym)
+}
+
+/// Return the mark name for `keysym`, if it is a letter or a digit.
+fn mark_name(keysym: u32) -> Option<String> {
+    char::from_u32(keysym)
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase().to_string())
+}
+
+/// Passive grab of the keys that end a cycle, in all root windows. The keys
+/// are released when it is dropped.
+pub(super) struct KeyGrab {
+    display: DisplayServer,
+    keycodes: Vec<x::Keycode>,
+}
+
+impl KeyGrab {
+    /// Grab the keys, with any combination of modifiers, so they can be
+    /// pressed while the modifiers of the cycle are held.
+    pub(super) async fn new(display: &DisplayServer) -> Result<Self, Error> {
+        let keysyms = CYCLE_KEYSYMS.map(|(keysym, _)| keysym);
+        let keys: HashMap<_, _> = keycodes(display, &keysyms)
+            .await?
+            .into_iter()
+            .map(|(key, index)| (key, CYCLE_KEYSYMS[index].1))
+            .collect();
+
+        // The grab is created before the requests, so the keys are
+        // released if the future is dropped while they are sent.
+        let grab = KeyGrab {
+            display: display.clone(),
+            keycodes: keys.keys().copied().collect(),
+        };
+
+        display.0.grabbed_keys.replace(keys);
+
+        for &key in &grab.keycodes {
+            for &root in display.roots() {
+                let req = x::GrabKey {
+                    owner_events: false,
+                    grab_window: root,
+                    modifiers: x::ModMask::ANY,
+                    key,
+                    pointer_mode: x::GrabMode::Async,
+                    keyboard_mode: x::GrabMode::Async,
+                };
+
+                // The request fails if another client grabbed the key.
+                if let Err(e) = display.send_void_request(&req).await {
+                    tracing::warn!("Failed to grab keycode {key}: {e}");
+                }
+            }
+        }
+
+        Ok(grab)
+    }
+}
+
+impl Drop for KeyGrab {
+    fn drop(&mut self) {
+        let conn = self.display.connection();
+
+        for &key in &self.keycodes {
+            for &root in self.display.roots() {
+                conn.send_request(&x::UngrabKey {
+                    key,
+                    grab_window: root,
+                    modifiers: x::ModMask::ANY,
+                });
+            }
+        }
+
+        self.display.0.grabbed_keys.borrow_mut().clear();
+        let _ = conn.flush();
+    }
+}
+
+/// Return the keycodes that produce any of the `keysyms`, with the position
+/// of the keysym in the slice.
+async fn keycodes(
+    display: &DisplayServer,
+    keysyms: &[u32],
+) -> Result<Vec<(x::Keycode, usize)>, Error> {
+    let setup = display.connection().get_setup();
+    let (first_keycode, last_keycode) = (setup.min_keycode(), setup.max_keycode());
+
+    let req = x::GetKeyboardMapping {
+        first_keycode,
+        count: last_keycode - first_keycode + 1,
+    };
+
+    let reply = display.send_request(&req).await?;
+    let per_keycode = usize::from(reply.keysyms_per_keycode()).max(1);
+
+    let mapping = (first_keycode..=last_keycode).zip(reply.keysyms().chunks(per_keycode));
+
+    Ok(mapping
+        .filter_map(|(keycode, symbols)| {
+            let index = keysyms.iter().position(|k| symbols.contains(k))?;
+            Some((keycode, index))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bindings() {
+        let binding = parse_key_binding("mod1+Tab").unwrap();
+        assert_eq!(binding.modifiers, x::ModMask::N1);
+        assert_eq!(binding.keysym, 0xff09);
+
+        let binding = parse_key_binding("ctrl + shift + F3").unwrap();
+        assert_eq!(binding.modifiers, x::ModMask::CONTROL | x::ModMask::SHIFT);
+        assert_eq!(binding.keysym, 0xffc0);
+
+        assert_eq!(parse_key_binding("mod4+A").unwrap().keysym, 0x61);
+        assert_eq!(parse_key_binding("0xff09").unwrap().keysym, 0xff09);
+
+        assert!(parse_key_binding("mod1+").is_err());
+        assert!(parse_key_binding("hyper+Tab").is_err());
+        assert!(parse_key_binding("mod1+F13").is_err());
+    }
+
+    #[test]
+    fn mark_names_for_keysyms() {
+        assert_eq!(mark_name(0x61).as_deref(), Some("a"));
+        assert_eq!(mark_name(0x4d).as_deref(), Some("m"));
+        assert_eq!(mark_name(0x37).as_deref(), Some("7"));
+
+        assert_eq!(mark_name(0x20), None);
+        assert_eq!(mark_name(ESCAPE), None);
+
+        assert!(is_modifier(0xffe1)); // Shift_L
+        assert!(is_modifier(0xfe03)); // ISO_Level3_Shift
+        assert!(!is_modifier(0x61));
+    }
+}
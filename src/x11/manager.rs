@@ -0,0 +1,138 @@
+//! Manager selection, used to detect other instances of the server.
+//!
+//! When an instance is replaced, the old one writes its history in the
+//! `HISTORY` property of the new owner of the selection, and then destroys
+//! its own window.
+
+use std::time::Duration;
+
+use xcb::{x, Xid};
+
+use crate::Error;
+
+/// Time to wait until the previous owner releases the selection.
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Return the current owner of the manager selection.
+pub(super) async fn owner(display: &super::DisplayServer) -> Result<Option<x::Window>, Error> {
+    let owner = display
+        .send_request(&x::GetSelectionOwner {
+            selection: display.atoms().manager,
+        })
+        .await?
+        .owner();
+
+    Ok(if owner.is_none() { None } else { Some(owner) })
+}
+
+/// Acquire the manager selection. If there is a previous owner, wait until
+/// it is released, and then return its history.
+pub(super) async fn acquire(display: &super::DisplayServer) -> Result<Vec<x::Window>, Error> {
+    let atoms = display.atoms();
+    let window = display.0.window;
+
+    let previous = owner(display).await?;
+
+    if let Some(previous) = previous {
+        // Receive a DestroyNotify when the previous owner is released.
+        display.0.previous_manager.set(Some(previous));
+
+        display
+            .send_void_request(&x::ChangeWindowAttributes {
+                window: previous,
+                value_list: &[x::Cw::EventMask(x::EventMask::STRUCTURE_NOTIFY)],
+            })
+            .await?;
+    }
+
+    let time = display.server_time().await?;
+
+    display
+        .send_void_request(&x::SetSelectionOwner {
+            owner: window,
+            selection: atoms.manager,
+            time,
+        })
+        .await?;
+
+    // Announce the new owner, as described in the ICCCM.
+    //
+    // https://tronche.com/gui/x/icccm/sec-2.html#s-2.8
+    let root = display.roots()[0];
+    let event = x::ClientMessageEvent::new(
+        root,
+        atoms.manager_message,
+        x::ClientMessageData::Data32([
+            time,
+            atoms.manager.resource_id(),
+            window.resource_id(),
+            0,
+            0,
+        ]),
+    );
+
+    display
+        .send_void_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(root),
+            event_mask: x::EventMask::STRUCTURE_NOTIFY,
+            event: &event,
+        })
+        .await?;
+
+    if previous.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let released = display.0.manager_released.notified();
+    if tokio::time::timeout(RELEASE_TIMEOUT, released)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Previous server did not release the manager selection.");
+        return Ok(Vec::new());
+    }
+
+    let req = x::GetProperty {
+        delete: true,
+        window,
+        property: atoms.history,
+        r#type: x::ATOM_WINDOW,
+        long_offset: 0,
+        long_length: u32::MAX,
+    };
+
+    let reply = display.send_request(&req).await?;
+    let windows = reply.value().to_vec();
+
+    for &window in &windows {
+        display.watch_window(window).await;
+    }
+
+    Ok(windows)
+}
+
+/// Called when the manager selection is taken by a new instance. The history
+/// is sent to the new owner, and the window of this instance is destroyed.
+pub(super) async fn hand_over(
+    display: &super::DisplayServer,
+    windows: &[x::Window],
+) -> Result<(), Error> {
+    if let Some(new_owner) = owner(display).await? {
+        display
+            .send_void_request(&x::ChangeProperty {
+                mode: x::PropMode::Replace,
+                window: new_owner,
+                property: display.atoms().history,
+                r#type: x::ATOM_WINDOW,
+                data: windows,
+            })
+            .await?;
+    }
+
+    display
+        .send_void_request(&x::DestroyWindow {
+            window: display.0.window,
+        })
+        .await
+}
@@ -1,16 +1,52 @@
-mod focustracker;
-mod rqueue;
+mod backend;
+mod flash;
+mod focus;
+mod keyboard;
+mod manager;
+mod outputs;
+mod overlay;
+pub mod property;
+mod replies;
 mod setup;
+mod stacking;
+mod thread;
+mod urgency;
+mod windowinfo;
 
-use std::{rc::Rc, sync::Mutex};
+pub use focus::Tracking;
+pub use keyboard::{parse_key_binding, Chord, KeyBinding};
+pub use replies::{DEFAULT_DEADLINE, DEFAULT_MAX_PENDING};
+pub use stacking::Raise;
+pub use thread::DisplayThread;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    future::Future,
+    rc::Rc,
+    time::Duration,
+};
 
 use tokio::{
     io::{unix::AsyncFd, Interest},
-    sync::{oneshot, watch, Notify},
+    sync::{mpsc, watch, Notify},
+    time::Instant,
 };
 
-use xcb::x;
+use xcb::{x, xinput, Xid};
+
+use crate::{
+    backend::{CycleKey, FocusEvent, ScreenState, Stats, WindowInfo},
+    error::Context,
+    rules::ActivationRules,
+    Error,
+};
 
+/// Connection to the X server.
+///
+/// Clones share the same connection. Events and replies are processed by
+/// [`main_loop`](Self::main_loop), which must be running to complete the
+/// futures returned by the other methods.
 #[derive(Clone)]
 pub struct DisplayServer(Rc<DisplayInner>);
 
@@ -18,41 +54,332 @@ struct DisplayInner {
     connection: AsyncFd<xcb::Connection>,
     atoms: Atoms,
     roots: Box<[x::Window]>,
-    requests: rqueue::Queue<DisplayServer>,
-    focus_tracker: focustracker::FocusTracker,
-    xkb_state_watcher: Mutex<Option<watch::Sender<x::ModMask>>>,
-    switch_command: Notify,
+    wm_selections: Box<[x::Atom]>,
+    replies: replies::Replies,
+    batch_depth: Cell<usize>,
+    unflushed: Cell<bool>,
+    stats: RefCell<Stats>,
+    xkb_state_watcher: RefCell<Option<watch::Sender<x::ModMask>>>,
+    tracking: Cell<Tracking>,
+    active_window_root: Cell<Option<x::Window>>,
+    destroyed_windows: RefCell<VecDeque<x::Window>>,
+    window_roots: RefCell<HashMap<x::Window, x::Window>>,
+    monitors: RefCell<HashMap<x::Window, Rc<[outputs::Monitor]>>>,
+    focus_event: Notify,
+    focus_quiet_time: Cell<Duration>,
+    commands: mpsc::UnboundedSender<Command>,
+    commands_receiver: Cell<Option<mpsc::UnboundedReceiver<Command>>>,
+    focus_output: Cell<bool>,
+    change_desktop: Cell<bool>,
+    unminimize: Cell<bool>,
+    raise: Cell<Raise>,
+    warp_pointer: Cell<bool>,
+    activation_rules: RefCell<ActivationRules>,
+    modifier_mask: Cell<x::ModMask>,
+    grabbed_keys: RefCell<HashMap<x::Keycode, CycleKey>>,
+    bound_keys: RefCell<Vec<keyboard::BoundKey>>,
+    chord_active: Cell<bool>,
+    chord_key: Cell<Option<x::Keycode>>,
+    chord_key_pressed: Notify,
+    pressed_key: Cell<Option<CycleKey>>,
+    key_pressed: Notify,
+    listening_interactions: Cell<bool>,
+    last_interaction: Cell<Option<Instant>>,
+    interacted: Notify,
+    overlay: overlay::Overlay,
+    urgency: urgency::Urgency,
+    window: x::Window,
+    timestamp: Cell<x::Timestamp>,
+    timestamp_received: Notify,
+    previous_manager: Cell<Option<x::Window>>,
+    manager_released: Notify,
+    stop: Notify,
+}
+
+/// Modifiers that delay the acceptance of a window. Caps Lock (`LOCK`) and
+/// Num Lock (usually `N2`) are excluded, since they can be active for a
+/// long time.
+pub const DEFAULT_MODIFIER_MASK: x::ModMask = x::ModMask::SHIFT
+    .union(x::ModMask::CONTROL)
+    .union(x::ModMask::N1)
+    .union(x::ModMask::N3)
+    .union(x::ModMask::N4)
+    .union(x::ModMask::N5);
+
+/// Time without focus changes before the focused window is read, so a
+/// burst of changes, like the ones after a workspace switch, is reported
+/// once.
+pub const DEFAULT_FOCUS_QUIET_TIME: Duration = Duration::from_millis(20);
+
+/// Parse the name of a modifier, like `shift` or `mod4`.
+pub fn parse_modifier(name: &str) -> Result<x::ModMask, String> {
+    let modifier = match name.to_ascii_lowercase().as_str() {
+        "shift" => x::ModMask::SHIFT,
+        "lock" => x::ModMask::LOCK,
+        "control" | "ctrl" => x::ModMask::CONTROL,
+        "mod1" => x::ModMask::N1,
+        "mod2" => x::ModMask::N2,
+        "mod3" => x::ModMask::N3,
+        "mod4" => x::ModMask::N4,
+        "mod5" => x::ModMask::N5,
+        _ => {
+            return Err(format!(
+                "Invalid modifier {name:?}. Expected shift, lock, control, or mod1 to mod5."
+            ))
+        }
+    };
+
+    Ok(modifier)
 }
 
+/// Steps of the activation of a window. See
+/// [`activation_options`](DisplayServer::activation_options).
+#[derive(Clone, Copy, Debug)]
+pub struct ActivationOptions {
+    pub focus_output: bool,
+    pub unminimize: bool,
+    pub change_desktop: bool,
+    pub raise: Raise,
+    pub warp_pointer: bool,
+}
+
+/// Commands received by the server.
+pub enum Command {
+    /// Activate the `nth` window in the history.
+    Switch(usize),
+
+    /// Another instance took the manager selection.
+    HandOver,
+
+    /// Line written to the `_X11_ALTERNATE_FOCUS_COMMAND` property of a
+    /// root window. See [`crate::socket::Request::parse`].
+    Text(String),
+
+    /// Activate the window with a mark, from a chord.
+    Goto(String),
+
+    /// Mark the current window, from a chord.
+    Mark(String),
+}
+
+/// Atoms used by the server, interned when the connection is created.
 pub struct Atoms {
     pub net_active_window: x::Atom,
+    pub net_wm_name: x::Atom,
+    pub net_wm_desktop: x::Atom,
+    pub net_wm_pid: x::Atom,
+    pub net_wm_state: x::Atom,
+    pub net_wm_state_demands_attention: x::Atom,
+    pub net_wm_state_hidden: x::Atom,
+    pub net_client_list: x::Atom,
+    pub net_client_list_stacking: x::Atom,
+    pub net_restack_window: x::Atom,
+    pub net_wm_window_type: x::Atom,
+
+    /// Atoms for the values of `_NET_WM_WINDOW_TYPE`, with their names.
+    pub window_types: Box<[(x::Atom, &'static str)]>,
+    pub net_current_desktop: x::Atom,
+    pub wm_state: x::Atom,
+    pub utf8_string: x::Atom,
+    pub i3_socket_path: x::Atom,
     pub switch_command: x::Atom,
+    pub manager: x::Atom,
+    pub history: x::Atom,
+    pub published_history: x::Atom,
+    pub command: x::Atom,
+    pub timestamp: x::Atom,
+    pub manager_message: x::Atom,
+    pub net_supporting_wm_check: x::Atom,
+    pub net_wm_cm_s0: x::Atom,
 }
 
 impl DisplayServer {
-    pub fn new() -> Result<Self, xcb::Error> {
-        let (conn, _) =
-            xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Xkb], &[])?;
+    /// Connect to the display in `$DISPLAY`.
+    pub fn new() -> Result<Self, Error> {
+        Self::connect(None)
+    }
 
-        setup::use_xkb(&conn)?;
+    /// Connect to the display `name`, or to the one in `$DISPLAY` if it is
+    /// `None`.
+    pub fn connect(name: Option<&str>) -> Result<Self, Error> {
+        let (conn, _) = xcb::Connection::connect_with_extensions(
+            name,
+            &[xcb::Extension::Xkb],
+            &[
+                xcb::Extension::RandR,
+                xcb::Extension::Composite,
+                xcb::Extension::Render,
+                xcb::Extension::Input,
+            ],
+        )?;
 
-        let atoms = setup::intern_atoms(&conn)?;
-        let roots = setup::listen_root_properties(&conn)?;
+        let roots = setup::roots(&conn);
+        let (window, create_window) = setup::create_window(&conn, roots[0]);
+
+        setup::use_xkb(&conn).context("XKB extension")?;
+
+        let atoms = setup::intern_atoms(&conn).context("Intern atoms")?;
+        let wm_selections = setup::wm_selections(&conn, roots.len()).context("Intern atoms")?;
+
+        // The replies of the atoms are received after the error of the
+        // window, if any.
+        conn.check_request(create_window)
+            .map_err(Error::from)
+            .context("Create window")?;
         let connection = AsyncFd::with_interest(conn, Interest::READABLE).unwrap();
+        let (commands, commands_receiver) = mpsc::unbounded_channel();
 
         let display = DisplayInner {
             connection,
             atoms,
             roots,
-            requests: rqueue::Queue::new(),
-            focus_tracker: Default::default(),
+            wm_selections,
+            replies: Default::default(),
+            batch_depth: Default::default(),
+            unflushed: Default::default(),
+            stats: Default::default(),
             xkb_state_watcher: Default::default(),
-            switch_command: Default::default(),
+            tracking: Default::default(),
+            active_window_root: Default::default(),
+            destroyed_windows: Default::default(),
+            window_roots: Default::default(),
+            monitors: Default::default(),
+            focus_event: Default::default(),
+            focus_quiet_time: Cell::new(DEFAULT_FOCUS_QUIET_TIME),
+            commands,
+            commands_receiver: Cell::new(Some(commands_receiver)),
+            focus_output: Default::default(),
+            change_desktop: Default::default(),
+            unminimize: Default::default(),
+            raise: Default::default(),
+            warp_pointer: Default::default(),
+            activation_rules: Default::default(),
+            modifier_mask: Cell::new(DEFAULT_MODIFIER_MASK),
+            grabbed_keys: Default::default(),
+            bound_keys: Default::default(),
+            chord_active: Default::default(),
+            chord_key: Default::default(),
+            chord_key_pressed: Default::default(),
+            pressed_key: Default::default(),
+            key_pressed: Default::default(),
+            listening_interactions: Default::default(),
+            last_interaction: Default::default(),
+            interacted: Default::default(),
+            overlay: Default::default(),
+            urgency: Default::default(),
+            window,
+            timestamp: Cell::new(x::CURRENT_TIME),
+            timestamp_received: Default::default(),
+            previous_manager: Default::default(),
+            manager_released: Default::default(),
+            stop: Default::default(),
         };
 
         Ok(DisplayServer(Rc::new(display)))
     }
 
+    /// Receive the commands sent to the root windows, the changes in the
+    /// monitors layout, and the urgency of the clients. Needed only in the
+    /// server.
+    ///
+    /// The focus changes from `tracking` are received in
+    /// [`next_focus_event`](Self::next_focus_event).
+    pub async fn listen_root_events(&self, tracking: Tracking) -> Result<(), Error> {
+        self.0.tracking.set(tracking);
+
+        // RandR is optional.
+        if let Err(e) = outputs::listen_changes(self).await {
+            tracing::warn!("RandR: {e}");
+        }
+
+        setup::listen_root_properties(self, self.root_event_mask())
+            .await
+            .context("Listen root events")?;
+
+        tokio::task::spawn_local(urgency::update_clients(self.clone()));
+
+        if tracking == Tracking::InputFocus {
+            tokio::task::spawn_local(focus::watch_children(self.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Receive the key and button presses, with the raw events of the
+    /// XInput extension. Needed by [`interaction`](Self::interaction).
+    pub async fn listen_interactions(&self) -> Result<(), Error> {
+        if !self
+            .connection()
+            .active_extensions()
+            .any(|e| e == xcb::Extension::Input)
+        {
+            return Err(Error::Config(
+                "ignore_hover needs the XInput extension.".into(),
+            ));
+        }
+
+        let req = xinput::XiQueryVersion {
+            major_version: 2,
+            minor_version: 0,
+        };
+
+        let reply = self.send_request(&req).await?;
+        if reply.major_version() < 2 {
+            return Err(Error::Config("ignore_hover needs XInput 2.".into()));
+        }
+
+        let mask = xinput::XiEventMask::RAW_KEY_PRESS | xinput::XiEventMask::RAW_BUTTON_PRESS;
+        let masks = [xinput::EventMaskBuf::new(
+            xinput::Device::AllMaster,
+            &[mask],
+        )];
+
+        for &root in self.roots() {
+            self.send_void_request(&xinput::XiSelectEvents {
+                window: root,
+                masks: &masks,
+            })
+            .await?;
+        }
+
+        self.0.listening_interactions.set(true);
+        Ok(())
+    }
+
+    /// Return `true` if the raw events are selected with
+    /// [`listen_interactions`](Self::listen_interactions).
+    pub fn listening_interactions(&self) -> bool {
+        self.0.listening_interactions.get()
+    }
+
+    /// Wait until a key or a pointer button is pressed after `since`. It
+    /// returns immediately if the raw events are not selected with
+    /// [`listen_interactions`](Self::listen_interactions).
+    pub async fn interaction(&self, since: Instant) {
+        if !self.0.listening_interactions.get() {
+            return;
+        }
+
+        loop {
+            let interacted = self.0.interacted.notified();
+
+            if self.0.last_interaction.get().is_some_and(|t| t >= since) {
+                return;
+            }
+
+            interacted.await;
+        }
+    }
+
+    /// Events selected in the root windows, in addition to the changes in
+    /// their properties.
+    fn root_event_mask(&self) -> x::EventMask {
+        match self.0.tracking.get() {
+            Tracking::InputFocus => x::EventMask::SUBSTRUCTURE_NOTIFY,
+            _ => x::EventMask::empty(),
+        }
+    }
+
     #[inline]
     pub fn connection(&self) -> &xcb::Connection {
         self.0.connection.get_ref()
@@ -60,7 +387,7 @@ impl DisplayServer {
 
     #[inline]
     fn is_root(&self, window: x::Window) -> bool {
-        self.0.roots.iter().any(|&r| r == window)
+        self.0.roots.contains(&window)
     }
 
     #[inline]
@@ -73,9 +400,151 @@ impl DisplayServer {
         &self.0.roots[..]
     }
 
-    #[inline]
-    pub fn switch_command(&self) -> &Notify {
-        &self.0.switch_command
+    /// Return the receiver for the commands sent to the server. It can be
+    /// taken only once.
+    pub fn take_commands(&self) -> Option<mpsc::UnboundedReceiver<Command>> {
+        self.0.commands_receiver.take()
+    }
+
+    /// Move the pointer to the monitor of the target window before
+    /// activating it.
+    pub fn set_focus_output(&self, enabled: bool) {
+        self.0.focus_output.set(enabled);
+    }
+
+    /// Change the current desktop before activating a window in another
+    /// desktop, for window managers that ignore `_NET_ACTIVE_WINDOW` in
+    /// that case.
+    pub fn set_change_desktop(&self, enabled: bool) {
+        self.0.change_desktop.set(enabled);
+    }
+
+    /// Restore minimized windows (with `_NET_WM_STATE_HIDDEN`) before
+    /// activating them.
+    pub fn set_unminimize(&self, enabled: bool) {
+        self.0.unminimize.set(enabled);
+    }
+
+    /// Wait until the focus changes stop for `time` before reading the
+    /// focused window. If it is zero, every change is read. The default is
+    /// [`DEFAULT_FOCUS_QUIET_TIME`].
+    pub fn set_focus_quiet_time(&self, time: Duration) {
+        self.0.focus_quiet_time.set(time);
+    }
+
+    /// Move the pointer to the center of the activated windows.
+    pub fn set_warp_pointer(&self, enabled: bool) {
+        self.0.warp_pointer.set(enabled);
+    }
+
+    /// Change the stacking order after activating a window.
+    pub fn set_raise(&self, raise: Raise) {
+        self.0.raise.set(raise);
+    }
+
+    /// Change the activation options for the windows matched by `rules`.
+    pub fn set_activation_rules(&self, rules: ActivationRules) {
+        self.0.activation_rules.replace(rules);
+    }
+
+    /// Return the options to activate `window`, with the changes of the
+    /// activation rules that match it.
+    pub async fn activation_options(&self, window: x::Window) -> ActivationOptions {
+        let mut options = ActivationOptions {
+            focus_output: self.0.focus_output.get(),
+            unminimize: self.0.unminimize.get(),
+            change_desktop: self.0.change_desktop.get(),
+            raise: self.0.raise.get(),
+            warp_pointer: self.0.warp_pointer.get(),
+        };
+
+        let rules = self.0.activation_rules.borrow().clone();
+        if rules.is_empty() {
+            return options;
+        }
+
+        let overrides = match self.window_info(window).await {
+            Ok(info) => rules.overrides(&info),
+            Err(e) => {
+                tracing::debug!(window = window.resource_id(), "Activation rules: {e}");
+                return options;
+            }
+        };
+
+        options.focus_output = overrides.focus_output.unwrap_or(options.focus_output);
+        options.raise = overrides.raise.unwrap_or(options.raise);
+        options.warp_pointer = overrides.warp_pointer.unwrap_or(options.warp_pointer);
+        options.change_desktop = overrides.change_desktop.unwrap_or(options.change_desktop);
+        options.unminimize = overrides.unminimize.unwrap_or(options.unminimize);
+        options
+    }
+
+    /// Set the modifiers checked by [`modifiers_pressed`](Self::modifiers_pressed)
+    /// and [`modifiers_released`](Self::modifiers_released).
+    pub fn set_modifier_mask(&self, mask: x::ModMask) {
+        self.0.modifier_mask.set(mask);
+    }
+
+    /// Grab `binding` in the root windows. When it is pressed, a
+    /// [`Command::Switch`] is sent to the server.
+    pub async fn grab_key_binding(&self, binding: KeyBinding) -> Result<(), Error> {
+        keyboard::grab_binding(self, binding, keyboard::KeyAction::Switch).await
+    }
+
+    /// Grab `binding` in the root windows to start a chord. The next key
+    /// is the name of a mark, and the [`Command::Goto`] or
+    /// [`Command::Mark`] for it is sent to the server.
+    pub async fn grab_chord(&self, binding: KeyBinding, chord: Chord) -> Result<(), Error> {
+        keyboard::grab_binding(self, binding, keyboard::KeyAction::Chord(chord)).await
+    }
+
+    /// Revert the changes made in the display, and stop the main loop.
+    ///
+    /// The events selected in the root windows and in XKB are cleared, and
+    /// the manager selection is released. The main loop is stopped after
+    /// the X server processes the requests.
+    pub async fn shutdown(&self) {
+        if let Err(e) = self
+            .send_void_request(&xkb_select_events_request(false))
+            .await
+        {
+            tracing::warn!("XKB events: {e}");
+        }
+
+        for &root in self.roots() {
+            let req = x::ChangeWindowAttributes {
+                window: root,
+                value_list: &[x::Cw::EventMask(x::EventMask::NO_EVENT)],
+            };
+
+            if let Err(e) = self.send_void_request(&req).await {
+                tracing::warn!("Root event mask: {e}");
+            }
+
+            let req = x::DeleteProperty {
+                window: root,
+                property: self.atoms().published_history,
+            };
+
+            if let Err(e) = self.send_void_request(&req).await {
+                tracing::warn!("Published history: {e}");
+            }
+        }
+
+        let req = x::DestroyWindow {
+            window: self.0.window,
+        };
+
+        if let Err(e) = self.send_void_request(&req).await {
+            tracing::warn!("Manager selection: {e}");
+        }
+
+        self.stop();
+    }
+
+    /// Stop the main loop.
+    pub fn stop(&self) {
+        self.0.stop.notify_one();
     }
 
     fn handle_root_property(&self, prop: x::PropertyNotifyEvent) {
@@ -85,12 +554,34 @@ impl DisplayServer {
         }
 
         if prop.atom() == self.0.atoms.net_active_window {
-            self.0.focus_tracker.track(prop.window(), self.clone());
+            if self.0.tracking.get() == Tracking::ActiveWindow {
+                self.0.active_window_root.set(Some(prop.window()));
+                self.0.focus_event.notify_one();
+            }
+        } else if prop.atom() == self.0.atoms.net_supporting_wm_check {
+            self.window_manager_changed();
+        } else if prop.atom() == self.0.atoms.net_client_list {
+            tokio::task::spawn_local(urgency::update_clients(self.clone()));
+        } else if prop.atom() == self.0.atoms.command {
+            tokio::task::spawn_local(read_commands(self.clone(), prop.window()));
+        }
+    }
+
+    fn handle_window_property(&self, prop: x::PropertyNotifyEvent) {
+        if prop.atom() == self.0.atoms.timestamp {
+            self.0.timestamp.set(prop.time());
+            self.0.timestamp_received.notify_waiters();
+        }
+    }
+
+    fn handle_client_property(&self, prop: x::PropertyNotifyEvent) {
+        if urgency::is_urgency_property(self, prop.atom()) {
+            tokio::task::spawn_local(urgency::check(self.clone(), prop.window()));
         }
     }
 
     fn handle_xkb_state(&self, state: xcb::xkb::StateNotifyEvent) {
-        if let Some(watcher) = &*self.0.xkb_state_watcher.lock().unwrap() {
+        if let Some(watcher) = &*self.0.xkb_state_watcher.borrow() {
             if watcher.send(state.mods()).is_ok() {
                 return;
             }
@@ -98,19 +589,165 @@ impl DisplayServer {
 
         // If we receive a state notification, but there are no receivers,
         // stop watching XKB notifications.
-        xkb_select_events(self.connection(), false);
+        xkb_select_events(self, false);
+    }
+
+    fn handle_key_press(&self, event: x::KeyPressEvent) {
+        if self.0.chord_active.get() {
+            self.0.chord_key.set(Some(event.detail()));
+            self.0.chord_key_pressed.notify_one();
+            return;
+        }
+
+        if let Some(&key) = self.0.grabbed_keys.borrow().get(&event.detail()) {
+            self.0.pressed_key.set(Some(key));
+            self.0.key_pressed.notify_one();
+            return;
+        }
+
+        match keyboard::bound_action(self, &event) {
+            Some(keyboard::KeyAction::Switch) => {
+                let _ = self.0.commands.send(Command::Switch(1));
+            }
+
+            Some(keyboard::KeyAction::Chord(chord)) => {
+                let chord = keyboard::read_chord(self.clone(), chord, event.time());
+                tokio::task::spawn_local(chord);
+            }
+
+            None => (),
+        }
+    }
+
+    fn handle_selection_clear(&self, event: x::SelectionClearEvent) {
+        if event.owner() != self.0.window || event.selection() != self.0.atoms.manager {
+            return;
+        }
+
+        let _ = self.0.commands.send(Command::HandOver);
+    }
+
+    fn handle_destroy(&self, event: x::DestroyNotifyEvent) {
+        if self.0.previous_manager.get() == Some(event.window()) {
+            self.0.previous_manager.set(None);
+            self.0.manager_released.notify_one();
+            return;
+        }
+
+        // Windows selected in `watch_window`.
+        self.0.window_roots.borrow_mut().remove(&event.window());
+        self.0.urgency.remove(event.window());
+
+        if self.0.tracking.get() != Tracking::Disabled {
+            self.0
+                .destroyed_windows
+                .borrow_mut()
+                .push_back(event.window());
+            self.0.focus_event.notify_one();
+        }
+    }
+
+    fn handle_create(&self, event: x::CreateNotifyEvent) {
+        if self.0.tracking.get() == Tracking::InputFocus && !event.override_redirect() {
+            let display = self.clone();
+            let window = event.window();
+            tokio::task::spawn_local(async move { display.watch_window(window).await });
+        }
+    }
+
+    fn handle_focus_in(&self, event: x::FocusInEvent) {
+        if self.0.tracking.get() == Tracking::InputFocus && focus::is_focus_change(&event) {
+            self.0.active_window_root.set(Some(self.roots()[0]));
+            self.0.focus_event.notify_one();
+        }
     }
 
     fn handle_client_message(&self, msg: x::ClientMessageEvent) {
         if msg.r#type() == self.0.atoms.switch_command {
-            self.0.switch_command.notify_waiters();
+            // The first item in the message is the position in the history
+            // of the target window. Zero is used by older clients.
+            let nth = match msg.data() {
+                x::ClientMessageData::Data32([nth, ..]) => nth.max(1) as usize,
+                _ => 1,
+            };
+
+            let _ = self.0.commands.send(Command::Switch(nth));
+        } else if msg.r#type() == self.0.atoms.manager_message {
+            // A new window manager acquired the WM_Sn selection.
+            if let x::ClientMessageData::Data32([_, selection, ..]) = msg.data() {
+                if self
+                    .0
+                    .wm_selections
+                    .iter()
+                    .any(|a| a.resource_id() == selection)
+                {
+                    self.window_manager_changed();
+                }
+            }
         }
     }
 
-    pub async fn main_loop(&self) -> Result<(), xcb::Error> {
-        while let Ok(mut guard) = self.0.connection.readable().await {
+    /// Select the events in the root windows again, since they can be lost
+    /// when the window manager is restarted. The active window is read
+    /// again, because it may be different after the restart.
+    fn window_manager_changed(&self) {
+        let display = self.clone();
+        tokio::task::spawn_local(async move {
+            let mask = display.root_event_mask();
+            if let Err(e) = setup::listen_root_properties(&display, mask).await {
+                tracing::error!("Failed to listen root events: {e}");
+                return;
+            }
+
+            if display.0.tracking.get() != Tracking::Disabled {
+                display.0.active_window_root.set(Some(display.roots()[0]));
+                display.0.focus_event.notify_one();
+            }
+        });
+    }
+
+    /// Process the events and the replies received from the X server,
+    /// until [`stop`](Self::stop) is called or the connection fails.
+    pub async fn main_loop(&self) -> Result<(), Error> {
+        self.0.replies.open();
+        let result = self.process_events().await;
+
+        // Pending requests can't receive their replies.
+        self.0.replies.close();
+        result
+    }
+
+    async fn process_events(&self) -> Result<(), Error> {
+        loop {
+            let mut guard = tokio::select! {
+                guard = self.0.connection.readable() => match guard {
+                    Ok(guard) => guard,
+                    Err(_) => return Err(Error::Connection(xcb::ConnError::Connection)),
+                },
+
+                _ = self.0.stop.notified() => break,
+            };
+
+            let started = Instant::now();
+
             // Events.
-            while let Some(event) = self.connection().poll_for_event()? {
+            loop {
+                let event = match self.connection().poll_for_event() {
+                    Ok(Some(event)) => event,
+                    Ok(None) => break,
+
+                    // Errors of unchecked requests are received as events.
+                    // They are not fatal for the connection.
+                    Err(xcb::Error::Protocol(e)) => {
+                        tracing::warn!("X11 error: {e}");
+                        continue;
+                    }
+
+                    Err(e) => return Err(e.into()),
+                };
+
+                self.0.stats.borrow_mut().events += 1;
+
                 match event {
                     xcb::Event::X(x::Event::PropertyNotify(prop))
                         if self.is_root(prop.window()) =>
@@ -118,6 +755,16 @@ impl DisplayServer {
                         self.handle_root_property(prop);
                     }
 
+                    xcb::Event::X(x::Event::PropertyNotify(prop))
+                        if prop.window() == self.0.window =>
+                    {
+                        self.handle_window_property(prop);
+                    }
+
+                    xcb::Event::X(x::Event::PropertyNotify(prop)) => {
+                        self.handle_client_property(prop);
+                    }
+
                     xcb::Event::X(x::Event::ClientMessage(msg)) => {
                         if self.is_root(msg.window()) {
                             self.handle_client_message(msg);
@@ -128,58 +775,241 @@ impl DisplayServer {
                         self.handle_xkb_state(state);
                     }
 
+                    xcb::Event::X(x::Event::Expose(event))
+                        if self.0.overlay.is_overlay(event.window()) =>
+                    {
+                        if event.count() == 0 {
+                            overlay::draw(self);
+                        }
+                    }
+
+                    xcb::Event::X(x::Event::KeyPress(event)) => {
+                        self.handle_key_press(event);
+                    }
+
+                    xcb::Event::Input(
+                        xinput::Event::RawKeyPress(_) | xinput::Event::RawButtonPress(_),
+                    ) => {
+                        self.0.last_interaction.set(Some(Instant::now()));
+                        self.0.interacted.notify_waiters();
+                    }
+
+                    xcb::Event::X(x::Event::SelectionClear(event)) => {
+                        self.handle_selection_clear(event);
+                    }
+
+                    xcb::Event::X(x::Event::DestroyNotify(event)) => {
+                        self.handle_destroy(event);
+                    }
+
+                    xcb::Event::X(x::Event::CreateNotify(event)) => {
+                        self.handle_create(event);
+                    }
+
+                    xcb::Event::X(x::Event::FocusIn(event)) => {
+                        self.handle_focus_in(event);
+                    }
+
+                    xcb::Event::X(x::Event::FocusOut(_)) => (),
+
+                    xcb::Event::RandR(
+                        xcb::randr::Event::ScreenChangeNotify(_) | xcb::randr::Event::Notify(_),
+                    ) => {
+                        // Monitors are plugged or rearranged.
+                        outputs::invalidate(self);
+                    }
+
                     unknown => {
-                        eprintln!("Unexpected event: {unknown:?}");
+                        tracing::debug!("Unexpected event: {unknown:?}");
                     }
                 }
             }
 
             // Replies from requests.
-            self.0.requests.process_queue(self);
+            self.0.replies.dispatch();
 
             guard.clear_ready();
             self.connection().flush()?;
+
+            self.0
+                .stats
+                .borrow_mut()
+                .main_loop
+                .record(started.elapsed());
         }
 
         Ok(())
     }
 
+    /// Limit the number of requests waiting for a reply. When it is
+    /// reached, new requests wait until a previous one is finished. The
+    /// default is [`DEFAULT_MAX_PENDING`].
+    pub fn set_max_pending(&self, max: usize) {
+        self.0.replies.set_capacity(max);
+    }
+
+    /// Return the latency metrics of the connection.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            pending_requests: self.0.replies.pending(),
+            max_pending_requests: self.0.replies.max_pending(),
+            ..*self.0.stats.borrow()
+        }
+    }
+
+    /// Send a request, and wait for its reply. It fails with
+    /// [`Error::Timeout`] if the reply is not received before
+    /// [`DEFAULT_DEADLINE`].
     pub async fn send_request<R>(
         &self,
         request: &R,
-    ) -> Result<<R::Cookie as xcb::CookieWithReplyChecked>::Reply, xcb::Error>
+    ) -> Result<<R::Cookie as xcb::CookieWithReplyChecked>::Reply, Error>
     where
-        R: xcb::Request + 'static,
-        R::Cookie: xcb::CookieWithReplyChecked,
+        R: xcb::Request,
+        R::Cookie: xcb::CookieWithReplyChecked + 'static,
     {
-        let cookie = self.connection().send_request(request);
-        self.connection().flush()?;
+        self.send_request_timeout(request, DEFAULT_DEADLINE).await
+    }
 
-        let (tx, rx) = oneshot::channel();
-        let mut tx = Some(tx);
+    /// Like [`send_request`](Self::send_request), but it fails with
+    /// [`Error::Timeout`] if the reply is not received before `timeout`.
+    /// The request is removed from the queue, and its reply is discarded
+    /// if it arrives later.
+    pub async fn send_request_timeout<R>(
+        &self,
+        request: &R,
+        timeout: Duration,
+    ) -> Result<<R::Cookie as xcb::CookieWithReplyChecked>::Reply, Error>
+    where
+        R: xcb::Request,
+        R::Cookie: xcb::CookieWithReplyChecked + 'static,
+    {
+        let sent = Instant::now();
 
-        self.0.requests.add(Box::new(move |c| {
-            match c.connection().poll_for_reply(&cookie) {
-                Some(r) => {
-                    if let Some(tx) = tx.take() {
-                        let _ = tx.send(r);
-                    }
+        let reply = tokio::time::timeout(timeout, async {
+            self.0.replies.acquire().await;
 
-                    false
-                }
+            let cookie = self.connection().send_request(request);
+            let reply = replies::Reply::new(self.clone(), cookie);
+            self.flush()?;
+
+            reply.await
+        })
+        .await
+        .unwrap_or(Err(Error::Timeout));
+
+        self.0.stats.borrow_mut().requests.record(sent.elapsed());
+        reply
+    }
+
+    /// Send a request without a reply, and wait until the X server
+    /// processes it. It fails with [`Error::Timeout`] if the request is
+    /// not processed before [`DEFAULT_DEADLINE`].
+    ///
+    /// A `GetInputFocus` request is sent after it. When its reply is
+    /// received, the error of the first request, if any, is received too,
+    /// so it can be checked without blocking.
+    pub async fn send_void_request<R>(&self, request: &R) -> Result<(), Error>
+    where
+        R: xcb::RequestWithoutReply,
+    {
+        tokio::time::timeout(DEFAULT_DEADLINE, async {
+            self.0.replies.acquire().await;
 
-                None => true,
+            let conn = self.connection();
+            let cookie = replies::VoidReply::new(self.clone(), conn.send_request_checked(request));
+            let sync = replies::Reply::new(self.clone(), conn.send_request(&x::GetInputFocus {}));
+            self.flush()?;
+
+            sync.await?;
+            cookie.check()
+        })
+        .await
+        .unwrap_or(Err(Error::Timeout))
+    }
+
+    /// Wait until the X server processes the requests of `cookies`, and
+    /// return the first error.
+    ///
+    /// The cookies are tracked when this function is called, so their
+    /// errors are discarded if the future is dropped.
+    pub(super) fn check_requests(
+        &self,
+        cookies: impl IntoIterator<Item = xcb::VoidCookieChecked>,
+    ) -> impl Future<Output = Result<(), Error>> + 'static {
+        let display = self.clone();
+        let cookies: Vec<_> = cookies
+            .into_iter()
+            .map(|cookie| replies::VoidReply::new(display.clone(), cookie))
+            .collect();
+
+        async move {
+            display.send_request(&x::GetInputFocus {}).await?;
+            cookies.into_iter().try_for_each(replies::VoidReply::check)
+        }
+    }
+
+    /// Check the requests of `cookies` in a new task, and log the error.
+    /// Used where the requests can't be awaited.
+    pub(super) fn log_request_errors(
+        &self,
+        context: &'static str,
+        cookies: impl IntoIterator<Item = xcb::VoidCookieChecked>,
+    ) {
+        let check = self.check_requests(cookies);
+        tokio::task::spawn_local(async move {
+            if let Err(e) = check.await {
+                tracing::warn!("{context}: {e}");
             }
-        }));
+        });
+    }
+
+    /// Send a request without a reply, and ignore its error. Used where
+    /// the request can't be awaited, and a failure is expected.
+    pub(super) fn send_request_discarded<R>(&self, request: &R)
+    where
+        R: xcb::RequestWithoutReply,
+    {
+        let cookie = self.connection().send_request_checked(request);
+        drop(replies::VoidReply::new(self.clone(), cookie));
+    }
 
-        match rx.await {
-            Ok(r) => r,
-            Err(_) => Err(xcb::Error::Connection(xcb::ConnError::Connection)),
+    /// Run `requests`, and flush the connection once after each poll,
+    /// instead of once for every request. Requests sent by futures that
+    /// are joined in `requests` share a single round trip.
+    pub async fn batch<F: Future>(&self, requests: F) -> F::Output {
+        let mut requests = std::pin::pin!(requests);
+
+        std::future::poll_fn(|cx| {
+            let depth = &self.0.batch_depth;
+            depth.set(depth.get() + 1);
+            let poll = requests.as_mut().poll(cx);
+            depth.set(depth.get() - 1);
+
+            if depth.get() == 0 && self.0.unflushed.take() {
+                if let Err(e) = self.connection().flush() {
+                    tracing::warn!("Flush: {e}");
+                }
+            }
+
+            poll
+        })
+        .await
+    }
+
+    /// Flush the connection, unless the request is sent in a
+    /// [`batch`](Self::batch).
+    fn flush(&self) -> Result<(), Error> {
+        if self.0.batch_depth.get() > 0 {
+            self.0.unflushed.set(true);
+            return Ok(());
         }
+
+        Ok(self.connection().flush()?)
     }
 
     pub fn watch_xkb_state(&self) -> watch::Receiver<x::ModMask> {
-        let mut xkb_state_watcher = self.0.xkb_state_watcher.lock().unwrap();
+        let mut xkb_state_watcher = self.0.xkb_state_watcher.borrow_mut();
 
         match &*xkb_state_watcher {
             Some(w) => {
@@ -192,7 +1022,7 @@ impl DisplayServer {
                 //
                 // Create a new watcher and configure XKB events.
 
-                xkb_select_events(self.connection(), true);
+                xkb_select_events(self, true);
 
                 let (tx, rx) = watch::channel(x::ModMask::empty());
                 *xkb_state_watcher = Some(tx.clone());
@@ -204,13 +1034,568 @@ impl DisplayServer {
         }
     }
 
-    pub fn switch_window(&self) -> Option<x::Window> {
-        self.0.focus_tracker.switch()
+    /// Return the metadata of `window`.
+    pub async fn window_info(&self, window: x::Window) -> Result<WindowInfo, Error> {
+        windowinfo::query(self, window).await
+    }
+
+    /// Write the history in the `_X11_ALTERNATE_FOCUS_HISTORY` property of
+    /// the root windows.
+    pub async fn publish_history(&self, windows: &[x::Window]) -> Result<(), Error> {
+        let property = self.atoms().published_history;
+        for &root in self.roots() {
+            property::set_windows(self, root, property, windows).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the value of `_NET_CURRENT_DESKTOP`.
+    pub async fn current_desktop(&self) -> Result<Option<u32>, Error> {
+        self.cardinal_property(self.roots()[0], self.atoms().net_current_desktop)
+            .await
+    }
+
+    /// Return the first item of a `CARDINAL` property.
+    async fn cardinal_property(
+        &self,
+        window: x::Window,
+        property: x::Atom,
+    ) -> Result<Option<u32>, Error> {
+        let items = property::get_cardinals(self, window, property).await?;
+        Ok(items.and_then(|items| items.first().copied()))
+    }
+
+    /// Return the value of a text property.
+    pub async fn text_property(
+        &self,
+        window: x::Window,
+        property: x::Atom,
+    ) -> Result<Option<String>, Error> {
+        windowinfo::text_property(self, window, property).await
+    }
+
+    /// Return the window of the server that is running on this display.
+    pub async fn manager_owner(&self) -> Result<Option<x::Window>, Error> {
+        manager::owner(self).await.context("Manager selection")
+    }
+
+    /// Register this process as the server for this display, replacing
+    /// the previous one, if any.
+    ///
+    /// Returns the history received from the previous server.
+    pub async fn acquire_manager(&self) -> Result<Vec<x::Window>, Error> {
+        manager::acquire(self).await.context("Manager selection")
+    }
+
+    /// Send the history to the new owner of the manager selection, and
+    /// stop the main loop.
+    pub async fn hand_over(&self, windows: &[x::Window]) {
+        if let Err(e) = manager::hand_over(self, windows).await {
+            tracing::error!("Failed to send history to the new server: {e}");
+        }
+
+        self.stop();
+    }
+
+    /// Wait until the focus changes, or a window returned by a previous
+    /// call is destroyed.
+    pub async fn next_focus_event(&self) -> Result<FocusEvent<x::Window>, Error> {
+        loop {
+            if let Some(window) = self.0.destroyed_windows.borrow_mut().pop_front() {
+                return Ok(FocusEvent::Closed(window));
+            }
+
+            self.0.focus_event.notified().await;
+
+            let quiet_time = self.0.focus_quiet_time.get();
+            if self.0.active_window_root.get().is_some() && !quiet_time.is_zero() {
+                self.debounce_focus_changes(quiet_time).await;
+            }
+
+            let root = match self.0.active_window_root.take() {
+                Some(root) => root,
+                None => continue,
+            };
+
+            let focused = match self.0.tracking.get() {
+                Tracking::InputFocus => focus::input_focus(self).await?,
+                _ => self.active_window(root).await?.map(|w| (w, root)),
+            };
+
+            match focused {
+                Some((window, _)) if self.0.overlay.is_overlay(window) => (),
+
+                Some((window, root)) if !window.is_none() => {
+                    self.0.window_roots.borrow_mut().insert(window, root);
+                    self.watch_window(window).await;
+                    return Ok(FocusEvent::Focused(window));
+                }
+
+                _ => tracing::debug!("No focused window"),
+            }
+        }
+    }
+
+    /// Wait until the focus changes stop for a moment, so a burst of
+    /// changes, like the ones after a workspace switch, is read once.
+    async fn debounce_focus_changes(&self, quiet_time: Duration) {
+        // Maximum delay, if the changes don't stop.
+        const MAX_DELAY: Duration = Duration::from_millis(100);
+
+        let deadline = Instant::now() + MAX_DELAY.max(quiet_time);
+
+        loop {
+            let quiet = Instant::now() + quiet_time;
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(quiet.min(deadline)) => return,
+                _ = self.0.focus_event.notified() => (),
+            }
+        }
+    }
+
+    /// Receive a `DestroyNotify` event when `window` is destroyed, and the
+    /// changes in its urgency. When the input focus is tracked, `FocusIn`
+    /// events are also received.
+    pub async fn watch_window(&self, window: x::Window) {
+        let mut event_mask = x::EventMask::STRUCTURE_NOTIFY | x::EventMask::PROPERTY_CHANGE;
+        if self.0.tracking.get() == Tracking::InputFocus {
+            event_mask |= x::EventMask::FOCUS_CHANGE;
+        }
+
+        let req = x::ChangeWindowAttributes {
+            window,
+            value_list: &[x::Cw::EventMask(event_mask)],
+        };
+
+        // The request fails if the window is already destroyed.
+        if let Err(e) = self.send_void_request(&req).await {
+            tracing::debug!(window = window.resource_id(), "Watch window: {e}");
+        }
+    }
+
+    /// Return `false` if `window` does not exist, or if its `WM_STATE` is
+    /// `WithdrawnState`.
+    pub async fn is_available(&self, window: x::Window) -> bool {
+        // https://tronche.com/gui/x/icccm/sec-4.html#s-4.1.3.1
+        const WITHDRAWN_STATE: u32 = 0;
+
+        let attributes = x::GetWindowAttributes { window };
+
+        let wm_state = x::GetProperty {
+            delete: false,
+            window,
+            property: self.atoms().wm_state,
+            r#type: self.atoms().wm_state,
+            long_offset: 0,
+            long_length: 1,
+        };
+
+        let (attributes, wm_state) = self
+            .batch(async {
+                tokio::join!(self.send_request(&attributes), self.send_request(&wm_state))
+            })
+            .await;
+
+        if attributes.is_err() {
+            return false;
+        }
+
+        // Windows without WM_STATE are accepted, since not all window
+        // managers set it.
+        match wm_state {
+            Ok(reply) if reply.format() == 32 => {
+                reply.value::<u32>().first() != Some(&WITHDRAWN_STATE)
+            }
+            _ => true,
+        }
+    }
+
+    /// Return `true` if any keyboard modifier in the mask is pressed.
+    pub async fn modifiers_pressed(&self) -> Result<bool, Error> {
+        let req = xcb::xkb::GetState {
+            device_spec: xcb::xkb::Id::UseCoreKbd as xcb::xkb::DeviceSpec,
+        };
+
+        let mods = self.send_request(&req).await?.mods();
+        Ok(mods.intersects(self.0.modifier_mask.get()))
+    }
+
+    /// Wait until all keyboard modifiers in the mask are released.
+    pub async fn modifiers_released(&self) {
+        let mut rx = self.watch_xkb_state();
+
+        loop {
+            if rx.changed().await.is_err() {
+                return;
+            }
+
+            if !rx
+                .borrow_and_update()
+                .intersects(self.0.modifier_mask.get())
+            {
+                break;
+            }
+        }
+    }
+
+    /// Grab Escape and Enter in the root windows, and wait until one of
+    /// them is pressed. The keys are released when the future is dropped.
+    pub async fn cycle_key(&self) -> Result<CycleKey, Error> {
+        let _grab = keyboard::KeyGrab::new(self).await?;
+        self.0.pressed_key.set(None);
+
+        loop {
+            self.0.key_pressed.notified().await;
+
+            if let Some(key) = self.0.pressed_key.take() {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// Show the `entries` in a window over the others, with the `selected`
+    /// line highlighted. Each entry is a window and its line. The windows
+    /// are used for the thumbnails, if a compositor is running.
+    pub async fn show_overlay(
+        &self,
+        entries: &[(x::Window, String)],
+        selected: usize,
+    ) -> Result<(), Error> {
+        overlay::show(self, entries, selected).await
+    }
+
+    /// Hide the window of [`show_overlay`](Self::show_overlay).
+    pub fn hide_overlay(&self) {
+        overlay::hide(self);
+    }
+
+    /// Return the windows demanding attention, most recent first.
+    pub fn urgent_windows(&self) -> Vec<x::Window> {
+        self.0.urgency.windows()
+    }
+
+    /// Draw a border around `window` for a moment. `color` is a color name,
+    /// or a `#rrggbb` value.
+    pub async fn flash(&self, window: x::Window, color: &str) -> Result<(), Error> {
+        flash::flash(self, window, color).await
+    }
+
+    /// Steps before activating a window, common to all X11 backends.
+    pub async fn prepare_activation(&self, window: x::Window, options: &ActivationOptions) {
+        if options.focus_output {
+            if let Err(e) = outputs::focus_output(self, window).await {
+                tracing::warn!("focus_output: {e}");
+            }
+        }
+
+        if options.unminimize {
+            if let Err(e) = self.unminimize(window).await {
+                tracing::warn!("unminimize: {e}");
+            }
+        }
+    }
+
+    /// Steps after activating a window, common to all X11 backends.
+    pub async fn finish_activation(&self, window: x::Window, options: &ActivationOptions) {
+        if options.warp_pointer {
+            if let Err(e) = outputs::warp_to_window(self, window).await {
+                tracing::warn!("warp_pointer: {e}");
+            }
+        }
+    }
+
+    /// Return the monitor where the pointer is.
+    pub async fn pointer_output(&self) -> Result<Option<u32>, Error> {
+        Ok(outputs::pointer_monitor(self).await?.map(|m| m.id()))
+    }
+
+    /// Return the monitor containing the center of `window`.
+    pub async fn window_output(&self, window: x::Window) -> Result<Option<u32>, Error> {
+        Ok(outputs::window_monitor(self, window).await?.map(|m| m.id()))
+    }
+
+    /// Return the root window of the screen where `window` is.
+    ///
+    /// The root is recorded when the window is activated. For other
+    /// windows, it is requested to the X server.
+    pub async fn root_of(&self, window: x::Window) -> x::Window {
+        if let Some(&root) = self.0.window_roots.borrow().get(&window) {
+            return root;
+        }
+
+        let req = x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        };
+
+        match self.send_request(&req).await {
+            Ok(reply) => reply.root(),
+            Err(_) => self.roots()[0],
+        }
+    }
+
+    /// Return the current time of the X server.
+    ///
+    /// It appends an empty value to a property, so the server sends a
+    /// `PropertyNotify` event with its timestamp.
+    pub async fn server_time(&self) -> Result<x::Timestamp, Error> {
+        // Time to wait for the event.
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        // Register before sending the request, so the event is not missed.
+        // notify_waiters does not store a permit, so an event that arrives
+        // after a timeout does not complete the next call.
+        let mut received = std::pin::pin!(self.0.timestamp_received.notified());
+        received.as_mut().enable();
+
+        self.send_void_request(&x::ChangeProperty {
+            mode: x::PropMode::Append,
+            window: self.0.window,
+            property: self.atoms().timestamp,
+            r#type: x::ATOM_INTEGER,
+            data: &[] as &[u32],
+        })
+        .await?;
+
+        if tokio::time::timeout(TIMEOUT, received).await.is_err() {
+            return Ok(x::CURRENT_TIME);
+        }
+
+        Ok(self.0.timestamp.get())
+    }
+
+    /// Return the active window of each screen, and the windows activated
+    /// in it.
+    pub async fn screen_states(&self) -> Vec<ScreenState> {
+        let mut states = Vec::with_capacity(self.roots().len());
+
+        for &root in self.roots() {
+            let active_window = match self.active_window(root).await {
+                Ok(Some(window)) if !window.is_none() => Some(window.resource_id().into()),
+                _ => None,
+            };
+
+            let mut windows: Vec<u64> = self
+                .0
+                .window_roots
+                .borrow()
+                .iter()
+                .filter(|(_, &r)| r == root)
+                .map(|(w, _)| w.resource_id().into())
+                .collect();
+
+            windows.sort_unstable();
+
+            states.push(ScreenState {
+                root: root.resource_id().into(),
+                active_window,
+                windows,
+            });
+        }
+
+        states
+    }
+
+    /// Return the value of `_NET_ACTIVE_WINDOW` in `root`.
+    async fn active_window(&self, root: x::Window) -> Result<Option<x::Window>, Error> {
+        let req = x::GetProperty {
+            delete: false,
+            window: root,
+            property: self.atoms().net_active_window,
+            r#type: x::ATOM_WINDOW,
+            long_offset: 0,
+            long_length: 1,
+        };
+
+        let reply = self.send_request(&req).await?;
+        Ok(match reply.format() {
+            32 => reply.value::<x::Window>().first().copied(),
+            _ => None,
+        })
+    }
+
+    /// Send a `_NET_ACTIVE_WINDOW` message to activate `window`. When the
+    /// input focus is tracked, the focus is set directly.
+    pub async fn activate(&self, window: x::Window) -> Result<(), Error> {
+        // https://specifications.freedesktop.org/wm-spec/1.5/ar01s09.html#sourceindication
+        const SOURCE_PAGER: u32 = 2;
+
+        let options = self.activation_options(window).await;
+        self.prepare_activation(window, &options).await;
+
+        if self.0.tracking.get() == Tracking::InputFocus {
+            focus::set_input_focus(self, window, options.raise).await?;
+            self.finish_activation(window, &options).await;
+            return Ok(());
+        }
+
+        let root = self.root_of(window).await;
+
+        // Window managers with focus stealing prevention need the time of
+        // the user action, and the window that is currently active.
+        let (timestamp, active_window) = tokio::join!(self.server_time(), self.active_window(root));
+        let timestamp = timestamp?;
+
+        if options.change_desktop {
+            if let Err(e) = self.change_desktop(window, root, timestamp).await {
+                tracing::warn!("change_desktop: {e}");
+            }
+        }
+
+        // To keep the stacking order, the window is put below the one that
+        // was above it before the activation.
+        let above = match options.raise {
+            Raise::Never => stacking::window_above(self, window, root)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!("_NET_CLIENT_LIST_STACKING: {e}");
+                    None
+                }),
+
+            _ => None,
+        };
+
+        let event = x::ClientMessageEvent::new(
+            window,
+            self.atoms().net_active_window,
+            x::ClientMessageData::Data32([
+                SOURCE_PAGER,
+                timestamp,
+                active_window?.map_or(0, |w| w.resource_id()),
+                0,
+                0,
+            ]),
+        );
+
+        let req = x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(root),
+            event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
+            event: &event,
+        };
+
+        self.send_void_request(&req).await?;
+
+        match (options.raise, above) {
+            (Raise::Always, _) => stacking::raise(self, window, root).await,
+            (Raise::Never, Some(sibling)) => stacking::restore(self, window, root, sibling),
+            _ => (),
+        }
+
+        self.finish_activation(window, &options).await;
+
+        Ok(())
+    }
+
+    /// Remove `_NET_WM_STATE_HIDDEN` from `window`, and map it, if it is
+    /// minimized.
+    async fn unminimize(&self, window: x::Window) -> Result<(), Error> {
+        // https://specifications.freedesktop.org/wm-spec/1.5/ar01s05.html#id-1.6.8
+        const REMOVE: u32 = 0;
+        const SOURCE_PAGER: u32 = 2;
+
+        let atoms = self.atoms();
+
+        let req = x::GetProperty {
+            delete: false,
+            window,
+            property: atoms.net_wm_state,
+            r#type: x::ATOM_ATOM,
+            long_offset: 0,
+            long_length: 64,
+        };
+
+        let state = self.send_request(&req).await?;
+        if state.format() != 32
+            || !state
+                .value::<x::Atom>()
+                .contains(&atoms.net_wm_state_hidden)
+        {
+            return Ok(());
+        }
+
+        tracing::debug!(?window, "Unminimize");
+
+        let root = self.root_of(window).await;
+        let event = x::ClientMessageEvent::new(
+            window,
+            atoms.net_wm_state,
+            x::ClientMessageData::Data32([
+                REMOVE,
+                atoms.net_wm_state_hidden.resource_id(),
+                0,
+                SOURCE_PAGER,
+                0,
+            ]),
+        );
+
+        self.send_void_request(&x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(root),
+            event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
+            event: &event,
+        })
+        .await?;
+
+        // Iconic windows are restored to the normal state with a map
+        // request (ICCCM 4.1.4).
+        self.send_void_request(&x::MapWindow { window }).await?;
+
+        Ok(())
+    }
+
+    /// Send a `_NET_CURRENT_DESKTOP` message if `window` is in another
+    /// desktop.
+    async fn change_desktop(
+        &self,
+        window: x::Window,
+        root: x::Window,
+        timestamp: x::Timestamp,
+    ) -> Result<(), Error> {
+        // Value of `_NET_WM_DESKTOP` for windows in all desktops.
+        const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+        let atoms = self.atoms();
+        let (desktop, current) = tokio::join!(
+            self.cardinal_property(window, atoms.net_wm_desktop),
+            self.cardinal_property(root, atoms.net_current_desktop),
+        );
+
+        let desktop = match (desktop?, current?) {
+            (Some(d), Some(c)) if d != c && d != ALL_DESKTOPS => d,
+            _ => return Ok(()),
+        };
+
+        tracing::debug!(?window, desktop, "Change desktop");
+
+        let event = x::ClientMessageEvent::new(
+            root,
+            atoms.net_current_desktop,
+            x::ClientMessageData::Data32([desktop, timestamp, 0, 0, 0]),
+        );
+
+        let req = x::SendEvent {
+            propagate: false,
+            destination: x::SendEventDest::Window(root),
+            event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
+            event: &event,
+        };
+
+        self.send_void_request(&req).await
     }
 }
 
 /// Enable or disable the notifications when the modifiers state is updated.
-fn xkb_select_events(conn: &xcb::Connection, active: bool) {
+/// The request is checked in a new task.
+fn xkb_select_events(display: &DisplayServer, active: bool) {
+    let request = xkb_select_events_request(active);
+    let cookie = display.connection().send_request_checked(&request);
+    display.log_request_errors("XKB events", [cookie]);
+}
+
+/// Request to select or clear the XKB events used by the server.
+fn xkb_select_events_request(active: bool) -> xcb::xkb::SelectEvents<'static> {
     let events = xcb::xkb::EventType::STATE_NOTIFY;
     let map = xcb::xkb::MapPart::MODIFIER_MAP;
 
@@ -225,7 +1610,7 @@ fn xkb_select_events(conn: &xcb::Connection, active: bool) {
         clear = events;
     }
 
-    let request = xcb::xkb::SelectEvents {
+    xcb::xkb::SelectEvents {
         device_spec: xcb::xkb::Id::UseCoreKbd as xcb::xkb::DeviceSpec,
         affect_which: events,
         clear,
@@ -233,10 +1618,38 @@ fn xkb_select_events(conn: &xcb::Connection, active: bool) {
         affect_map: map,
         map,
         details: &[],
+    }
+}
+
+/// Read and delete the command property of `root`, and send a command for
+/// each line in it.
+async fn read_commands(display: DisplayServer, root: x::Window) {
+    // The property is deleted only if the whole value is read, so the
+    // length is not limited.
+    let req = x::GetProperty {
+        delete: true,
+        window: root,
+        property: display.atoms().command,
+        r#type: x::ATOM_ANY,
+        long_offset: 0,
+        long_length: u32::MAX / 4,
     };
 
-    if let Err(e) = conn.check_request(conn.send_request_checked(&request)) {
-        eprintln!("xkb_select_events(*, {active}): {e}");
+    let reply = match display.send_request(&req).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            tracing::warn!("Command property: {e}");
+            return;
+        }
+    };
+
+    if reply.format() != 8 {
+        return;
+    }
+
+    let text = String::from_utf8_lossy(reply.value::<u8>());
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let _ = display.0.commands.send(Command::Text(line.to_string()));
     }
 }
 
@@ -245,7 +1658,11 @@ fn xkb_close_listener(display: DisplayServer, tx: watch::Sender<x::ModMask>) {
     tokio::task::spawn_local(async move {
         tx.closed().await;
 
-        *display.0.xkb_state_watcher.lock().unwrap() = None;
-        xkb_select_events(display.connection(), false);
+        display.0.xkb_state_watcher.take();
+
+        let request = xkb_select_events_request(false);
+        if let Err(e) = display.send_void_request(&request).await {
+            tracing::warn!("XKB events: {e}");
+        }
     });
 }
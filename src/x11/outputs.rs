@@ -0,0 +1,214 @@
+use std::rc::Rc;
+
+use xcb::{randr, x, Xid};
+
+use crate::Error;
+
+/// Area of a monitor, in root window coordinates.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) struct Monitor {
+    name: x::Atom,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+}
+
+impl Monitor {
+    /// Identifier of the monitor, from its name.
+    pub(super) fn id(&self) -> u32 {
+        self.name.resource_id()
+    }
+
+    fn contains(&self, (x, y): (i16, i16)) -> bool {
+        let (x, y) = (i32::from(x), i32::from(y));
+        let (left, top) = (i32::from(self.x), i32::from(self.y));
+
+        x >= left
+            && y >= top
+            && x < left + i32::from(self.width)
+            && y < top + i32::from(self.height)
+    }
+
+    pub(super) fn center(&self) -> (i16, i16) {
+        (
+            self.x.saturating_add((self.width / 2) as i16),
+            self.y.saturating_add((self.height / 2) as i16),
+        )
+    }
+}
+
+/// Return the monitors of the screen of `root`.
+///
+/// The layout is cached until [`invalidate`] is called.
+async fn monitors(display: &super::DisplayServer, root: x::Window) -> Result<Rc<[Monitor]>, Error> {
+    if let Some(monitors) = display.0.monitors.borrow().get(&root) {
+        return Ok(monitors.clone());
+    }
+
+    let req = randr::GetMonitors {
+        window: root,
+        get_active: true,
+    };
+
+    let monitors: Rc<[Monitor]> = display
+        .send_request(&req)
+        .await?
+        .monitors()
+        .map(|m| Monitor {
+            name: m.name(),
+            x: m.x(),
+            y: m.y(),
+            width: m.width(),
+            height: m.height(),
+        })
+        .collect();
+
+    display
+        .0
+        .monitors
+        .borrow_mut()
+        .insert(root, monitors.clone());
+
+    Ok(monitors)
+}
+
+/// Receive RandR notifications when the layout is changed.
+pub(super) async fn listen_changes(display: &super::DisplayServer) -> Result<(), Error> {
+    let enable = randr::NotifyMask::SCREEN_CHANGE
+        | randr::NotifyMask::CRTC_CHANGE
+        | randr::NotifyMask::OUTPUT_CHANGE;
+
+    for &window in display.roots() {
+        display
+            .send_void_request(&randr::SelectInput { window, enable })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Discard the cached layout, so it is requested again when needed.
+pub(super) fn invalidate(display: &super::DisplayServer) {
+    display.0.monitors.borrow_mut().clear();
+}
+
+/// Move the pointer to the center of `window`.
+pub(super) async fn warp_to_window(
+    display: &super::DisplayServer,
+    window: x::Window,
+) -> Result<(), Error> {
+    let (root, (dst_x, dst_y)) = window_center(display, window).await?;
+
+    let req = x::WarpPointer {
+        src_window: x::Window::none(),
+        dst_window: root,
+        src_x: 0,
+        src_y: 0,
+        src_width: 0,
+        src_height: 0,
+        dst_x,
+        dst_y,
+    };
+
+    display.send_void_request(&req).await
+}
+
+/// Return the root window, and the center of `window` in root coordinates.
+async fn window_center(
+    display: &super::DisplayServer,
+    window: x::Window,
+) -> Result<(x::Window, (i16, i16)), Error> {
+    let geometry = display
+        .send_request(&x::GetGeometry {
+            drawable: x::Drawable::Window(window),
+        })
+        .await?;
+
+    let root = geometry.root();
+
+    let position = display
+        .send_request(&x::TranslateCoordinates {
+            src_window: window,
+            dst_window: root,
+            src_x: 0,
+            src_y: 0,
+        })
+        .await?;
+
+    let center = (
+        position
+            .dst_x()
+            .saturating_add((geometry.width() / 2) as i16),
+        position
+            .dst_y()
+            .saturating_add((geometry.height() / 2) as i16),
+    );
+
+    Ok((root, center))
+}
+
+/// Return the monitor containing the center of `window`.
+pub(super) async fn window_monitor(
+    display: &super::DisplayServer,
+    window: x::Window,
+) -> Result<Option<Monitor>, Error> {
+    let (root, center) = window_center(display, window).await?;
+    let monitors = monitors(display, root).await?;
+    Ok(monitors.iter().find(|m| m.contains(center)).copied())
+}
+
+/// Return the monitor containing the pointer.
+pub(super) async fn pointer_monitor(
+    display: &super::DisplayServer,
+) -> Result<Option<Monitor>, Error> {
+    let root = display.roots()[0];
+
+    let pointer = display
+        .send_request(&x::QueryPointer { window: root })
+        .await?;
+
+    let monitors = monitors(display, pointer.root()).await?;
+    let position = (pointer.root_x(), pointer.root_y());
+    Ok(monitors.iter().find(|m| m.contains(position)).copied())
+}
+
+/// If `window` is in a different monitor than the pointer, move the pointer
+/// to the center of the monitor of `window`.
+pub(super) async fn focus_output(
+    display: &super::DisplayServer,
+    window: x::Window,
+) -> Result<(), Error> {
+    let (root, center) = window_center(display, window).await?;
+    let monitors = monitors(display, root).await?;
+
+    let pointer = display
+        .send_request(&x::QueryPointer { window: root })
+        .await?;
+
+    let find = |position| monitors.iter().find(|m| m.contains(position));
+
+    let target = match find(center) {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+
+    if find((pointer.root_x(), pointer.root_y())) == Some(target) {
+        return Ok(());
+    }
+
+    let (dst_x, dst_y) = target.center();
+
+    let req = x::WarpPointer {
+        src_window: x::Window::none(),
+        dst_window: root,
+        src_x: 0,
+        src_y: 0,
+        src_width: 0,
+        src_height: 0,
+        dst_x,
+        dst_y,
+    };
+
+    display.send_void_request(&req).await
+}
@@ -0,0 +1,526 @@
+//! Window to show the history while the windows are cycled.
+//!
+//! It is an override-redirect window, so it is never managed or focused by
+//! the window manager. Text is drawn with the core `fixed` font.
+//!
+//! If a compositor is running, each line has a thumbnail of its window,
+//! copied from the pixmaps of the Composite extension, and scaled with the
+//! Render extension. Thumbnails are updated while the overlay is visible.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    time::Duration,
+};
+
+use xcb::{composite, render, x, Xid};
+
+use super::{outputs, windowinfo, DisplayServer};
+use crate::Error;
+
+/// Space around the lines, in pixels.
+const PADDING: u16 = 8;
+
+/// Maximum number of characters in each line.
+const MAX_LINE: usize = 80;
+
+/// Maximum size of the thumbnails, in pixels.
+const THUMBNAIL_WIDTH: u16 = 96;
+const THUMBNAIL_HEIGHT: u16 = 64;
+
+/// Time between updates of the thumbnails.
+const THUMBNAIL_REFRESH: Duration = Duration::from_millis(250);
+
+#[derive(Default)]
+pub(super) struct Overlay {
+    resources: Cell<Option<Resources>>,
+    lines: RefCell<Vec<Vec<u8>>>,
+    windows: RefCell<Vec<x::Window>>,
+    selected: Cell<usize>,
+
+    /// Height of each line, including the thumbnail.
+    row_height: Cell<u16>,
+
+    /// If `true`, thumbnails are drawn in the visible overlay.
+    thumbnails: Cell<bool>,
+
+    /// If `true`, a task is updating the thumbnails.
+    refreshing: Cell<bool>,
+
+    /// Render formats for each visual.
+    formats: RefCell<HashMap<x::Visualid, render::Pictformat>>,
+}
+
+/// X resources of the overlay, created when it is shown for the first
+/// time.
+#[derive(Clone, Copy)]
+struct Resources {
+    window: x::Window,
+    gc: x::Gcontext,
+    char_width: u16,
+    ascent: u16,
+    line_height: u16,
+    black: u32,
+    white: u32,
+
+    /// Picture of the window, to draw the thumbnails. `None` if the
+    /// Composite or Render extensions are not available.
+    picture: Option<render::Picture>,
+}
+
+impl Overlay {
+    /// Return `true` if `window` is the overlay.
+    pub(super) fn is_overlay(&self, window: x::Window) -> bool {
+        self.resources.get().is_some_and(|r| r.window == window)
+    }
+
+    /// Horizontal position of the text.
+    fn text_x(&self) -> u16 {
+        match self.thumbnails.get() {
+            true => 2 * PADDING + THUMBNAIL_WIDTH,
+            false => PADDING,
+        }
+    }
+}
+
+/// Show the `entries` in the monitor where the pointer is, with the
+/// `selected` line highlighted. Each entry is a window and its line.
+pub(super) async fn show(
+    display: &DisplayServer,
+    entries: &[(x::Window, String)],
+    selected: usize,
+) -> Result<(), Error> {
+    let overlay = &display.0.overlay;
+
+    let resources = match overlay.resources.get() {
+        Some(r) => r,
+        None => {
+            let r = create(display).await?;
+            overlay.resources.set(Some(r));
+            r
+        }
+    };
+
+    // Core fonts only support Latin-1.
+    let lines: Vec<Vec<u8>> = entries
+        .iter()
+        .map(|(_, line)| {
+            line.chars()
+                .take(MAX_LINE)
+                .map(|c| u8::try_from(c).unwrap_or(b'?'))
+                .collect()
+        })
+        .collect();
+
+    let thumbnails = resources.picture.is_some() && compositor_running(display).await?;
+    overlay.thumbnails.set(thumbnails);
+
+    let row_height = match thumbnails {
+        true => resources.line_height.max(THUMBNAIL_HEIGHT + 4),
+        false => resources.line_height,
+    };
+
+    let columns = lines.iter().map(Vec::len).max().unwrap_or(0) as u16;
+    let width = overlay.text_x() + columns * resources.char_width + PADDING;
+    let height = lines.len() as u16 * row_height + 2 * PADDING;
+
+    let screen = display.connection().get_setup().roots().next().unwrap();
+    let (center_x, center_y) = match outputs::pointer_monitor(display).await? {
+        Some(monitor) => monitor.center(),
+        None => (
+            (screen.width_in_pixels() / 2) as i16,
+            (screen.height_in_pixels() / 2) as i16,
+        ),
+    };
+
+    overlay.lines.replace(lines);
+    overlay
+        .windows
+        .replace(entries.iter().map(|&(w, _)| w).collect());
+    overlay.selected.set(selected);
+    overlay.row_height.set(row_height);
+
+    display
+        .send_void_request(&x::ConfigureWindow {
+            window: resources.window,
+            value_list: &[
+                x::ConfigWindow::X(i32::from(center_x) - i32::from(width / 2)),
+                x::ConfigWindow::Y(i32::from(center_y) - i32::from(height / 2)),
+                x::ConfigWindow::Width(width.into()),
+                x::ConfigWindow::Height(height.into()),
+                x::ConfigWindow::StackMode(x::StackMode::Above),
+            ],
+        })
+        .await?;
+
+    display
+        .send_void_request(&x::MapWindow {
+            window: resources.window,
+        })
+        .await?;
+
+    draw(display);
+
+    if thumbnails && !overlay.refreshing.replace(true) {
+        tokio::task::spawn_local(refresh_thumbnails(display.clone()));
+    }
+
+    Ok(())
+}
+
+/// Unmap the overlay, if it is visible.
+pub(super) fn hide(display: &DisplayServer) {
+    display.0.overlay.thumbnails.set(false);
+
+    if let Some(resources) = display.0.overlay.resources.get() {
+        let cookie = display.connection().send_request_checked(&x::UnmapWindow {
+            window: resources.window,
+        });
+
+        display.log_request_errors("Hide overlay", [cookie]);
+    }
+}
+
+/// Draw the lines in the overlay. Called when it is shown, and when it is
+/// exposed.
+pub(super) fn draw(display: &DisplayServer) {
+    let overlay = &display.0.overlay;
+    let Some(r) = overlay.resources.get() else {
+        return;
+    };
+
+    let conn = display.connection();
+    let drawable = x::Drawable::Window(r.window);
+    let row_height = overlay.row_height.get();
+
+    let mut cookies = Vec::new();
+
+    cookies.push(conn.send_request_checked(&x::ClearArea {
+        exposures: false,
+        window: r.window,
+        x: 0,
+        y: 0,
+        width: 0,
+        height: 0,
+    }));
+
+    for (n, line) in overlay.lines.borrow().iter().enumerate() {
+        let top = (PADDING + n as u16 * row_height) as i16;
+        let selected = n == overlay.selected.get();
+
+        let (fg, bg) = match selected {
+            true => (r.black, r.white),
+            false => (r.white, r.black),
+        };
+
+        if selected {
+            cookies.push(conn.send_request_checked(&x::ChangeGc {
+                gc: r.gc,
+                value_list: &[x::Gc::Foreground(bg)],
+            }));
+
+            cookies.push(conn.send_request_checked(&x::PolyFillRectangle {
+                drawable,
+                gc: r.gc,
+                rectangles: &[x::Rectangle {
+                    x: 0,
+                    y: top,
+                    width: u16::MAX,
+                    height: row_height,
+                }],
+            }));
+        }
+
+        cookies.push(conn.send_request_checked(&x::ChangeGc {
+            gc: r.gc,
+            value_list: &[x::Gc::Foreground(fg), x::Gc::Background(bg)],
+        }));
+
+        let baseline = top + ((row_height - r.line_height) / 2 + r.ascent) as i16;
+
+        cookies.push(conn.send_request_checked(&x::ImageText8 {
+            drawable,
+            gc: r.gc,
+            x: overlay.text_x() as i16,
+            y: baseline,
+            string: line,
+        }));
+    }
+
+    display.log_request_errors("Draw overlay", cookies);
+}
+
+/// Draw the thumbnails until the overlay is hidden.
+async fn refresh_thumbnails(display: DisplayServer) {
+    let overlay = &display.0.overlay;
+
+    while overlay.thumbnails.get() {
+        let windows = overlay.windows.borrow().clone();
+
+        for (n, window) in windows.into_iter().enumerate() {
+            // Windows can be unmapped or destroyed at any time.
+            if let Err(e) = draw_thumbnail(&display, n, window).await {
+                tracing::trace!(window = window.resource_id(), "Thumbnail: {e}");
+            }
+        }
+
+        let _ = display.connection().flush();
+        tokio::time::sleep(THUMBNAIL_REFRESH).await;
+    }
+
+    overlay.refreshing.set(false);
+}
+
+/// Draw the thumbnail of `window` in the `nth` line.
+async fn draw_thumbnail(
+    display: &DisplayServer,
+    nth: usize,
+    window: x::Window,
+) -> Result<(), Error> {
+    let overlay = &display.0.overlay;
+    let Some(Resources {
+        picture: Some(dst), ..
+    }) = overlay.resources.get()
+    else {
+        return Ok(());
+    };
+
+    // The window manager can put the window in a frame. The pixmap is
+    // only available for the top-level window.
+    let frame = windowinfo::top_level(display, window).await?;
+
+    let geometry = x::GetGeometry {
+        drawable: x::Drawable::Window(frame),
+    };
+
+    let attributes = x::GetWindowAttributes { window: frame };
+
+    let (geometry, attributes) = tokio::join!(
+        display.send_request(&geometry),
+        display.send_request(&attributes),
+    );
+
+    let (geometry, attributes) = (geometry?, attributes?);
+
+    let format = overlay.formats.borrow().get(&attributes.visual()).copied();
+    let Some(format) = format else {
+        return Ok(());
+    };
+
+    let conn = display.connection();
+
+    let pixmap = conn.generate_id();
+    display
+        .send_void_request(&composite::NameWindowPixmap {
+            window: frame,
+            pixmap,
+        })
+        .await?;
+
+    // The requests are checked together, after the last one.
+    let mut cookies = Vec::new();
+
+    let src = conn.generate_id();
+    cookies.push(conn.send_request_checked(&render::CreatePicture {
+        pid: src,
+        drawable: x::Drawable::Pixmap(pixmap),
+        format,
+        value_list: &[],
+    }));
+
+    // Scale the window to fit in the thumbnail, keeping its aspect ratio.
+    let scale = f64::max(
+        f64::from(geometry.width()) / f64::from(THUMBNAIL_WIDTH),
+        f64::from(geometry.height()) / f64::from(THUMBNAIL_HEIGHT),
+    )
+    .max(1.0);
+
+    let fixed = |n: f64| (n * 65536.0) as render::Fixed;
+
+    cookies.push(conn.send_request_checked(&render::SetPictureTransform {
+        picture: src,
+        transform: render::Transform {
+            matrix11: fixed(scale),
+            matrix12: 0,
+            matrix13: 0,
+            matrix21: 0,
+            matrix22: fixed(scale),
+            matrix23: 0,
+            matrix31: 0,
+            matrix32: 0,
+            matrix33: fixed(1.0),
+        },
+    }));
+
+    cookies.push(conn.send_request_checked(&render::SetPictureFilter {
+        picture: src,
+        filter: b"bilinear",
+        values: &[],
+    }));
+
+    let width = (f64::from(geometry.width()) / scale) as u16;
+    let height = (f64::from(geometry.height()) / scale) as u16;
+    let row_height = overlay.row_height.get();
+    let top = PADDING + nth as u16 * row_height + (row_height - height) / 2;
+
+    cookies.push(conn.send_request_checked(&render::Composite {
+        op: render::PictOp::Src,
+        src,
+        mask: render::Picture::none(),
+        dst,
+        src_x: 0,
+        src_y: 0,
+        mask_x: 0,
+        mask_y: 0,
+        dst_x: (PADDING + (THUMBNAIL_WIDTH - width) / 2) as i16,
+        dst_y: top as i16,
+        width,
+        height,
+    }));
+
+    cookies.push(conn.send_request_checked(&render::FreePicture { picture: src }));
+    cookies.push(conn.send_request_checked(&x::FreePixmap { pixmap }));
+
+    display.check_requests(cookies).await
+}
+
+/// Return `true` if a compositor owns the `_NET_WM_CM_S0` selection.
+async fn compositor_running(display: &DisplayServer) -> Result<bool, Error> {
+    let req = x::GetSelectionOwner {
+        selection: display.atoms().net_wm_cm_s0,
+    };
+
+    Ok(!display.send_request(&req).await?.owner().is_none())
+}
+
+/// Create the window and the graphics context.
+async fn create(display: &DisplayServer) -> Result<Resources, Error> {
+    let conn = display.connection();
+    let screen = conn.get_setup().roots().next().unwrap();
+
+    let font: x::Font = conn.generate_id();
+    display
+        .send_void_request(&x::OpenFont {
+            fid: font,
+            name: b"fixed",
+        })
+        .await?;
+
+    let metrics = display
+        .send_request(&x::QueryFont {
+            font: x::Fontable::Font(font),
+        })
+        .await?;
+
+    let window = conn.generate_id();
+    display
+        .send_void_request(&x::CreateWindow {
+            depth: x::COPY_FROM_PARENT as u8,
+            wid: window,
+            parent: screen.root(),
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+            border_width: 1,
+            class: x::WindowClass::InputOutput,
+            visual: x::COPY_FROM_PARENT,
+            value_list: &[
+                x::Cw::BackPixel(screen.black_pixel()),
+                x::Cw::BorderPixel(screen.white_pixel()),
+                x::Cw::OverrideRedirect(true),
+                x::Cw::EventMask(x::EventMask::EXPOSURE),
+            ],
+        })
+        .await?;
+
+    let gc = conn.generate_id();
+    display
+        .send_void_request(&x::CreateGc {
+            cid: gc,
+            drawable: x::Drawable::Window(window),
+            value_list: &[
+                x::Gc::Foreground(screen.white_pixel()),
+                x::Gc::Background(screen.black_pixel()),
+                x::Gc::Font(font),
+            ],
+        })
+        .await?;
+
+    tracing::debug!(window = window.resource_id(), "Overlay created");
+
+    let picture = match create_picture(display, window, screen.root_visual()).await {
+        Ok(picture) => picture,
+        Err(e) => {
+            tracing::debug!("No thumbnails in the overlay: {e}");
+            None
+        }
+    };
+
+    let ascent = metrics.font_ascent().max(0) as u16;
+    let descent = metrics.font_descent().max(0) as u16;
+
+    Ok(Resources {
+        window,
+        gc,
+        char_width: metrics.max_bounds().character_width.max(1) as u16,
+        ascent,
+        line_height: ascent + descent + 2,
+        black: screen.black_pixel(),
+        white: screen.white_pixel(),
+        picture,
+    })
+}
+
+/// Create the picture to draw the thumbnails in the overlay, if the
+/// Composite and Render extensions are available.
+async fn create_picture(
+    display: &DisplayServer,
+    window: x::Window,
+    visual: x::Visualid,
+) -> Result<Option<render::Picture>, Error> {
+    let conn = display.connection();
+
+    let available = |ext| conn.active_extensions().any(|e| e == ext);
+    if !available(xcb::Extension::Composite) || !available(xcb::Extension::Render) {
+        return Ok(None);
+    }
+
+    // NameWindowPixmap needs version 0.2.
+    display
+        .send_request(&composite::QueryVersion {
+            client_major_version: 0,
+            client_minor_version: 4,
+        })
+        .await?;
+
+    let pict_formats = display.send_request(&render::QueryPictFormats {}).await?;
+
+    let formats: HashMap<_, _> = pict_formats
+        .screens()
+        .flat_map(|s| {
+            s.depths()
+                .flat_map(|d| d.visuals().to_vec())
+                .collect::<Vec<_>>()
+        })
+        .map(|v| (v.visual, v.format))
+        .collect();
+
+    let Some(&format) = formats.get(&visual) else {
+        return Ok(None);
+    };
+
+    display.0.overlay.formats.replace(formats);
+
+    let picture = conn.generate_id();
+    display
+        .send_void_request(&render::CreatePicture {
+            pid: picture,
+            drawable: x::Drawable::Window(window),
+            format,
+            value_list: &[],
+        })
+        .await?;
+
+    Ok(Some(picture))
+}
@@ -0,0 +1,247 @@
+//! Typed access to the properties of the windows.
+//!
+//! The getters return the whole value, and they fail if the property has a
+//! different type or format. A missing property is returned as `None`.
+//!
+//! The setters replace the value. List-valued properties can be updated
+//! incrementally with [`change`] in the `Append` and `Prepend` modes.
+
+use xcb::{x, Xid};
+
+use super::DisplayServer;
+use crate::Error;
+
+/// Return the value of a `STRING` property. The text is in Latin-1.
+pub async fn get_string(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+) -> Result<Option<String>, Error> {
+    let Some(reply) = get(display, window, property, x::ATOM_STRING, 8).await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(
+        reply.value::<u8>().iter().map(|&b| b as char).collect(),
+    ))
+}
+
+/// Return the value of a `UTF8_STRING` property.
+pub async fn get_utf8(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+) -> Result<Option<String>, Error> {
+    let utf8_string = display.atoms().utf8_string;
+    let Some(reply) = get(display, window, property, utf8_string, 8).await? else {
+        return Ok(None);
+    };
+
+    match String::from_utf8(reply.value::<u8>().to_vec()) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => Err(Error::InvalidProperty(format!("Invalid UTF-8: {e}"))),
+    }
+}
+
+/// Return the items of a `CARDINAL` property.
+pub async fn get_cardinals(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+) -> Result<Option<Vec<u32>>, Error> {
+    let reply = get(display, window, property, x::ATOM_CARDINAL, 32).await?;
+    Ok(reply.map(|r| r.value::<u32>().to_vec()))
+}
+
+/// Return the items of a `WINDOW` property.
+pub async fn get_windows(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+) -> Result<Option<Vec<x::Window>>, Error> {
+    let reply = get(display, window, property, x::ATOM_WINDOW, 32).await?;
+    Ok(reply.map(|r| r.value::<x::Window>().to_vec()))
+}
+
+/// Return the items of an `ATOM` property.
+pub async fn get_atoms(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+) -> Result<Option<Vec<x::Atom>>, Error> {
+    let reply = get(display, window, property, x::ATOM_ATOM, 32).await?;
+    Ok(reply.map(|r| r.value::<x::Atom>().to_vec()))
+}
+
+/// Replace the value of a property with a `STRING`. Characters out of
+/// Latin-1 are replaced with `?`.
+pub async fn set_string(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    value: &str,
+) -> Result<(), Error> {
+    let data: Vec<u8> = value
+        .chars()
+        .map(|c| u8::try_from(c).unwrap_or(b'?'))
+        .collect();
+
+    replace(display, window, property, x::ATOM_STRING, &data).await
+}
+
+/// Replace the value of a property with a `UTF8_STRING`.
+pub async fn set_utf8(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    value: &str,
+) -> Result<(), Error> {
+    let utf8_string = display.atoms().utf8_string;
+    replace(display, window, property, utf8_string, value.as_bytes()).await
+}
+
+/// Replace the value of a property with a `CARDINAL` array.
+pub async fn set_cardinals(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    value: &[u32],
+) -> Result<(), Error> {
+    replace(display, window, property, x::ATOM_CARDINAL, value).await
+}
+
+/// Replace the value of a property with a `WINDOW` array.
+pub async fn set_windows(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    value: &[x::Window],
+) -> Result<(), Error> {
+    replace(display, window, property, x::ATOM_WINDOW, value).await
+}
+
+/// Replace the value of a property with an `ATOM` array.
+pub async fn set_atoms(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    value: &[x::Atom],
+) -> Result<(), Error> {
+    replace(display, window, property, x::ATOM_ATOM, value).await
+}
+
+/// Add windows at the end of a `WINDOW` property.
+pub async fn append_windows(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    value: &[x::Window],
+) -> Result<(), Error> {
+    let mode = x::PropMode::Append;
+    change(display, window, property, x::ATOM_WINDOW, mode, value).await
+}
+
+/// Add windows at the start of a `WINDOW` property.
+pub async fn prepend_windows(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    value: &[x::Window],
+) -> Result<(), Error> {
+    let mode = x::PropMode::Prepend;
+    change(display, window, property, x::ATOM_WINDOW, mode, value).await
+}
+
+/// Change a property with items of any type.
+///
+/// With `Append` and `Prepend`, `r#type` and the size of the items must be
+/// the ones of the current value, or the X server returns a `Match` error.
+/// A missing property is created.
+pub async fn change<P: x::PropEl>(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    r#type: x::Atom,
+    mode: x::PropMode,
+    data: &[P],
+) -> Result<(), Error> {
+    let req = x::ChangeProperty {
+        mode,
+        window,
+        property,
+        r#type,
+        data,
+    };
+
+    display.send_void_request(&req).await
+}
+
+/// Remove a property. It is not an error if the property is missing.
+pub async fn delete(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+) -> Result<(), Error> {
+    display
+        .send_void_request(&x::DeleteProperty { window, property })
+        .await
+}
+
+/// Read a property of type `r#type`, with items of `format` bits.
+async fn get(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    r#type: x::Atom,
+    format: u8,
+) -> Result<Option<x::GetPropertyReply>, Error> {
+    let req = x::GetProperty {
+        delete: false,
+        window,
+        property,
+        r#type,
+        long_offset: 0,
+        long_length: u32::MAX / 4,
+    };
+
+    let reply = display.send_request(&req).await?;
+
+    if reply.r#type() == x::ATOM_NONE {
+        return Ok(None);
+    }
+
+    if reply.r#type() != r#type {
+        return Err(Error::InvalidProperty(format!(
+            "Expected type {}, found {}.",
+            r#type.resource_id(),
+            reply.r#type().resource_id(),
+        )));
+    }
+
+    if reply.format() != format {
+        return Err(Error::InvalidProperty(format!(
+            "Expected format {format}, found {}.",
+            reply.format(),
+        )));
+    }
+
+    Ok(Some(reply))
+}
+
+async fn replace<P: x::PropEl>(
+    display: &DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+    r#type: x::Atom,
+    data: &[P],
+) -> Result<(), Error> {
+    change(
+        display,
+        window,
+        property,
+        r#type,
+        x::PropMode::Replace,
+        data,
+    )
+    .await
+}
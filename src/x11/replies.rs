@@ -0,0 +1,272 @@
+//! Dispatcher of the replies to the requests.
+//!
+//! The futures returned by [`DisplayServer::send_request`] are registered
+//! with the sequence number of their request. The X server sends the
+//! replies in the same order of the requests, so when the connection is
+//! readable only the oldest pending request is woken. When it receives its
+//! reply, it wakes the next one, until a request without a reply is found.
+//!
+//! Requests without replies are synchronized with a request with a reply.
+//! See [`DisplayServer::send_void_request`].
+//!
+//! The number of requests waiting for a reply is limited. When the limit is
+//! reached, new requests wait until a previous one is finished.
+//!
+//! Requests are cancelled after a deadline, and the replies of the
+//! cancelled or dropped requests are discarded by libxcb when they are
+//! received, so nothing is kept for them in the dispatcher.
+//!
+//! [`DisplayServer::send_request`]: super::DisplayServer::send_request
+//! [`DisplayServer::send_void_request`]: super::DisplayServer::send_void_request
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use xcb::Cookie;
+
+use super::DisplayServer;
+use crate::Error;
+
+/// Default limit of requests waiting for a reply.
+pub const DEFAULT_MAX_PENDING: usize = 1024;
+
+/// Time to receive a reply before the request is cancelled, if no other
+/// deadline is given.
+pub const DEFAULT_DEADLINE: Duration = Duration::from_secs(30);
+
+pub(super) struct Replies {
+    /// Wakers of the pending requests, by sequence number.
+    waiters: RefCell<BTreeMap<u64, Waker>>,
+
+    /// Requests sent and not finished.
+    in_flight: Cell<usize>,
+
+    /// Limit of `in_flight`.
+    capacity: Cell<usize>,
+
+    /// Notified when a request is finished.
+    released: Notify,
+
+    /// Maximum number of pending requests.
+    max_pending: Cell<usize>,
+
+    /// If `true`, the main loop is stopped, and the pending requests are
+    /// cancelled.
+    closed: Cell<bool>,
+}
+
+impl Default for Replies {
+    fn default() -> Self {
+        Replies {
+            waiters: Default::default(),
+            in_flight: Cell::new(0),
+            capacity: Cell::new(DEFAULT_MAX_PENDING),
+            released: Notify::new(),
+            max_pending: Cell::new(0),
+            closed: Cell::new(false),
+        }
+    }
+}
+
+impl Replies {
+    /// Number of requests waiting for a reply.
+    pub(super) fn pending(&self) -> usize {
+        self.in_flight.get()
+    }
+
+    /// Change the limit of requests waiting for a reply.
+    pub(super) fn set_capacity(&self, capacity: usize) {
+        self.capacity.set(capacity.max(1));
+        self.released.notify_waiters();
+    }
+
+    /// Wait until a new request can be sent.
+    pub(super) async fn acquire(&self) {
+        while !self.closed.get() && self.in_flight.get() >= self.capacity.get() {
+            self.released.notified().await;
+        }
+
+        self.in_flight.set(self.in_flight.get() + 1);
+        self.max_pending
+            .set(self.max_pending.get().max(self.in_flight.get()));
+    }
+
+    /// Maximum number of requests waiting for a reply.
+    pub(super) fn max_pending(&self) -> usize {
+        self.max_pending.get()
+    }
+
+    /// Wake the oldest pending request. Called when new data is read from
+    /// the connection.
+    pub(super) fn dispatch(&self) {
+        if let Some((_, waker)) = self.waiters.borrow().first_key_value() {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// Start the main loop.
+    pub(super) fn open(&self) {
+        self.closed.set(false);
+    }
+
+    /// Cancel the pending requests, since the main loop is stopped.
+    pub(super) fn close(&self) {
+        self.closed.set(true);
+        self.released.notify_waiters();
+
+        for (_, waker) in std::mem::take(&mut *self.waiters.borrow_mut()) {
+            waker.wake();
+        }
+    }
+
+    fn register(&self, sequence: u64, waker: &Waker) {
+        let mut waiters = self.waiters.borrow_mut();
+
+        match waiters.get_mut(&sequence) {
+            Some(w) => w.clone_from(waker),
+            None => {
+                waiters.insert(sequence, waker.clone());
+            }
+        }
+    }
+
+    /// Remove a finished request, and wake the next one, since its reply
+    /// may be already received.
+    fn finish(&self, sequence: u64) {
+        self.in_flight.set(self.in_flight.get().saturating_sub(1));
+        self.released.notify_one();
+
+        let mut waiters = self.waiters.borrow_mut();
+        waiters.remove(&sequence);
+
+        if let Some((_, waker)) = waiters.range(sequence..).next() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// Reply of a request sent with [`DisplayServer::send_request`].
+pub(super) struct Reply<C>
+where
+    C: xcb::CookieWithReplyChecked + 'static,
+{
+    display: DisplayServer,
+    cookie: Option<C>,
+}
+
+impl<C> Reply<C>
+where
+    C: xcb::CookieWithReplyChecked + 'static,
+{
+    pub(super) fn new(display: DisplayServer, cookie: C) -> Self {
+        Reply {
+            display,
+            cookie: Some(cookie),
+        }
+    }
+}
+
+// The cookie is never pinned.
+impl<C> Unpin for Reply<C> where C: xcb::CookieWithReplyChecked + 'static {}
+
+impl<C> Future for Reply<C>
+where
+    C: xcb::CookieWithReplyChecked + 'static,
+{
+    type Output = Result<C::Reply, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let replies = &this.display.0.replies;
+
+        let Some(cookie) = &this.cookie else {
+            panic!("Reply polled after completion");
+        };
+
+        let sequence = cookie.sequence();
+
+        if let Some(reply) = this.display.connection().poll_for_reply(cookie) {
+            this.cookie = None;
+            replies.finish(sequence);
+            return Poll::Ready(Ok(reply?));
+        }
+
+        if replies.closed.get() {
+            this.cookie = None;
+            replies.finish(sequence);
+            return Poll::Ready(Err(Error::Cancelled));
+        }
+
+        replies.register(sequence, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<C> Drop for Reply<C>
+where
+    C: xcb::CookieWithReplyChecked + 'static,
+{
+    fn drop(&mut self) {
+        let Some(cookie) = self.cookie.take() else {
+            return;
+        };
+
+        discard(&self.display, cookie.sequence());
+        self.display.0.replies.finish(cookie.sequence());
+    }
+}
+
+/// Error of a checked request without a reply, read after the request is
+/// synchronized. See [`DisplayServer::send_void_request`].
+pub(super) struct VoidReply {
+    display: DisplayServer,
+    cookie: Option<xcb::VoidCookieChecked>,
+}
+
+impl VoidReply {
+    pub(super) fn new(display: DisplayServer, cookie: xcb::VoidCookieChecked) -> Self {
+        VoidReply {
+            display,
+            cookie: Some(cookie),
+        }
+    }
+
+    /// Read the error of the request. It does not block if the request
+    /// is synchronized.
+    pub(super) fn check(mut self) -> Result<(), Error> {
+        match self.cookie.take() {
+            Some(cookie) => Ok(self.display.connection().check_request(cookie)?),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for VoidReply {
+    fn drop(&mut self) {
+        if let Some(cookie) = self.cookie.take() {
+            discard(&self.display, cookie.sequence());
+        }
+    }
+}
+
+/// Tell libxcb to discard the reply, or the error, of a request when it
+/// is received, so it is not kept in the connection.
+fn discard(display: &DisplayServer, sequence: u64) {
+    let conn = display.connection().get_raw_conn();
+
+    // The sequence number is from a request sent in this connection, and
+    // its reply is not read anymore.
+    unsafe { xcb_discard_reply64(conn.cast(), sequence) };
+}
+
+extern "C" {
+    fn xcb_discard_reply64(c: *mut std::ffi::c_void, sequence: u64);
+}
@@ -1,8 +1,10 @@
-use super::Atoms;
+use super::{Atoms, DisplayServer};
 
 use xcb::x;
 
-pub(super) fn use_xkb(conn: &xcb::Connection) -> Result<(), xcb::Error> {
+use crate::Error;
+
+pub(super) fn use_xkb(conn: &xcb::Connection) -> Result<(), Error> {
     let req = xcb::xkb::UseExtension {
         wanted_major: 1,
         wanted_minor: 0,
@@ -15,7 +17,25 @@ pub(super) fn use_xkb(conn: &xcb::Connection) -> Result<(), xcb::Error> {
     Ok(())
 }
 
-pub(super) fn intern_atoms(conn: &xcb::Connection) -> Result<Atoms, xcb::Error> {
+/// Names of the `_NET_WM_WINDOW_TYPE_*` atoms, without the prefix.
+const WINDOW_TYPES: [&str; 14] = [
+    "desktop",
+    "dock",
+    "toolbar",
+    "menu",
+    "utility",
+    "splash",
+    "dialog",
+    "dropdown_menu",
+    "popup_menu",
+    "tooltip",
+    "notification",
+    "combo",
+    "dnd",
+    "normal",
+];
+
+pub(super) fn intern_atoms(conn: &xcb::Connection) -> Result<Atoms, Error> {
     macro_rules! atom {
         ($name:expr) => {
             conn.wait_for_reply(conn.send_request(&x::InternAtom {
@@ -26,30 +46,111 @@ pub(super) fn intern_atoms(conn: &xcb::Connection) -> Result<Atoms, xcb::Error>
         };
     }
 
+    let mut window_types = Vec::with_capacity(WINDOW_TYPES.len());
+    for name in WINDOW_TYPES {
+        let atom_name = format!("_NET_WM_WINDOW_TYPE_{}", name.to_ascii_uppercase());
+        window_types.push((atom!(atom_name), name));
+    }
+
     Ok(Atoms {
         net_active_window: atom!("_NET_ACTIVE_WINDOW"),
+        net_wm_name: atom!("_NET_WM_NAME"),
+        net_wm_desktop: atom!("_NET_WM_DESKTOP"),
+        net_wm_pid: atom!("_NET_WM_PID"),
+        net_wm_state: atom!("_NET_WM_STATE"),
+        net_wm_state_demands_attention: atom!("_NET_WM_STATE_DEMANDS_ATTENTION"),
+        net_wm_state_hidden: atom!("_NET_WM_STATE_HIDDEN"),
+        net_client_list: atom!("_NET_CLIENT_LIST"),
+        net_client_list_stacking: atom!("_NET_CLIENT_LIST_STACKING"),
+        net_restack_window: atom!("_NET_RESTACK_WINDOW"),
+        net_wm_window_type: atom!("_NET_WM_WINDOW_TYPE"),
+        window_types: window_types.into(),
+        net_current_desktop: atom!("_NET_CURRENT_DESKTOP"),
+        wm_state: atom!("WM_STATE"),
+        utf8_string: atom!("UTF8_STRING"),
+        i3_socket_path: atom!("I3_SOCKET_PATH"),
         switch_command: atom!("x11-alternate-focus/switch"),
+        manager: atom!("x11-alternate-focus/manager"),
+        history: atom!("x11-alternate-focus/history"),
+        published_history: atom!("_X11_ALTERNATE_FOCUS_HISTORY"),
+        command: atom!("_X11_ALTERNATE_FOCUS_COMMAND"),
+        timestamp: atom!("x11-alternate-focus/timestamp"),
+        manager_message: atom!("MANAGER"),
+        net_supporting_wm_check: atom!("_NET_SUPPORTING_WM_CHECK"),
+        net_wm_cm_s0: atom!("_NET_WM_CM_S0"),
     })
 }
 
-pub(super) fn listen_root_properties(
+/// Intern the `WM_Sn` atoms, used for the window manager selection of
+/// each screen.
+pub(super) fn wm_selections(
     conn: &xcb::Connection,
-) -> Result<Box<[x::Window]>, xcb::Error> {
-    let mut roots = Vec::new();
+    screens: usize,
+) -> Result<Box<[x::Atom]>, Error> {
+    (0..screens)
+        .map(|n| {
+            let name = format!("WM_S{n}");
+            let reply = conn.wait_for_reply(conn.send_request(&x::InternAtom {
+                only_if_exists: false,
+                name: name.as_bytes(),
+            }))?;
+
+            Ok(reply.atom())
+        })
+        .collect()
+}
 
-    let setup = conn.get_setup();
-    for screen in setup.roots() {
-        roots.push(screen.root());
+pub(super) fn roots(conn: &xcb::Connection) -> Box<[x::Window]> {
+    conn.get_setup().roots().map(|s| s.root()).collect()
+}
 
-        let req = conn.send_request_checked(&x::ChangeWindowAttributes {
-            window: screen.root(),
-            value_list: &[x::Cw::EventMask(
-                x::EventMask::PROPERTY_CHANGE | x::EventMask::STRUCTURE_NOTIFY,
-            )],
-        });
+/// Select the changes in the properties of the root windows, with the
+/// events in `extra_mask`.
+pub(super) async fn listen_root_properties(
+    display: &DisplayServer,
+    extra_mask: x::EventMask,
+) -> Result<(), Error> {
+    let event_mask = x::EventMask::STRUCTURE_NOTIFY | x::EventMask::PROPERTY_CHANGE | extra_mask;
 
-        conn.check_request(req)?;
+    for &root in display.roots() {
+        display
+            .send_void_request(&x::ChangeWindowAttributes {
+                window: root,
+                value_list: &[x::Cw::EventMask(event_mask)],
+            })
+            .await?;
     }
 
-    Ok(roots.into_boxed_slice())
+    Ok(())
+}
+
+/// Create a hidden window, used to own the manager selection in the
+/// server.
+///
+/// The request is not checked here. The cookie must be checked after
+/// another reply is received, so it does not need its own round trip.
+pub(super) fn create_window(
+    conn: &xcb::Connection,
+    root: x::Window,
+) -> (x::Window, xcb::VoidCookieChecked) {
+    let window = conn.generate_id();
+
+    let req = conn.send_request_checked(&x::CreateWindow {
+        depth: x::COPY_FROM_PARENT as u8,
+        wid: window,
+        parent: root,
+        x: -1,
+        y: -1,
+        width: 1,
+        height: 1,
+        border_width: 0,
+        class: x::WindowClass::InputOnly,
+        visual: x::COPY_FROM_PARENT,
+        value_list: &[
+            x::Cw::OverrideRedirect(true),
+            x::Cw::EventMask(x::EventMask::PROPERTY_CHANGE),
+        ],
+    });
+
+    (window, req)
 }
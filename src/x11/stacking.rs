@@ -0,0 +1,111 @@
+//! Stacking order of the activated windows.
+//!
+//! Window managers decide if an activated window is raised. After the
+//! activation, the server can send a `_NET_RESTACK_WINDOW` message to raise
+//! the window, or to put it back at its previous position.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use xcb::{x, Xid};
+
+use super::{property, DisplayServer};
+use crate::Error;
+
+/// Time for the window manager to process the activation before the
+/// window is put back at its position.
+const RESTORE_DELAY: Duration = Duration::from_millis(100);
+
+/// How the stacking order is changed after an activation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Raise {
+    /// Decided by the window manager.
+    #[default]
+    Auto,
+
+    /// The window is raised.
+    Always,
+
+    /// The window is kept at its position.
+    Never,
+}
+
+/// Return the client above `window` in `_NET_CLIENT_LIST_STACKING`, if any.
+pub(super) async fn window_above(
+    display: &DisplayServer,
+    window: x::Window,
+    root: x::Window,
+) -> Result<Option<x::Window>, Error> {
+    let property = display.atoms().net_client_list_stacking;
+    let Some(clients) = property::get_windows(display, root, property).await? else {
+        return Ok(None);
+    };
+
+    // The list is in bottom-to-top order.
+    Ok(clients
+        .iter()
+        .position(|&w| w == window)
+        .and_then(|index| clients.get(index + 1))
+        .copied())
+}
+
+/// Raise `window` after it is activated.
+pub(super) async fn raise(display: &DisplayServer, window: x::Window, root: x::Window) {
+    if let Err(e) = restack(display, window, root, None).await {
+        tracing::warn!("Raise: {e}");
+    }
+}
+
+/// Put `window` below `sibling` after the window manager processes the
+/// activation.
+pub(super) fn restore(
+    display: &DisplayServer,
+    window: x::Window,
+    root: x::Window,
+    sibling: x::Window,
+) {
+    let display = display.clone();
+    tokio::task::spawn_local(async move {
+        tokio::time::sleep(RESTORE_DELAY).await;
+
+        if let Err(e) = restack(&display, window, root, Some(sibling)).await {
+            tracing::warn!("Restack: {e}");
+        }
+    });
+}
+
+/// Send a `_NET_RESTACK_WINDOW` message. Without `sibling`, the window is
+/// put on top of the others. With it, it is put directly below it.
+///
+/// <https://specifications.freedesktop.org/wm-spec/1.5/ar01s04.html#id-1.5.8>
+async fn restack(
+    display: &DisplayServer,
+    window: x::Window,
+    root: x::Window,
+    sibling: Option<x::Window>,
+) -> Result<(), Error> {
+    const SOURCE_PAGER: u32 = 2;
+    const ABOVE: u32 = 0;
+    const BELOW: u32 = 1;
+
+    let (sibling, detail) = match sibling {
+        Some(sibling) => (sibling.resource_id(), BELOW),
+        None => (0, ABOVE),
+    };
+
+    let event = x::ClientMessageEvent::new(
+        window,
+        display.atoms().net_restack_window,
+        x::ClientMessageData::Data32([SOURCE_PAGER, sibling, detail, 0, 0]),
+    );
+
+    let req = x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(root),
+        event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
+        event: &event,
+    };
+
+    display.send_void_request(&req).await
+}
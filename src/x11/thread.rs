@@ -0,0 +1,121 @@
+//! Connection to the X server in a dedicated thread.
+//!
+//! [`DisplayServer`] is not `Send`, so it needs a [`LocalSet`]. Applications
+//! with a multi-threaded runtime can use a [`DisplayThread`] instead: the
+//! connection and its main loop run in a thread with its own runtime, and
+//! the handle sends closures to it from any task.
+
+use std::{future::Future, pin::Pin, thread};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::LocalSet,
+};
+
+use super::DisplayServer;
+use crate::Error;
+
+/// Closure executed in the thread of the connection.
+type Job = Box<dyn FnOnce(DisplayServer) -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+/// `Send` handle to a [`DisplayServer`] running in its own thread.
+///
+/// Clones share the same connection. The thread is stopped when all the
+/// handles are dropped.
+#[derive(Clone)]
+pub struct DisplayThread {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+impl DisplayThread {
+    /// Connect to the display `name`, or to the one in `$DISPLAY` if it is
+    /// `None`, and start its main loop in a new thread.
+    pub fn spawn(name: Option<String>) -> Result<DisplayThread, Error> {
+        let (jobs, receiver) = mpsc::unbounded_channel();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+
+        thread::Builder::new()
+            .name("x11-display".into())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(Error::Thread(e.to_string())));
+                        return;
+                    }
+                };
+
+                LocalSet::new().block_on(&runtime, async move {
+                    let display = match DisplayServer::connect(name.as_deref()) {
+                        Ok(display) => display,
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            return;
+                        }
+                    };
+
+                    let _ = ready_tx.send(Ok(()));
+                    run_jobs(display, receiver).await;
+                });
+            })
+            .map_err(|e| Error::Thread(e.to_string()))?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(DisplayThread { jobs }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(Error::Thread("stopped before connecting.".into())),
+        }
+    }
+
+    /// Run `f` with the connection in its thread, and return the output of
+    /// its future. It fails with [`Error::Cancelled`] if the main loop is
+    /// stopped before the future is completed, and with [`Error::Thread`]
+    /// if the future panics.
+    pub async fn run<F, Fut, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(DisplayServer) -> Fut + Send + 'static,
+        Fut: Future<Output = T> + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+
+        let job: Job = Box::new(move |display| {
+            Box::pin(async move {
+                let output = match tokio::task::spawn_local(f(display)).await {
+                    Ok(output) => Ok(output),
+                    Err(e) if e.is_panic() => Err(Error::Thread("job panicked.".into())),
+                    Err(_) => Err(Error::Cancelled),
+                };
+
+                let _ = tx.send(output);
+            })
+        });
+
+        self.jobs.send(job).map_err(|_| Error::Cancelled)?;
+        rx.await.map_err(|_| Error::Cancelled)?
+    }
+}
+
+/// Run the main loop, and spawn the jobs received from the handles, until
+/// the connection fails or all the handles are dropped.
+async fn run_jobs(display: DisplayServer, mut receiver: mpsc::UnboundedReceiver<Job>) {
+    let jobs = {
+        let display = display.clone();
+        async move {
+            while let Some(job) = receiver.recv().await {
+                tokio::task::spawn_local(job(display.clone()));
+            }
+
+            display.stop();
+        }
+    };
+
+    tokio::task::spawn_local(jobs);
+
+    if let Err(e) = display.main_loop().await {
+        tracing::error!("Display thread: {e}");
+    }
+}
@@ -0,0 +1,137 @@
+//! Windows demanding attention.
+//!
+//! The clients in `_NET_CLIENT_LIST` are watched for changes in
+//! `_NET_WM_STATE` (`_NET_WM_STATE_DEMANDS_ATTENTION`) and in the urgency
+//! hint of `WM_HINTS`.
+
+use std::{cell::RefCell, collections::HashSet};
+
+use xcb::x;
+
+use super::DisplayServer;
+use crate::Error;
+
+/// Flag in the first item of `WM_HINTS`.
+///
+/// <https://tronche.com/gui/x/icccm/sec-4.html#s-4.1.2.4>
+const URGENCY_HINT: u32 = 1 << 8;
+
+#[derive(Default)]
+pub(super) struct Urgency {
+    /// Clients with selected events.
+    clients: RefCell<HashSet<x::Window>>,
+
+    /// Windows demanding attention, most recent first.
+    windows: RefCell<Vec<x::Window>>,
+}
+
+impl Urgency {
+    /// Windows demanding attention, most recent first.
+    pub(super) fn windows(&self) -> Vec<x::Window> {
+        self.windows.borrow().clone()
+    }
+
+    /// Forget a destroyed window.
+    pub(super) fn remove(&self, window: x::Window) {
+        self.clients.borrow_mut().remove(&window);
+        self.windows.borrow_mut().retain(|&w| w != window);
+    }
+}
+
+/// Return `true` if a change in `property` can modify the urgency.
+pub(super) fn is_urgency_property(display: &DisplayServer, property: x::Atom) -> bool {
+    property == x::ATOM_WM_HINTS || property == display.atoms().net_wm_state
+}
+
+/// Read `_NET_CLIENT_LIST` in all root windows, and check the urgency of
+/// the new clients.
+pub(super) async fn update_clients(display: DisplayServer) {
+    for &root in display.roots() {
+        let req = x::GetProperty {
+            delete: false,
+            window: root,
+            property: display.atoms().net_client_list,
+            r#type: x::ATOM_WINDOW,
+            long_offset: 0,
+            long_length: u32::MAX / 4,
+        };
+
+        let clients = match display.send_request(&req).await {
+            Ok(reply) if reply.format() == 32 => reply.value::<x::Window>().to_vec(),
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::warn!("_NET_CLIENT_LIST: {e}");
+                continue;
+            }
+        };
+
+        for window in clients {
+            if !display.0.urgency.clients.borrow_mut().insert(window) {
+                continue;
+            }
+
+            display.watch_window(window).await;
+            check(display.clone(), window).await;
+        }
+    }
+}
+
+/// Update the urgency of `window`.
+pub(super) async fn check(display: DisplayServer, window: x::Window) {
+    let urgent = match is_urgent(&display, window).await {
+        Ok(urgent) => urgent,
+        Err(_) => {
+            // The window was destroyed.
+            display.0.urgency.remove(window);
+            return;
+        }
+    };
+
+    let mut windows = display.0.urgency.windows.borrow_mut();
+    let known = windows.contains(&window);
+
+    if urgent && !known {
+        tracing::debug!(?window, "Demands attention");
+        windows.insert(0, window);
+    } else if !urgent && known {
+        windows.retain(|&w| w != window);
+    }
+}
+
+async fn is_urgent(display: &DisplayServer, window: x::Window) -> Result<bool, Error> {
+    let atoms = display.atoms();
+
+    let state = x::GetProperty {
+        delete: false,
+        window,
+        property: atoms.net_wm_state,
+        r#type: x::ATOM_ATOM,
+        long_offset: 0,
+        long_length: 64,
+    };
+
+    let hints = x::GetProperty {
+        delete: false,
+        window,
+        property: x::ATOM_WM_HINTS,
+        r#type: x::ATOM_WM_HINTS,
+        long_offset: 0,
+        long_length: 1,
+    };
+
+    let (state, hints) = tokio::join!(display.send_request(&state), display.send_request(&hints));
+    let (state, hints) = (state?, hints?);
+
+    let demands_attention = state.format() == 32
+        && state
+            .value::<x::Atom>()
+            .contains(&atoms.net_wm_state_demands_attention);
+
+    let urgency_hint = hints.format() == 32
+        && hints
+            .value::<u32>()
+            .first()
+            .is_some_and(|flags| flags & URGENCY_HINT != 0);
+
+    Ok(demands_attention || urgency_hint)
+}
@@ -0,0 +1,138 @@
+use xcb::{x, Xid};
+
+use crate::{backend::WindowInfo, Error};
+
+pub(super) async fn query(
+    display: &super::DisplayServer,
+    window: x::Window,
+) -> Result<WindowInfo, Error> {
+    let atoms = display.atoms();
+
+    let property = |property, r#type| x::GetProperty {
+        delete: false,
+        window,
+        property,
+        r#type,
+        long_offset: 0,
+        long_length: 1024,
+    };
+
+    let wm_class = property(x::ATOM_WM_CLASS, x::ATOM_STRING);
+    let net_wm_name = property(atoms.net_wm_name, atoms.utf8_string);
+    let wm_name = property(x::ATOM_WM_NAME, x::ATOM_ANY);
+    let desktop = property(atoms.net_wm_desktop, x::ATOM_CARDINAL);
+    let pid = property(atoms.net_wm_pid, x::ATOM_CARDINAL);
+    let window_type = property(atoms.net_wm_window_type, x::ATOM_ATOM);
+    let transient_for = property(x::ATOM_WM_TRANSIENT_FOR, x::ATOM_WINDOW);
+
+    let (wm_class, net_wm_name, wm_name, desktop, pid, window_type, transient_for) = display
+        .batch(async {
+            tokio::join!(
+                display.send_request(&wm_class),
+                display.send_request(&net_wm_name),
+                display.send_request(&wm_name),
+                display.send_request(&desktop),
+                display.send_request(&pid),
+                display.send_request(&window_type),
+                display.send_request(&transient_for),
+            )
+        })
+        .await;
+
+    // Some clients use the root window for transients of a whole group.
+    let transient_for = cardinal(&transient_for?)
+        .filter(|&w| w != 0 && !display.roots().iter().any(|r| r.resource_id() == w))
+        .map(u64::from);
+
+    // WM_CLASS contains two strings: instance and class.
+    let class = bytes(&wm_class?)
+        .split(|&b| b == 0)
+        .rfind(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned());
+
+    let title = [net_wm_name?, wm_name?]
+        .iter()
+        .map(bytes)
+        .find(|v| !v.is_empty())
+        .map(|v| String::from_utf8_lossy(v).into_owned());
+
+    Ok(WindowInfo {
+        class,
+        title,
+        desktop: cardinal(&desktop?),
+        pid: cardinal(&pid?),
+        window_type: window_type_name(display, &window_type?),
+        transient_for,
+    })
+}
+
+pub(super) async fn text_property(
+    display: &super::DisplayServer,
+    window: x::Window,
+    property: x::Atom,
+) -> Result<Option<String>, Error> {
+    let req = x::GetProperty {
+        delete: false,
+        window,
+        property,
+        r#type: x::ATOM_ANY,
+        long_offset: 0,
+        long_length: 1024,
+    };
+
+    let reply = display.send_request(&req).await?;
+
+    Ok(match bytes(&reply) {
+        [] => None,
+        value => Some(String::from_utf8_lossy(value).into_owned()),
+    })
+}
+
+/// Return the name of the first known type in `_NET_WM_WINDOW_TYPE`. The
+/// list is in order of preference.
+fn window_type_name(display: &super::DisplayServer, reply: &x::GetPropertyReply) -> Option<String> {
+    if reply.format() != 32 {
+        return None;
+    }
+
+    let types = &display.atoms().window_types;
+    reply.value::<x::Atom>().iter().find_map(|atom| {
+        types
+            .iter()
+            .find(|(a, _)| a == atom)
+            .map(|(_, name)| name.to_string())
+    })
+}
+
+/// Return the first item of a property with 32-bit items.
+fn cardinal(reply: &x::GetPropertyReply) -> Option<u32> {
+    match reply.format() {
+        32 => reply.value::<u32>().first().copied(),
+        _ => None,
+    }
+}
+
+/// Return the value of a property with 8-bit items. Values in other formats
+/// are ignored.
+fn bytes(reply: &x::GetPropertyReply) -> &[u8] {
+    match reply.format() {
+        8 => reply.value(),
+        _ => &[],
+    }
+}
+
+/// Return the ancestor of `window` that is a child of the root window.
+pub(super) async fn top_level(
+    display: &super::DisplayServer,
+    mut window: x::Window,
+) -> Result<x::Window, Error> {
+    loop {
+        let tree = display.send_request(&x::QueryTree { window }).await?;
+
+        if tree.parent() == tree.root() || tree.parent().is_none() {
+            return Ok(window);
+        }
+
+        window = tree.parent();
+    }
+}
@@ -0,0 +1,304 @@
+//! Backend to test the server without a display.
+
+// Not all helpers are used in every test crate.
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    error::Error,
+    future::Future,
+};
+
+use tokio::{
+    sync::{mpsc, watch, Mutex},
+    time::Instant,
+};
+
+use x11_alternate_focus::backend::{
+    CycleKey, DisplayBackend, FocusEvent, ScreenState, Stats, WindowInfo,
+};
+
+pub type Window = u32;
+
+/// Backend controlled by the tests.
+///
+/// Focus events are injected with [`focus`](Self::focus) and
+/// [`close`](Self::close). Activated windows are focused immediately, like a
+/// window manager would do.
+pub struct MockBackend {
+    sender: mpsc::UnboundedSender<FocusEvent<Window>>,
+    events: Mutex<mpsc::UnboundedReceiver<FocusEvent<Window>>>,
+    modifiers: watch::Sender<bool>,
+    keys: watch::Sender<Option<CycleKey>>,
+    interactions: watch::Sender<Option<Instant>>,
+    detect_input: Cell<bool>,
+    windows: RefCell<HashMap<Window, WindowInfo>>,
+    unavailable: RefCell<HashSet<Window>>,
+    urgent: RefCell<Vec<Window>>,
+    activated: RefCell<Vec<Window>>,
+    overlay: RefCell<Option<(Vec<String>, usize)>>,
+    flashed: RefCell<Vec<(Window, String)>>,
+    published: RefCell<Vec<Window>>,
+    workspace: Cell<Option<u32>>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        let (sender, events) = mpsc::unbounded_channel();
+
+        MockBackend {
+            sender,
+            events: Mutex::new(events),
+            modifiers: watch::Sender::new(false),
+            keys: watch::Sender::new(None),
+            interactions: watch::Sender::new(None),
+            detect_input: Cell::new(true),
+            windows: Default::default(),
+            unavailable: Default::default(),
+            urgent: Default::default(),
+            activated: Default::default(),
+            overlay: Default::default(),
+            flashed: Default::default(),
+            published: Default::default(),
+            workspace: Default::default(),
+        }
+    }
+
+    pub fn focus(&self, window: Window) {
+        self.sender.send(FocusEvent::Focused(window)).unwrap();
+    }
+
+    pub fn close(&self, window: Window) {
+        self.sender.send(FocusEvent::Closed(window)).unwrap();
+    }
+
+    pub fn set_modifiers(&self, pressed: bool) {
+        self.modifiers.send_replace(pressed);
+    }
+
+    /// Press a key grabbed during a cycle.
+    pub fn press_key(&self, key: CycleKey) {
+        self.keys.send_replace(Some(key));
+    }
+
+    /// Press a key or a button in the focused window.
+    pub fn interact(&self) {
+        self.interactions.send_replace(Some(Instant::now()));
+    }
+
+    /// Behave like a backend that can't detect the key and button presses.
+    pub fn disable_input_detection(&self) {
+        self.detect_input.set(false);
+    }
+
+    pub fn set_info(&self, window: Window, class: &str, desktop: u32) {
+        let info = WindowInfo {
+            class: Some(class.to_string()),
+            title: Some(format!("Window {window}")),
+            desktop: Some(desktop),
+            pid: None,
+            window_type: None,
+            transient_for: None,
+        };
+
+        self.windows.borrow_mut().insert(window, info);
+    }
+
+    /// Set the process of a window added with [`set_info`](Self::set_info).
+    pub fn set_pid(&self, window: Window, pid: u32) {
+        if let Some(info) = self.windows.borrow_mut().get_mut(&window) {
+            info.pid = Some(pid);
+        }
+    }
+
+    pub fn set_workspace(&self, workspace: u32) {
+        self.workspace.set(Some(workspace));
+    }
+
+    /// Make `window` fail the availability check, like a withdrawn window.
+    pub fn withdraw(&self, window: Window) {
+        self.unavailable.borrow_mut().insert(window);
+    }
+
+    /// Set the type of a window added with [`set_info`](Self::set_info).
+    pub fn set_window_type(&self, window: Window, window_type: &str) {
+        if let Some(info) = self.windows.borrow_mut().get_mut(&window) {
+            info.window_type = Some(window_type.to_string());
+        }
+    }
+
+    /// Make `window` a transient of `parent`.
+    pub fn set_transient_for(&self, window: Window, parent: Window) {
+        if let Some(info) = self.windows.borrow_mut().get_mut(&window) {
+            info.transient_for = Some(parent.into());
+        }
+    }
+
+    /// Mark `window` as demanding attention.
+    pub fn set_urgent(&self, window: Window) {
+        self.urgent.borrow_mut().insert(0, window);
+    }
+
+    /// Lines in the overlay, and the selected one, if it is visible.
+    pub fn overlay(&self) -> Option<(Vec<String>, usize)> {
+        self.overlay.borrow().clone()
+    }
+
+    /// Windows flashed by the server, with the color.
+    pub fn flashed(&self) -> Vec<(Window, String)> {
+        self.flashed.borrow().clone()
+    }
+
+    /// Windows activated by the server.
+    pub fn activated(&self) -> Vec<Window> {
+        self.activated.borrow().clone()
+    }
+
+    /// Last history published by the server.
+    pub fn published(&self) -> Vec<Window> {
+        self.published.borrow().clone()
+    }
+}
+
+impl DisplayBackend for MockBackend {
+    type Window = Window;
+
+    async fn focus_event(&self) -> Result<FocusEvent<Window>, Box<dyn Error>> {
+        let mut events = self.events.lock().await;
+        events.recv().await.ok_or_else(|| "closed".into())
+    }
+
+    async fn modifiers_pressed(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(*self.modifiers.borrow())
+    }
+
+    async fn modifiers_released(&self) {
+        let _ = self
+            .modifiers
+            .subscribe()
+            .wait_for(|pressed| !pressed)
+            .await;
+    }
+
+    async fn interaction(&self, since: Instant) {
+        if !self.detect_input.get() {
+            return;
+        }
+
+        let _ = self
+            .interactions
+            .subscribe()
+            .wait_for(|time| time.is_some_and(|t| t >= since))
+            .await;
+    }
+
+    async fn cycle_key(&self) -> Result<CycleKey, Box<dyn Error>> {
+        let mut keys = self.keys.subscribe();
+        keys.mark_unchanged();
+
+        loop {
+            keys.changed().await?;
+            if let Some(key) = *keys.borrow_and_update() {
+                return Ok(key);
+            }
+        }
+    }
+
+    async fn show_overlay(
+        &self,
+        entries: &[(Window, String)],
+        selected: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let lines = entries.iter().map(|(_, line)| line.clone()).collect();
+        self.overlay.replace(Some((lines, selected)));
+        Ok(())
+    }
+
+    fn hide_overlay(&self) {
+        self.overlay.replace(None);
+    }
+
+    async fn flash(&self, window: Window, color: &str) -> Result<(), Box<dyn Error>> {
+        self.flashed.borrow_mut().push((window, color.to_string()));
+        Ok(())
+    }
+
+    async fn is_available(&self, window: Window) -> bool {
+        !self.unavailable.borrow().contains(&window)
+    }
+
+    fn urgent_windows(&self) -> Vec<Window> {
+        self.urgent.borrow().clone()
+    }
+
+    async fn activate(&self, window: Window) -> Result<(), Box<dyn Error>> {
+        self.activated.borrow_mut().push(window);
+        self.urgent.borrow_mut().retain(|&w| w != window);
+        self.focus(window);
+        Ok(())
+    }
+
+    async fn current_workspace(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self.workspace.get())
+    }
+
+    async fn current_output(&self) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    async fn window_output(&self, _: Window) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    async fn window_info(&self, window: Window) -> Result<WindowInfo, Box<dyn Error>> {
+        Ok(self
+            .windows
+            .borrow()
+            .get(&window)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn screens(&self) -> Option<usize> {
+        None
+    }
+
+    async fn screen_states(&self) -> Vec<ScreenState> {
+        Vec::new()
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        None
+    }
+
+    async fn publish_history(&self, windows: &[Window]) -> Result<(), Box<dyn Error>> {
+        self.published.replace(windows.to_vec());
+        Ok(())
+    }
+
+    fn window_id(window: Window) -> u64 {
+        window.into()
+    }
+
+    fn window_from_id(id: u64) -> Option<Window> {
+        id.try_into().ok()
+    }
+}
+
+/// Run `test` in a `LocalSet`, so tasks can be spawned with `spawn_local`.
+pub fn run<F: Future>(test: F) -> F::Output {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    tokio::task::LocalSet::new().block_on(&runtime, test)
+}
+
+/// Let the spawned tasks process the pending events.
+pub async fn settle() {
+    for _ in 0..20 {
+        tokio::task::yield_now().await;
+    }
+}
@@ -0,0 +1,1044 @@
+//! Tests for the focus tracker and the switch logic, with a mock backend.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use common::{run, settle, MockBackend, Window};
+use x11_alternate_focus::{
+    backend::{CycleKey, WindowInfo},
+    config::{Config, Transient},
+    rules::Rules,
+    server::{Server, Settings, CYCLE_TIMEOUT},
+    socket::{HistoryEntry, Request, Response, SwitchFilter},
+    state,
+};
+
+/// Create a server for `backend`, and start tracking the focus.
+fn start(backend: &Rc<MockBackend>) -> Rc<Server<MockBackend>> {
+    let server = Server::new(backend.clone());
+
+    let tracker = server.clone();
+    tokio::task::spawn_local(async move { tracker.track_focus().await });
+
+    server
+}
+
+/// Create a server with [`start`], and focus `windows` in order, so the
+/// last one is the current window.
+async fn start_with_history(
+    backend: &Rc<MockBackend>,
+    windows: &[Window],
+) -> Rc<Server<MockBackend>> {
+    let server = start(backend);
+
+    for &window in windows {
+        backend.focus(window);
+        settle().await;
+    }
+
+    server
+}
+
+#[test]
+fn switch_to_previous_window() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        assert_eq!(server.history(), [3, 2, 1]);
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [2]);
+        assert_eq!(server.history(), [2, 3, 1]);
+
+        server.switch(2, &SwitchFilter::default()).await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [2, 1]);
+        assert_eq!(server.history(), [1, 2, 3]);
+    });
+}
+
+#[test]
+fn ignore_windows_while_modifiers_are_pressed() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        // Cycle through the windows, like with Alt+Tab. Only the last one
+        // displaces the others.
+        backend.set_modifiers(true);
+
+        backend.focus(2);
+        settle().await;
+        backend.focus(1);
+        settle().await;
+
+        assert_eq!(server.history(), [1, 3, 2]);
+
+        backend.set_modifiers(false);
+        settle().await;
+
+        backend.focus(2);
+        settle().await;
+
+        assert_eq!(server.history(), [2, 1, 3]);
+    });
+}
+
+#[test]
+fn remove_closed_windows() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        backend.close(2);
+        settle().await;
+
+        assert_eq!(server.history(), [3, 1]);
+    });
+}
+
+#[test]
+fn skip_window_types() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        let config = Config {
+            skip_types: vec!["dialog".into(), "splash".into()],
+            ..Config::default()
+        };
+
+        server.configure(Settings::new(&config).unwrap());
+
+        for (window, window_type) in [(1, "normal"), (2, "dialog"), (3, "popup_dialog")] {
+            backend.set_info(window, "App", 0);
+            backend.set_window_type(window, window_type);
+            backend.focus(window);
+            settle().await;
+        }
+
+        assert_eq!(server.history(), [3, 1]);
+    });
+}
+
+#[test]
+fn skip_unavailable_windows() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        backend.withdraw(2);
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [1]);
+        assert_eq!(server.history(), [1, 3]);
+
+        let error = server.switch(5, &SwitchFilter::default()).await;
+        assert!(error.is_err());
+    });
+}
+
+#[test]
+fn exclude_windows_by_class() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        server.configure(Settings {
+            rules: Rules::new(&["class=^Skip$".to_string()]).unwrap(),
+            ..Settings::default()
+        });
+
+        backend.set_info(1, "Term", 0);
+        backend.set_info(2, "Skip", 0);
+        backend.set_info(3, "Browser", 0);
+
+        for window in [1, 2, 3] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        assert_eq!(server.history(), [3, 1]);
+    });
+}
+
+#[test]
+fn record_transient_windows() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        for window in [1, 2, 3, 4] {
+            backend.set_info(window, "App", 0);
+        }
+
+        backend.set_transient_for(3, 1);
+        backend.set_transient_for(4, 3);
+
+        let settings = |transient| Settings {
+            transient,
+            ..Settings::default()
+        };
+
+        // Skipped.
+        server.configure(settings(Transient::Skip));
+
+        for window in [1, 2, 3] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        assert_eq!(server.history(), [2, 1]);
+
+        // Replaced by the main window.
+        server.configure(settings(Transient::Parent));
+
+        backend.focus(4);
+        settle().await;
+
+        assert_eq!(server.history(), [1, 2]);
+    });
+}
+
+#[test]
+fn filter_by_workspace_and_class() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+
+        backend.set_info(1, "Term", 0);
+        backend.set_info(2, "Browser", 1);
+        backend.set_info(3, "Term", 1);
+        backend.set_info(4, "Term", 0);
+
+        let server = start_with_history(&backend, &[1, 2, 3, 4]).await;
+
+        backend.set_workspace(0);
+
+        let same_workspace = SwitchFilter {
+            same_workspace: true,
+            ..SwitchFilter::default()
+        };
+
+        server.switch(1, &same_workspace).await.unwrap();
+        assert_eq!(backend.activated(), [1]);
+
+        settle().await;
+
+        let same_class = SwitchFilter {
+            same_class: true,
+            ..SwitchFilter::default()
+        };
+
+        server.switch(1, &same_class).await.unwrap();
+        assert_eq!(backend.activated(), [1, 4]);
+
+        settle().await;
+
+        let browser = SwitchFilter {
+            class: Some("^Brow".into()),
+            ..SwitchFilter::default()
+        };
+
+        server.switch(1, &browser).await.unwrap();
+        assert_eq!(backend.activated(), [1, 4, 2]);
+
+        settle().await;
+
+        let invalid = SwitchFilter {
+            class: Some("(".into()),
+            ..SwitchFilter::default()
+        };
+
+        assert!(server.switch(1, &invalid).await.is_err());
+    });
+}
+
+#[test]
+fn filter_by_process() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+
+        for window in [1, 2, 3] {
+            backend.set_info(window, "Term", 0);
+            backend.set_pid(window, 100 + window % 2);
+        }
+
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        let filter = SwitchFilter {
+            pid: Some(100),
+            ..SwitchFilter::default()
+        };
+
+        server.switch(1, &filter).await.unwrap();
+        assert_eq!(backend.activated(), [2]);
+    });
+}
+
+#[test]
+fn never_switch_desktops() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        server.configure(Settings {
+            no_desktop_switch: true,
+            ..Settings::default()
+        });
+
+        backend.set_info(1, "Term", 0);
+        backend.set_info(2, "Term", 1);
+        backend.set_info(3, "Term", 0);
+
+        for window in [1, 2, 3] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        backend.set_workspace(0);
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        assert_eq!(backend.activated(), [1]);
+    });
+}
+
+#[test]
+fn accept_after_timeout() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        server.configure(Settings {
+            accept_timeout: Some(Duration::from_millis(50)),
+            ..Settings::default()
+        });
+
+        for window in [1, 2] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        backend.set_modifiers(true);
+        backend.focus(3);
+        settle().await;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // The modifiers are still pressed, but the previous window was
+        // accepted, so it is kept in the history.
+        backend.focus(1);
+        settle().await;
+
+        assert_eq!(server.history(), [1, 3, 2]);
+    });
+}
+
+#[test]
+fn ignore_brief_focus_changes() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        server.configure(Settings {
+            min_dwell: Some(Duration::from_millis(50)),
+            ..Settings::default()
+        });
+
+        for window in [1, 2] {
+            backend.focus(window);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        // A popup is focused, and the focus returns to the previous window
+        // before the minimum time.
+        backend.focus(3);
+        settle().await;
+        backend.focus(2);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(server.history(), [2, 1]);
+    });
+}
+
+#[test]
+fn suppress_focus_flicker() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        server.configure(Settings {
+            flicker_interval: Some(Duration::from_millis(100)),
+            ..Settings::default()
+        });
+
+        for window in [1, 2, 3] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        // New and known windows are put back when the focus returns.
+        for window in [4, 1] {
+            backend.focus(window);
+            settle().await;
+            backend.focus(3);
+            settle().await;
+
+            assert_eq!(server.history(), [3, 2, 1]);
+        }
+
+        // After the interval, the window is kept.
+        backend.focus(1);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        backend.focus(3);
+        settle().await;
+
+        assert_eq!(server.history(), [3, 1, 2]);
+    });
+}
+
+#[test]
+fn walk_history_while_modifiers_are_pressed() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3, 4]).await;
+
+        backend.set_modifiers(true);
+
+        for _ in 0..2 {
+            server.switch(1, &SwitchFilter::default()).await.unwrap();
+            settle().await;
+        }
+
+        assert_eq!(backend.activated(), [3, 2]);
+
+        backend.set_modifiers(false);
+        settle().await;
+
+        assert_eq!(server.history(), [2, 4, 3, 1]);
+    });
+}
+
+#[test]
+fn cycle_in_both_directions() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3, 4]).await;
+
+        let filter = SwitchFilter::default();
+        for step in [1, 1, -1, -1, -1] {
+            server.cycle(step, &filter).await.unwrap();
+            settle().await;
+        }
+
+        assert_eq!(backend.activated(), [3, 2, 3, 4, 1]);
+        assert_eq!(server.history(), [1, 4, 3, 2]);
+
+        // The cycle ends, and the last window is accepted.
+        tokio::time::sleep(CYCLE_TIMEOUT + Duration::from_millis(100)).await;
+
+        backend.focus(4);
+        settle().await;
+
+        assert_eq!(server.history(), [4, 1, 3, 2]);
+    });
+}
+
+#[test]
+fn end_cycle_with_keys() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3, 4]).await;
+
+        backend.set_modifiers(true);
+
+        let filter = SwitchFilter::default();
+        for _ in 0..2 {
+            server.cycle(1, &filter).await.unwrap();
+            settle().await;
+        }
+
+        // Escape returns to the window before the cycle.
+        backend.press_key(CycleKey::Cancel);
+        settle().await;
+
+        assert_eq!(backend.activated(), [3, 2, 4]);
+
+        backend.set_modifiers(false);
+        settle().await;
+        assert_eq!(server.history(), [4, 3, 2, 1]);
+
+        // Enter accepts the window without releasing the modifiers.
+        backend.set_modifiers(true);
+        server.cycle(1, &filter).await.unwrap();
+        settle().await;
+
+        backend.press_key(CycleKey::Commit);
+        settle().await;
+
+        // 3 is accepted, so it is not displaced by the next window.
+        backend.focus(1);
+        settle().await;
+
+        assert_eq!(server.history(), [1, 3, 4, 2]);
+    });
+}
+
+#[test]
+fn show_overlay_while_cycling() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        server.configure(Settings {
+            overlay: true,
+            ..Settings::default()
+        });
+
+        for window in [1, 2, 3] {
+            backend.set_info(window, &format!("app{window}"), 0);
+            backend.focus(window);
+            settle().await;
+        }
+
+        backend.set_modifiers(true);
+
+        for _ in 0..2 {
+            server.cycle(1, &SwitchFilter::default()).await.unwrap();
+            settle().await;
+        }
+
+        let (lines, selected) = backend.overlay().unwrap();
+        assert_eq!(
+            lines,
+            ["app3  Window 3", "app2  Window 2", "app1  Window 1"]
+        );
+        assert_eq!(selected, 2);
+
+        // The overlay is hidden when the cycle is finished.
+        backend.set_modifiers(false);
+        settle().await;
+
+        assert!(backend.overlay().is_none());
+    });
+}
+
+#[test]
+fn flash_activated_windows() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2]).await;
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        settle().await;
+        assert!(backend.flashed().is_empty());
+
+        server.configure(Settings {
+            flash: Some("#ff8800".into()),
+            ..Settings::default()
+        });
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        settle().await;
+        assert_eq!(backend.flashed(), [(2, "#ff8800".to_string())]);
+    });
+}
+
+#[test]
+fn activate_window_by_id() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        server.activate(1).await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [1]);
+        assert_eq!(server.history(), [1, 3, 2]);
+
+        // Windows not in the history are added by the tracker.
+        server.activate(4).await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [1, 4]);
+        assert_eq!(server.history(), [4, 1, 3, 2]);
+
+        backend.withdraw(5);
+        assert!(server.activate(5).await.is_err());
+    });
+}
+
+#[test]
+fn find_switch_target_without_activating() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        backend.withdraw(2);
+
+        let filter = SwitchFilter::default();
+        assert_eq!(server.switch_target(1, &filter).await.unwrap(), 1);
+        assert_eq!(server.switch_target(2, &filter).await.unwrap(), 1);
+        assert!(server.switch_target(3, &filter).await.is_err());
+
+        settle().await;
+
+        assert!(backend.activated().is_empty());
+        assert_eq!(server.history(), [3, 2, 1]);
+    });
+}
+
+#[test]
+fn peek_previous_window() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        let filter = SwitchFilter::default();
+        assert!(server.peek(&filter).await.is_err());
+
+        // Releasing the modifiers returns to the current window.
+        backend.set_modifiers(true);
+        server.peek(&filter).await.unwrap();
+        settle().await;
+
+        backend.set_modifiers(false);
+        settle().await;
+
+        assert_eq!(backend.activated(), [2, 3]);
+        assert_eq!(server.history(), [3, 2, 1]);
+
+        // Enter keeps the previous window.
+        backend.set_modifiers(true);
+        server.peek(&filter).await.unwrap();
+        settle().await;
+
+        backend.press_key(CycleKey::Commit);
+        settle().await;
+
+        backend.set_modifiers(false);
+        settle().await;
+
+        assert_eq!(backend.activated(), [2, 3, 2]);
+        assert_eq!(server.history(), [2, 3, 1]);
+    });
+}
+
+#[test]
+fn jump_to_marked_windows() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        backend.focus(1);
+        settle().await;
+        server.mark("a").unwrap();
+
+        for window in [2, 3, 4] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        server.mark("b").unwrap();
+        server.goto("a").await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [1]);
+        assert_eq!(server.history(), [1, 4, 3, 2]);
+        assert_eq!(server.marks(), [("a".into(), 1), ("b".into(), 4)]);
+
+        // Marks are removed with the windows.
+        backend.close(4);
+        settle().await;
+
+        assert_eq!(server.marks(), [("a".into(), 1)]);
+        assert!(server.goto("b").await.is_err());
+    });
+}
+
+#[test]
+fn switch_to_urgent_window() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        assert!(server.switch_urgent().await.is_err());
+
+        backend.set_urgent(5);
+        backend.set_urgent(1);
+        backend.withdraw(1);
+
+        server.switch_urgent().await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [5]);
+        assert_eq!(server.history(), [5, 3, 2, 1]);
+
+        // A normal switch returns to the previous window.
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [5, 3]);
+    });
+}
+
+#[test]
+fn return_from_stolen_focus() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        for window in [1, 2] {
+            backend.interact();
+            backend.focus(window);
+            settle().await;
+        }
+
+        assert!(server.switch_stolen().await.is_err());
+
+        // A known window focused without interaction is not a steal.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        backend.focus(1);
+        settle().await;
+        assert!(server.switch_stolen().await.is_err());
+
+        // A new window is.
+        backend.focus(3);
+        settle().await;
+
+        server.switch_stolen().await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [1]);
+        assert_eq!(server.history(), [1, 3, 2]);
+        assert!(server.switch_stolen().await.is_err());
+    });
+}
+
+#[test]
+fn backend_without_input_detection() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        backend.disable_input_detection();
+
+        let server = start(&backend);
+
+        server.configure(Settings {
+            ignore_hover: true,
+            ..Settings::default()
+        });
+
+        // Windows are accepted without waiting for an interaction, and new
+        // windows are not considered focus steals.
+        for window in [1, 2, 3] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        assert_eq!(server.history(), [3, 2, 1]);
+        assert!(server.switch_stolen().await.is_err());
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        settle().await;
+
+        assert_eq!(backend.activated(), [2]);
+        assert_eq!(server.history(), [2, 3, 1]);
+    });
+}
+
+#[test]
+fn watch_active_window() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        let mut changes = server.watch_active_window();
+
+        backend.set_info(1, "Term", 0);
+        backend.set_info(2, "Mail", 0);
+
+        for window in [1, 2] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        let first = changes.next().await.unwrap();
+        assert_eq!(first.window.id, 1);
+        assert_eq!(first.window.info.class.as_deref(), Some("Term"));
+
+        let second = changes.next().await.unwrap();
+        assert_eq!(second.window.id, 2);
+        assert_eq!(second.window.info.class.as_deref(), Some("Mail"));
+        assert!(second.timestamp >= first.timestamp);
+    });
+}
+
+#[test]
+fn pause_tracking() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2]).await;
+
+        server.handle(Request::Pause).await;
+
+        backend.focus(3);
+        settle().await;
+        backend.close(1);
+        settle().await;
+
+        assert_eq!(server.history(), [2]);
+
+        server.handle(Request::Resume).await;
+
+        backend.focus(4);
+        settle().await;
+
+        assert_eq!(server.history(), [4, 2]);
+    });
+}
+
+#[test]
+fn publish_history() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        assert_eq!(backend.published(), [3, 2, 1]);
+
+        backend.focus(1);
+        settle().await;
+
+        assert_eq!(backend.published(), [1, 3, 2]);
+
+        // Changes without a new accepted window are also published.
+        backend.close(3);
+        settle().await;
+
+        assert_eq!(backend.published(), [1, 2]);
+
+        let entry = |id| HistoryEntry {
+            id,
+            info: WindowInfo::default(),
+        };
+
+        server.import(&[entry(4), entry(2)]).await;
+        settle().await;
+
+        assert_eq!(backend.published(), [1, 4, 2]);
+    });
+}
+
+#[test]
+fn text_commands() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        let request = Request::parse("switch 2").unwrap();
+        assert!(matches!(server.handle(request).await, Response::Ok));
+        settle().await;
+
+        assert_eq!(server.history(), [1, 3, 2]);
+    });
+}
+
+#[test]
+fn reload_settings() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        assert!(server.reload().is_err());
+
+        for window in [1, 2, 3] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        let config = Rc::new(RefCell::new(Config::default()));
+        let source = config.clone();
+        server.set_reload(move || Settings::new(&source.borrow()));
+
+        config.borrow_mut().history_size = Some(2);
+        server.reload().unwrap();
+
+        assert_eq!(server.history(), [3, 2]);
+
+        // Invalid settings are not applied.
+        config.borrow_mut().history_size = Some(1);
+        assert!(server.reload().is_err());
+
+        backend.focus(4);
+        settle().await;
+
+        assert_eq!(server.history(), [4, 3]);
+    });
+}
+
+#[test]
+fn quit_server() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        assert!(matches!(server.handle(Request::Quit).await, Response::Ok));
+
+        let quit = tokio::time::timeout(Duration::from_secs(1), server.quit_requested());
+        assert!(quit.await.is_ok());
+    });
+}
+
+#[test]
+fn save_and_restore_state() {
+    run(async {
+        let path = std::env::temp_dir().join(format!(
+            "x11-alternate-focus-state-{}.json",
+            std::process::id()
+        ));
+
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        state::save(&server, &path).await.unwrap();
+
+        // The new server keeps its current window.
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        backend.focus(4);
+        settle().await;
+
+        state::restore(&server, &path).await.unwrap();
+        assert_eq!(server.history(), [4, 3, 2, 1]);
+
+        // The file is used only once.
+        assert!(!path.exists());
+        state::restore(&server, &path).await.unwrap();
+    });
+}
+
+#[test]
+fn record_activation_latency() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2]).await;
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        assert!(server.switch(5, &SwitchFilter::default()).await.is_err());
+
+        let Response::Stats(metrics) = server.handle(Request::Stats).await else {
+            panic!("Expected a stats response");
+        };
+
+        assert_eq!(metrics.activations.count, 2);
+        assert!(metrics.activations.max_us >= metrics.activations.average_us());
+        assert!(metrics.display.is_none());
+    });
+}
+
+#[test]
+fn dump_internal_state() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        server.mark("a").unwrap();
+        backend.set_urgent(1);
+
+        // The new window is pending until the modifiers are released.
+        backend.set_modifiers(true);
+        backend.focus(1);
+        settle().await;
+
+        let Response::State(state) = server.handle(Request::DumpState).await else {
+            panic!("Expected a state response");
+        };
+
+        let history: Vec<_> = state
+            .history
+            .iter()
+            .map(|e| (e.window.id, e.accepted))
+            .collect();
+
+        assert_eq!(history, [(1, false), (3, true), (2, true)]);
+        assert_eq!(state.committed, [3, 2, 1]);
+        assert_eq!(state.marks.get("a"), Some(&3));
+        assert_eq!(state.urgent, [1]);
+        assert!(state.status.waiting_modifiers);
+    });
+}
+
+#[test]
+fn import_history() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start_with_history(&backend, &[1, 2, 3]).await;
+
+        backend.set_info(4, "Firefox", 0);
+        backend.set_info(5, "XTerm", 0);
+        backend.withdraw(6);
+
+        let entry = |id, class: Option<&str>| HistoryEntry {
+            id,
+            info: WindowInfo {
+                class: class.map(Into::into),
+                ..WindowInfo::default()
+            },
+        };
+
+        // 5 has a different class, and 6 is withdrawn.
+        let entries = [
+            entry(4, Some("Firefox")),
+            entry(5, Some("Emacs")),
+            entry(6, None),
+            entry(1, None),
+        ];
+
+        server.import(&entries).await;
+
+        assert_eq!(server.history(), [3, 4, 1]);
+    });
+}
+
+#[test]
+fn ignore_hovered_windows() {
+    run(async {
+        let backend = Rc::new(MockBackend::new());
+        let server = start(&backend);
+
+        server.configure(Settings {
+            ignore_hover: true,
+            ..Settings::default()
+        });
+
+        for window in [1, 2] {
+            backend.focus(window);
+            backend.interact();
+            settle().await;
+        }
+
+        // The pointer moves over 3 and 4, without clicking them.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        for window in [3, 4] {
+            backend.focus(window);
+            settle().await;
+        }
+
+        assert_eq!(server.history(), [4, 2, 1]);
+
+        // A click after the focus change accepts the window, and the
+        // hovered one is discarded.
+        backend.focus(3);
+        settle().await;
+        backend.interact();
+        settle().await;
+
+        assert_eq!(server.history(), [3, 2, 1]);
+    });
+}
@@ -0,0 +1,550 @@
+//! Tests with a real X server.
+//!
+//! They need `Xvfb` in `$PATH`, so they are ignored by default. Run them
+//! with `cargo test -- --ignored`.
+//!
+//! The window manager is replaced by a script that focuses the windows
+//! requested with `_NET_ACTIVE_WINDOW` messages, or it is not used when the
+//! input focus is tracked.
+
+mod common;
+
+use std::{
+    process::{Child, Command},
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+use xcb::{x, Xid};
+
+use x11_alternate_focus::{
+    backend::FocusEvent,
+    server::Server,
+    socket::SwitchFilter,
+    x11::{property, Command as ServerCommand, DisplayServer, DisplayThread, Tracking},
+    Error,
+};
+
+const DISPLAY: &str = ":97";
+
+/// Display for the tests without a window manager.
+const DISPLAY_NO_WM: &str = ":98";
+
+/// Display for the connection in its own thread.
+const DISPLAY_THREAD: &str = ":96";
+
+/// Display for the property tests.
+const DISPLAY_PROPERTY: &str = ":95";
+
+/// Display for the tests of the executable.
+const DISPLAY_COMMAND: &str = ":94";
+
+/// Display for the command property.
+const DISPLAY_COMMAND_PROPERTY: &str = ":93";
+
+/// Display for the bursts of focus changes.
+const DISPLAY_FOCUS_BURST: &str = ":92";
+
+/// Xvfb process, killed when the value is dropped.
+struct Xvfb(Child);
+
+impl Xvfb {
+    fn start(display: &str) -> Xvfb {
+        let xvfb = Command::new("Xvfb")
+            .args([display, "-screen", "0", "800x600x24", "-nolisten", "tcp"])
+            .spawn()
+            .map(Xvfb)
+            .expect("Xvfb is not available");
+
+        for _ in 0..50 {
+            if xcb::Connection::connect(Some(display)).is_ok() {
+                return xvfb;
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        panic!("Xvfb is not ready");
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Command to execute the program in `DISPLAY_COMMAND`, with the runtime
+/// and configuration directories in `dir`.
+fn program(dir: &std::path::Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_x11-alternate-focus"));
+    command
+        .env("DISPLAY", DISPLAY_COMMAND)
+        .env("XDG_RUNTIME_DIR", dir)
+        .env("XDG_CONFIG_HOME", dir);
+    command
+}
+
+/// Intern the atom `name` in the connection.
+fn atom(conn: &xcb::Connection, name: &str) -> x::Atom {
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: false,
+        name: name.as_bytes(),
+    });
+
+    conn.wait_for_reply(cookie).unwrap().atom()
+}
+
+/// Create `count` windows in `display`, and start a thread to update
+/// `_NET_ACTIVE_WINDOW` when a window is activated.
+fn fake_window_manager(display: &str, count: usize) -> Vec<x::Window> {
+    let (conn, screen) = xcb::Connection::connect(Some(display)).unwrap();
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .unwrap()
+        .root();
+
+    let net_active_window = atom(&conn, "_NET_ACTIVE_WINDOW");
+    let wm_state = atom(&conn, "WM_STATE");
+
+    conn.send_and_check_request(&x::ChangeWindowAttributes {
+        window: root,
+        value_list: &[x::Cw::EventMask(
+            x::EventMask::SUBSTRUCTURE_REDIRECT | x::EventMask::SUBSTRUCTURE_NOTIFY,
+        )],
+    })
+    .unwrap();
+
+    let windows: Vec<x::Window> = (0..count)
+        .map(|_| {
+            let window = conn.generate_id();
+
+            conn.send_and_check_request(&x::CreateWindow {
+                depth: x::COPY_FROM_PARENT as u8,
+                wid: window,
+                parent: root,
+                x: 0,
+                y: 0,
+                width: 100,
+                height: 100,
+                border_width: 0,
+                class: x::WindowClass::InputOutput,
+                visual: x::COPY_FROM_PARENT,
+                value_list: &[],
+            })
+            .unwrap();
+
+            // NormalState
+            conn.send_and_check_request(&x::ChangeProperty {
+                mode: x::PropMode::Replace,
+                window,
+                property: wm_state,
+                r#type: wm_state,
+                data: &[1u32, 0],
+            })
+            .unwrap();
+
+            window
+        })
+        .collect();
+
+    thread::spawn(move || loop {
+        let event = match conn.wait_for_event() {
+            Ok(event) => event,
+            Err(_) => return,
+        };
+
+        if let xcb::Event::X(x::Event::ClientMessage(ev)) = event {
+            if ev.r#type() == net_active_window {
+                set_active_window(&conn, root, net_active_window, ev.window());
+            }
+        }
+    });
+
+    windows
+}
+
+fn set_active_window(conn: &xcb::Connection, root: x::Window, atom: x::Atom, window: x::Window) {
+    conn.send_and_check_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window: root,
+        property: atom,
+        r#type: x::ATOM_WINDOW,
+        data: &[window.resource_id()],
+    })
+    .unwrap();
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn switch_with_ewmh() {
+    let _xvfb = Xvfb::start(DISPLAY);
+    std::env::set_var("DISPLAY", DISPLAY);
+
+    let windows = fake_window_manager(DISPLAY, 3);
+
+    let (conn, screen) = xcb::Connection::connect(Some(DISPLAY)).unwrap();
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .unwrap()
+        .root();
+    let net_active_window = atom(&conn, "_NET_ACTIVE_WINDOW");
+
+    common::run(async {
+        let display = DisplayServer::new().unwrap();
+        let server = Server::new(Rc::new(display.clone()));
+
+        let main_loop = display.clone();
+        tokio::task::spawn_local(async move { main_loop.main_loop().await });
+
+        display
+            .listen_root_events(Tracking::ActiveWindow)
+            .await
+            .unwrap();
+
+        let tracker = server.clone();
+        tokio::task::spawn_local(async move { tracker.track_focus().await });
+
+        for &window in &windows {
+            set_active_window(&conn, root, net_active_window, window);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        assert_eq!(server.history(), [windows[2], windows[1], windows[0]]);
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(server.history(), [windows[1], windows[2], windows[0]]);
+
+        display.stop();
+    });
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn switch_with_input_focus() {
+    let _xvfb = Xvfb::start(DISPLAY_NO_WM);
+
+    let (conn, screen) = xcb::Connection::connect(Some(DISPLAY_NO_WM)).unwrap();
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .unwrap()
+        .root();
+
+    // Without a window manager, windows are mapped directly.
+    let windows: Vec<x::Window> = (0..3)
+        .map(|_| {
+            let window = conn.generate_id();
+
+            conn.send_and_check_request(&x::CreateWindow {
+                depth: x::COPY_FROM_PARENT as u8,
+                wid: window,
+                parent: root,
+                x: 0,
+                y: 0,
+                width: 100,
+                height: 100,
+                border_width: 0,
+                class: x::WindowClass::InputOutput,
+                visual: x::COPY_FROM_PARENT,
+                value_list: &[],
+            })
+            .unwrap();
+
+            conn.send_and_check_request(&x::MapWindow { window })
+                .unwrap();
+
+            window
+        })
+        .collect();
+
+    common::run(async {
+        let display = DisplayServer::connect(Some(DISPLAY_NO_WM)).unwrap();
+        let server = Server::new(Rc::new(display.clone()));
+
+        let main_loop = display.clone();
+        tokio::task::spawn_local(async move { main_loop.main_loop().await });
+
+        display
+            .listen_root_events(Tracking::InputFocus)
+            .await
+            .unwrap();
+
+        let tracker = server.clone();
+        tokio::task::spawn_local(async move { tracker.track_focus().await });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for &window in &windows {
+            conn.send_and_check_request(&x::SetInputFocus {
+                revert_to: x::InputFocus::PointerRoot,
+                focus: window,
+                time: x::CURRENT_TIME,
+            })
+            .unwrap();
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        assert_eq!(server.history(), [windows[2], windows[1], windows[0]]);
+
+        server.switch(1, &SwitchFilter::default()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(server.history(), [windows[1], windows[2], windows[0]]);
+
+        display.stop();
+    });
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn focus_burst() {
+    let _xvfb = Xvfb::start(DISPLAY_FOCUS_BURST);
+
+    let windows = fake_window_manager(DISPLAY_FOCUS_BURST, 3);
+
+    let (conn, screen) = xcb::Connection::connect(Some(DISPLAY_FOCUS_BURST)).unwrap();
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .unwrap()
+        .root();
+    let net_active_window = atom(&conn, "_NET_ACTIVE_WINDOW");
+
+    common::run(async {
+        let display = DisplayServer::connect(Some(DISPLAY_FOCUS_BURST)).unwrap();
+        display.set_focus_quiet_time(Duration::from_millis(50));
+
+        let main_loop = display.clone();
+        tokio::task::spawn_local(async move { main_loop.main_loop().await });
+
+        display
+            .listen_root_events(Tracking::ActiveWindow)
+            .await
+            .unwrap();
+
+        // Only the last window of the burst is reported.
+        for &window in &windows {
+            set_active_window(&conn, root, net_active_window, window);
+        }
+
+        let event = display.next_focus_event().await.unwrap();
+        assert!(matches!(event, FocusEvent::Focused(w) if w == windows[2]));
+
+        let next = tokio::time::timeout(Duration::from_millis(200), display.next_focus_event());
+        assert!(next.await.is_err());
+
+        // Without the quiet time, every change is reported.
+        display.set_focus_quiet_time(Duration::ZERO);
+
+        for &window in &windows[..2] {
+            set_active_window(&conn, root, net_active_window, window);
+
+            let event = display.next_focus_event().await.unwrap();
+            assert!(matches!(event, FocusEvent::Focused(w) if w == window));
+        }
+
+        display.stop();
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs Xvfb"]
+async fn request_from_another_thread() {
+    let _xvfb = Xvfb::start(DISPLAY_THREAD);
+
+    let display = DisplayThread::spawn(Some(DISPLAY_THREAD.to_string())).unwrap();
+
+    // The task can be moved between the threads of the runtime, so the
+    // handle and the result must be `Send`.
+    let task = tokio::spawn(async move {
+        display
+            .run(|display| async move {
+                let root = display.roots()[0];
+                let tree = display.send_request(&x::QueryTree { window: root }).await?;
+                Ok::<_, Error>((display.roots().len(), tree.root()))
+            })
+            .await
+    });
+
+    let (screens, root) = task.await.unwrap().unwrap().unwrap();
+
+    assert_eq!(screens, 1);
+    assert!(!root.is_none());
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn typed_properties() {
+    let _xvfb = Xvfb::start(DISPLAY_PROPERTY);
+
+    let (conn, _) = xcb::Connection::connect(Some(DISPLAY_PROPERTY)).unwrap();
+    let prop = atom(&conn, "_TEST_PROPERTY");
+
+    common::run(async {
+        let display = DisplayServer::connect(Some(DISPLAY_PROPERTY)).unwrap();
+        let root = display.roots()[0];
+
+        let main_loop = display.clone();
+        tokio::task::spawn_local(async move { main_loop.main_loop().await });
+
+        assert_eq!(
+            property::get_utf8(&display, root, prop).await.unwrap(),
+            None
+        );
+
+        property::set_utf8(&display, root, prop, "añb")
+            .await
+            .unwrap();
+        assert_eq!(
+            property::get_utf8(&display, root, prop)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("añb"),
+        );
+
+        // Wrong type.
+        assert!(property::get_cardinals(&display, root, prop).await.is_err());
+
+        property::set_windows(&display, root, prop, &[root])
+            .await
+            .unwrap();
+        assert_eq!(
+            property::get_windows(&display, root, prop).await.unwrap(),
+            Some(vec![root]),
+        );
+
+        let none = x::Window::none();
+        property::prepend_windows(&display, root, prop, &[none])
+            .await
+            .unwrap();
+        property::append_windows(&display, root, prop, &[none])
+            .await
+            .unwrap();
+        assert_eq!(
+            property::get_windows(&display, root, prop).await.unwrap(),
+            Some(vec![none, root, none]),
+        );
+
+        property::delete(&display, root, prop).await.unwrap();
+        assert_eq!(
+            property::get_windows(&display, root, prop).await.unwrap(),
+            None
+        );
+
+        display.stop();
+    });
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn commands_in_root_property() {
+    let _xvfb = Xvfb::start(DISPLAY_COMMAND_PROPERTY);
+
+    let (conn, _) = xcb::Connection::connect(Some(DISPLAY_COMMAND_PROPERTY)).unwrap();
+    let prop = atom(&conn, "_X11_ALTERNATE_FOCUS_COMMAND");
+
+    common::run(async {
+        let display = DisplayServer::connect(Some(DISPLAY_COMMAND_PROPERTY)).unwrap();
+        let root = display.roots()[0];
+        let mut commands = display.take_commands().unwrap();
+
+        let main_loop = display.clone();
+        tokio::task::spawn_local(async move { main_loop.main_loop().await });
+
+        display
+            .listen_root_events(Tracking::ActiveWindow)
+            .await
+            .unwrap();
+
+        // The value is longer than a single read of the property.
+        let text = format!("switch 2\n{}mark mail\n goto mail \n", "\n".repeat(10_000));
+
+        conn.send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Append,
+            window: root,
+            property: prop,
+            r#type: x::ATOM_STRING,
+            data: text.as_bytes(),
+        })
+        .unwrap();
+
+        let mut lines = Vec::new();
+        while lines.len() < 3 {
+            let command = tokio::time::timeout(Duration::from_secs(1), commands.recv())
+                .await
+                .unwrap()
+                .unwrap();
+
+            if let ServerCommand::Text(line) = command {
+                lines.push(line);
+            }
+        }
+
+        assert_eq!(lines, ["switch 2", "mark mail", "goto mail"]);
+
+        // The property is deleted after it is read.
+        assert_eq!(
+            property::get_string(&display, root, prop).await.unwrap(),
+            None
+        );
+
+        display.stop();
+    });
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn run_server_command() {
+    let _xvfb = Xvfb::start(DISPLAY_COMMAND);
+
+    let dir = std::env::temp_dir().join(format!("x11-alternate-focus-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let pidfile = dir.join("server.pid");
+    let mut server = program(&dir)
+        .arg("server")
+        .arg("--pidfile")
+        .arg(&pidfile)
+        .spawn()
+        .unwrap();
+
+    let ready = (0..50).any(|_| {
+        thread::sleep(Duration::from_millis(100));
+        program(&dir).arg("ping").status().unwrap().success()
+    });
+
+    assert!(ready, "the server is not running");
+
+    // A second server fails, and keeps the PID file of the first one.
+    let second = program(&dir)
+        .arg("server")
+        .arg("--pidfile")
+        .arg(&pidfile)
+        .status()
+        .unwrap();
+
+    assert!(!second.success());
+    assert_eq!(
+        std::fs::read_to_string(&pidfile).unwrap(),
+        format!("{}\n", server.id()),
+    );
+
+    assert!(program(&dir).arg("quit").status().unwrap().success());
+    assert!(server.wait().unwrap().success());
+    assert!(!pidfile.exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}